@@ -0,0 +1,251 @@
+///! This module provides a buffered access log written by a dedicated thread.
+///!
+///! Request handling only sends lines through a channel, so it never blocks on disk I/O.
+
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use http::Status;
+
+/// Default interval after which buffered lines are flushed.
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// Default number of buffered lines after which they are flushed.
+pub const DEFAULT_BUFFERED_LINES: usize = 64;
+
+enum Message {
+    Line(String),
+    Terminate,
+}
+
+/// Owns the access log thread.
+///
+/// Dropping it flushes all buffered lines and waits until the thread has finished.
+pub struct AccessLog {
+    sender: mpsc::Sender<Message>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+/// Handle to send lines to the access log from any thread.
+#[derive(Clone)]
+pub struct AccessLogger {
+    sender: mpsc::Sender<Message>,
+}
+
+impl AccessLog {
+    /// Starts the access log thread writing to the given target.
+    ///
+    /// Buffered lines are flushed after `flush_interval` or when `buffered_lines` lines are buffered.
+    pub fn start<W>(target: W, flush_interval: Duration, buffered_lines: usize) -> AccessLog
+        where
+            W: Write + Send + 'static
+    {
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            write_lines(receiver, BufWriter::new(target), flush_interval, buffered_lines);
+        });
+
+        AccessLog { sender, thread: Some(thread) }
+    }
+
+    /// Creates a new handle to send lines to this access log.
+    pub fn logger(&self) -> AccessLogger {
+        AccessLogger { sender: self.sender.clone() }
+    }
+}
+
+impl Drop for AccessLog {
+    fn drop(&mut self) {
+        debug!("Shutting down access log.");
+
+        if self.sender.send(Message::Terminate).is_err() {
+            debug!("Access log thread already terminated.");
+        }
+
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
+
+impl AccessLogger {
+    /// Sends a line to the access log. The line must not contain the trailing newline.
+    pub fn log(&self, line: String) {
+        if self.sender.send(Message::Line(line)).is_err() {
+            debug!("Access log thread already terminated. Dropping line.");
+        }
+    }
+}
+
+fn write_lines<W: Write>(receiver: mpsc::Receiver<Message>, mut target: BufWriter<W>, flush_interval: Duration, buffered_lines: usize) {
+    let mut pending = 0;
+
+    loop {
+        match receiver.recv_timeout(flush_interval) {
+            Ok(Message::Line(line)) => {
+                if let Err(err) = writeln!(target, "{}", line) {
+                    error!("Can't write to access log: {}", err);
+                }
+
+                pending += 1;
+
+                if pending >= buffered_lines {
+                    flush(&mut target);
+                    pending = 0;
+                }
+            },
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                if pending > 0 {
+                    flush(&mut target);
+                    pending = 0;
+                }
+            },
+            Ok(Message::Terminate) | Err(mpsc::RecvTimeoutError::Disconnected) => {
+                flush(&mut target);
+                break;
+            },
+        }
+    }
+}
+
+/// Decides whether to log a request answered with the given status.
+///
+/// Failed requests (`4xx` and `5xx`) are always logged, others with a probability of `sample_rate`.
+pub fn is_sampled(status: &Status, sample_rate: f64) -> bool {
+    status.code() >= 400 || random_fraction() < sample_rate
+}
+
+/// Replaces the values of the named header fields of a raw request by `***`.
+///
+/// Names are compared ignoring case. The request line and all other lines are kept as they are.
+pub fn redact_headers(raw_request: &str, redacted: &[String]) -> String {
+    raw_request.split("\r\n")
+        .enumerate()
+        .map(|(index, line)| match line.split_once(':') {
+            Some((name, _)) if index > 0 && redacted.iter().any(|redacted| redacted.eq_ignore_ascii_case(name.trim())) => {
+                format!("{}: ***", name)
+            },
+            _ => line.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("\r\n")
+}
+
+thread_local! {
+    static RANDOM_STATE: Cell<u64> = Cell::new(RandomState::new().build_hasher().finish() | 1);
+}
+
+/// Returns a pseudo random number in `[0, 1)` from a per thread xorshift generator.
+///
+/// It is cheap, but not suitable for anything but sampling.
+fn random_fraction() -> f64 {
+    RANDOM_STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    })
+}
+
+fn flush<W: Write>(target: &mut BufWriter<W>) {
+    if let Err(err) = target.flush() {
+        error!("Can't flush access log: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn is_sampled_always_logs_failed_requests() {
+        for _ in 0..100 {
+            assert_that!(is_sampled(&Status::Ok, 0.0), is(false));
+            assert_that!(is_sampled(&Status::Ok, 1.0), is(true));
+            assert_that!(is_sampled(&Status::NotFound, 0.0), is(true));
+            assert_that!(is_sampled(&Status::InternalServerError, 0.0), is(true));
+        }
+    }
+
+    #[test]
+    fn redact_headers_hides_values_of_named_headers() {
+        let redacted = vec!(String::from("Authorization"), String::from("Cookie"));
+        let raw_request = "GET /Authorization: HTTP/1.1\r\nHost: localhost\r\nauthorization: Basic dXNlcjpzZWNyZXQ=\r\n\
+                           Cookie: session=secret\r\nAccept: */*";
+
+        assert_that!(
+            redact_headers(raw_request, &redacted),
+            is(equal_to(String::from(
+                "GET /Authorization: HTTP/1.1\r\nHost: localhost\r\nauthorization: ***\r\nCookie: ***\r\nAccept: */*"))));
+    }
+
+    #[test]
+    fn random_fraction_is_below_one() {
+        let samples: Vec<f64> = (0..1000).map(|_| random_fraction()).collect();
+
+        assert_that!(samples.iter().all(|&sample| (0.0..1.0).contains(&sample)), is(true));
+        assert_that!(samples.iter().filter(|&&sample| sample < 0.5).count() > 300, is(true));
+    }
+
+    #[test]
+    fn all_lines_are_written_after_shutdown() {
+        let buffer = SharedBuffer(Arc::new(Mutex::new(Vec::new())));
+        let sut = AccessLog::start(buffer.clone(), Duration::from_secs(3600), 1000);
+        let logger = sut.logger();
+
+        for i in 0..100 {
+            logger.log(format!("line {}", i));
+        }
+
+        drop(sut);
+
+        let written = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let expected: Vec<String> = (0..100).map(|i| format!("line {}", i)).collect();
+        assert_that!(written.lines().map(String::from).collect::<Vec<String>>(), is(equal_to(expected)));
+    }
+
+    #[test]
+    fn lines_are_flushed_when_buffer_is_full() {
+        let buffer = SharedBuffer(Arc::new(Mutex::new(Vec::new())));
+        let sut = AccessLog::start(buffer.clone(), Duration::from_secs(3600), 2);
+        let logger = sut.logger();
+
+        logger.log(String::from("first"));
+        logger.log(String::from("second"));
+
+        for _ in 0..100 {
+            if !buffer.0.lock().unwrap().is_empty() {
+                break;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_that!(
+            String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap(),
+            is(equal_to(String::from("first\nsecond\n"))));
+        drop(sut);
+    }
+}
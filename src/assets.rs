@@ -0,0 +1,364 @@
+///! This module provides the sources static assets are served from.
+///!
+///! By default assets are read from the web root directory and the mounts. For single binary
+///! deployments they may instead be embedded into the executable and served from memory.
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use Config;
+use file;
+use http;
+
+/// Source to look up static assets by URL path.
+///
+/// The source is cheap to clone. Clones of an embedded source share the same assets.
+#[derive(Clone)]
+pub enum AssetSource {
+    /// Reads assets from the web root directory and the mounts of the configuration.
+    FileSystem,
+    /// Serves assets from memory, keyed by their URL path, e.g. `/css/main.css`.
+    Embedded(Arc<HashMap<String, Vec<u8>>>),
+}
+
+/// A static asset found for a requested URL path.
+#[derive(Debug, PartialEq)]
+pub enum Asset<'a> {
+    /// A file on disk.
+    File(PathBuf),
+    /// An asset embedded into the executable, given by its URL path and content.
+    Embedded(&'a str, &'a [u8]),
+}
+
+impl AssetSource {
+    /// Creates a source serving the given assets from memory.
+    pub fn embedded(assets: HashMap<String, Vec<u8>>) -> AssetSource {
+        AssetSource::Embedded(Arc::new(assets))
+    }
+
+    /// Looks up the asset for the given URL path.
+    ///
+    /// For a directory its index file is returned. The path is percent-decoded first.
+    pub fn find(&self, config: &Config, url: &str) -> Option<Asset<'_>> {
+        let url = http::decode_path(url)?;
+
+        match *self {
+            AssetSource::FileSystem => find_file(config, &url).map(Asset::File),
+            AssetSource::Embedded(ref assets) => find_embedded(config, assets, &url),
+        }
+    }
+
+    /// Whether the URL path names a directory, with or without trailing slash.
+    pub fn is_directory(&self, config: &Config, url: &str) -> bool {
+        let url = match http::decode_path(url) {
+            Some(url) => url,
+            None => return false,
+        };
+
+        match *self {
+            AssetSource::FileSystem => resolve_path(config, &url).is_some_and(|path| path.is_dir()),
+            AssetSource::Embedded(ref assets) => {
+                let prefix = format!("{}/", url.trim_end_matches('/'));
+                assets.keys().any(|key| key.starts_with(&prefix))
+            },
+        }
+    }
+
+    /// Whether the URL path names a file, ignoring a trailing slash.
+    pub fn is_file(&self, config: &Config, url: &str) -> bool {
+        let url = match http::decode_path(url) {
+            Some(url) => url,
+            None => return false,
+        };
+        let url = url.trim_end_matches('/');
+
+        match *self {
+            AssetSource::FileSystem => resolve_path(config, url).is_some_and(|path| path.is_file()),
+            AssetSource::Embedded(ref assets) => assets.contains_key(url),
+        }
+    }
+
+    /// Whether there are no assets at all.
+    pub fn is_empty(&self, config: &Config) -> bool {
+        match *self {
+            AssetSource::FileSystem => match fs::read_dir(config.web_dir()) {
+                Ok(mut entries) => entries.next().is_none(),
+                Err(_) => false,
+            },
+            AssetSource::Embedded(ref assets) => assets.is_empty(),
+        }
+    }
+}
+
+impl fmt::Debug for AssetSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AssetSource::FileSystem => write!(f, "FileSystem"),
+            AssetSource::Embedded(ref assets) => write!(f, "Embedded({} assets)", assets.len()),
+        }
+    }
+}
+
+impl<'a> Asset<'a> {
+    /// Get the path of the asset, used to determine its content type.
+    pub fn path(&self) -> PathBuf {
+        match *self {
+            Asset::File(ref path) => path.clone(),
+            Asset::Embedded(url, _) => PathBuf::from(url),
+        }
+    }
+
+    /// Reads the content of the asset.
+    ///
+    /// Only files may fail, e.g. if they were removed since they were found or may not be read.
+    pub fn read(&self) -> io::Result<Vec<u8>> {
+        match *self {
+            Asset::File(ref path) => file::read_bytes(path),
+            Asset::Embedded(_, content) => Ok(content.to_vec()),
+        }
+    }
+}
+
+/// Computes a strong entity tag from the length and a hash of the content.
+pub fn content_etag(content: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    format!("\"{:x}-{:x}\"", content.len(), hasher.finish())
+}
+
+fn find_embedded<'a>(config: &Config, assets: &'a HashMap<String, Vec<u8>>, url: &str) -> Option<Asset<'a>> {
+    if !url.ends_with('/') {
+        return assets.get_key_value(url)
+            .map(|(url, content)| Asset::Embedded(url, content));
+    }
+
+    for index_file in config.index_files() {
+        let wanted = format!("{}{}", url, index_file);
+        debug!("Wanted embedded asset is a directory. Looking for {:?}", wanted);
+
+        if let Some((url, content)) = assets.get_key_value(&wanted) {
+            return Some(Asset::Embedded(url, content));
+        }
+    }
+
+    debug!("Not found embedded asset {:?}", url);
+    None
+}
+
+fn find_file(config: &Config, url: &str) -> Option<PathBuf> {
+    let wanted_resource = resolve_path(config, url)?;
+    debug!("Wanted resource is {:?}", wanted_resource);
+
+    if wanted_resource.is_dir() {
+        let (root, _) = resolve_root(config, url);
+        return handle_directory_resource(config, root, wanted_resource);
+    }
+
+    if wanted_resource.exists() {
+        Some(wanted_resource)
+    } else {
+        debug!("Not found {:?}", wanted_resource);
+        None
+    }
+}
+
+/// Maps the decoded URL path to a path below the directory it is served from.
+///
+/// Returns nothing if the path would be outside of that directory. The path must be decoded
+/// before, otherwise encoded dot segments like `%2e%2e` would slip past the check.
+fn resolve_path(config: &Config, url: &str) -> Option<PathBuf> {
+    let (root, url) = resolve_root(config, url);
+
+    if escapes_root(url) {
+        debug!("Refusing to serve {:?} outside of {:?}", url, root);
+        return None;
+    }
+
+    let path = create_resource_path(root, url);
+
+    if !stays_below_root(config, root, &path) {
+        debug!("Refusing to follow symlinks of {:?} outside of {:?}", path, root);
+        return None;
+    }
+
+    Some(path)
+}
+
+/// Finds the directory to serve the URL from and the URL relative to it.
+///
+/// The mounts are consulted in order before the web root directory.
+fn resolve_root<'a>(config: &'a Config, url: &'a str) -> (&'a str, &'a str) {
+    for mount in config.mounts() {
+        if let Some(rest) = mount.strip_prefix(url) {
+            debug!("URL {:?} is mounted from {:?}", url, mount.root());
+            return (mount.root(), rest);
+        }
+    }
+
+    (config.web_dir(), url)
+}
+
+/// Whether the URL would resolve to a path outside of the directory it is served from.
+fn escapes_root(url: &str) -> bool {
+    Path::new(url).components().any(|component| component == Component::ParentDir)
+}
+
+/// Whether the path is still below the root once all symlinks are followed, or symlinks may
+/// lead anywhere.
+///
+/// A path which doesn't exist can't lead anywhere, it is not found anyway.
+fn stays_below_root(config: &Config, root: &str, path: &Path) -> bool {
+    if *config.follow_symlinks() {
+        return true;
+    }
+
+    match (fs::canonicalize(root), fs::canonicalize(path)) {
+        (Ok(root), Ok(path)) => path.starts_with(root),
+        _ => true,
+    }
+}
+
+fn handle_directory_resource(config: &Config, root: &str, wanted_resource: PathBuf) -> Option<PathBuf> {
+    for index_file in config.index_files() {
+        let wanted_resource_file = wanted_resource.join(index_file);
+        debug!("Wanted resource is a directory. Looking for {:?}", wanted_resource_file);
+
+        if wanted_resource_file.exists() && stays_below_root(config, root, &wanted_resource_file) {
+            return Some(wanted_resource_file);
+        }
+    }
+
+    debug!("Nothing appropriate found!");
+    None
+}
+
+fn create_resource_path(web_root: &str, resource_url: &str) -> PathBuf {
+    let relative_resource_url = relativize_uri(resource_url);
+    Path::new(web_root).join(relative_resource_url)
+}
+
+fn relativize_uri(resource_url: &str) -> String {
+    if resource_url.starts_with("/") {
+        resource_url[1..].to_string()
+    } else {
+        resource_url.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+
+    fn test_config() -> Config {
+        Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("test/web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+    }
+
+    #[test]
+    fn find_embedded_asset_and_directory_index() {
+        let mut assets = HashMap::new();
+        assets.insert(String::from("/index.html"), b"<h1>Home</h1>".to_vec());
+        assets.insert(String::from("/css/main.css"), b"body {}".to_vec());
+        let sut = AssetSource::embedded(assets);
+
+        assert_that!(
+            sut.find(&test_config(), "/css/main.css"),
+            is(equal_to(Some(Asset::Embedded("/css/main.css", b"body {}")))));
+        assert_that!(
+            sut.find(&test_config(), "/"),
+            is(equal_to(Some(Asset::Embedded("/index.html", b"<h1>Home</h1>")))));
+        assert_that!(sut.find(&test_config(), "/hello.html"), is(equal_to(None)));
+        assert_that!(sut.is_empty(&test_config()), is(false));
+    }
+
+    #[test]
+    fn find_first_existing_index_file_of_directory() {
+        let sut = AssetSource::FileSystem;
+
+        assert_that!(
+            sut.find(&test_config(), "/legacy/"),
+            is(equal_to(Some(Asset::File(PathBuf::from("test/web_dir/legacy/index.htm"))))));
+
+        let config = test_config()
+            .with_index_files(vec!(String::from("default.html"), String::from("index.htm"), String::from("index.html")))
+            .unwrap();
+        assert_that!(
+            sut.find(&config, "/legacy/"),
+            is(equal_to(Some(Asset::File(PathBuf::from("test/web_dir/legacy/index.htm"))))));
+        assert_that!(sut.find(&config, "/").is_some(), is(true));
+
+        let config = test_config().with_index_files(vec!(String::from("index.html"))).unwrap();
+        assert_that!(sut.find(&config, "/legacy/"), is(equal_to(None)));
+    }
+
+    #[test]
+    fn find_decodes_path_before_checking_for_traversal() {
+        let sut = AssetSource::FileSystem;
+
+        assert_that!(
+            sut.find(&test_config(), "/hello%2Ehtml"),
+            is(equal_to(Some(Asset::File(PathBuf::from("test/web_dir/hello.html"))))));
+
+        for url in ["/%2e%2e/%2e%2e/etc/passwd", "/css/%2E%2E/%2E%2E/%2E%2E/etc/passwd", "/..%2f..%2fetc/passwd",
+                    "/.%2e/test/web_dir/hello.html", "/%2e%2e%5c%2e%2e%5cetc/passwd"].iter() {
+            assert_that!(sut.find(&test_config(), url), is(equal_to(None)));
+            assert_that!(sut.is_file(&test_config(), url), is(false));
+            assert_that!(sut.is_directory(&test_config(), url), is(false));
+        }
+    }
+
+    #[test]
+    fn tell_directories_from_files() {
+        let mut assets = HashMap::new();
+        assets.insert(String::from("/css/main.css"), b"body {}".to_vec());
+        let embedded = AssetSource::embedded(assets);
+
+        for sut in &[AssetSource::FileSystem, embedded] {
+            assert_that!(sut.is_directory(&test_config(), "/css"), is(true));
+            assert_that!(sut.is_directory(&test_config(), "/css/"), is(true));
+            assert_that!(sut.is_directory(&test_config(), "/css/main.css"), is(false));
+            assert_that!(sut.is_file(&test_config(), "/css/main.css"), is(true));
+            assert_that!(sut.is_file(&test_config(), "/css/main.css/"), is(true));
+            assert_that!(sut.is_file(&test_config(), "/css"), is(false));
+        }
+    }
+
+    #[test]
+    fn content_etag_depends_on_content() {
+        assert_that!(content_etag(b"foo"), is(equal_to(content_etag(b"foo"))));
+        assert_that!(content_etag(b"foo") == content_etag(b"bar"), is(false));
+        assert_that!(content_etag(b"foo").starts_with("\"3-"), is(true));
+    }
+
+    #[test]
+    fn test_relativize_uri() {
+        assert_that!(relativize_uri( & String::from("foo/bar/bax.html")),
+            is(equal_to(String::from("foo/bar/bax.html"))));
+        assert_that!(relativize_uri( & String::from("/foo/bar/bax.html")),
+            is(equal_to(String::from("foo/bar/bax.html"))));
+    }
+
+    #[test]
+    fn test_create_resource_path() {
+        assert_that!(
+            create_resource_path( & String::from("web_root/"), & String::from("/")),
+            is(equal_to(PathBuf::from("web_root/"))));
+        assert_that!(
+            create_resource_path( & String::from("web_root/"), &String::from("/index.html")),
+            is(equal_to(PathBuf::from("web_root/index.html"))));
+        assert_that!(
+            create_resource_path( & String::from("web_root/"), & String::from("/css/main.css")),
+            is(equal_to(PathBuf::from("web_root/css/main.css"))));
+    }
+}
@@ -0,0 +1,185 @@
+///! This module provides decoding of request bodies sent with `Transfer-Encoding: chunked`.
+///!
+///! It implements the chunked transfer coding of [RFC 7230, section 4.1](https://tools.ietf.org/html/rfc7230#section-4.1).
+///! The body is decoded while it is read, so a body exceeding the maximum size is rejected before
+///! it is read completely.
+
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+
+/// Maximum length of a chunk size line or a trailer line in bytes.
+const MAX_LINE_BYTES: usize = 4096;
+
+/// Error returned when a chunked body can't be read.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChunkedError {
+    /// The body does not follow the chunked transfer coding.
+    Malformed(&'static str),
+    /// The decoded body would exceed the given maximum size.
+    TooLarge,
+    /// Reading from the stream failed.
+    Io(io::ErrorKind),
+}
+
+impl fmt::Display for ChunkedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChunkedError::Malformed(reason) => write!(f, "Malformed chunked body: {}", reason),
+            ChunkedError::TooLarge => write!(f, "Chunked body exceeds maximum size!"),
+            ChunkedError::Io(kind) => write!(f, "Can't read chunked body: {:?}", kind),
+        }
+    }
+}
+
+/// Reads and decodes a chunked body, taking the bytes already read after the headers first.
+///
+/// Reading stops with [`ChunkedError::TooLarge`](enum.ChunkedError.html) as soon as a chunk
+/// would make the body exceed `max_bytes`, before the data of that chunk is read. Trailer fields
+/// are skipped. Bytes read beyond the body belong to the next request and are kept in `pending`.
+pub fn read_body<R: Read>(stream: &mut R, pending: &mut Vec<u8>, max_bytes: usize) -> Result<Vec<u8>, ChunkedError> {
+    let mut input = Input { stream, buffer: mem::take(pending), position: 0 };
+    let mut body = Vec::new();
+
+    loop {
+        let size = parse_chunk_size(&input.read_line()?)?;
+
+        if size == 0 {
+            break;
+        }
+
+        if size > max_bytes - body.len() {
+            return Err(ChunkedError::TooLarge);
+        }
+
+        input.read_exact_into(size, &mut body)?;
+
+        if !input.read_line()?.is_empty() {
+            return Err(ChunkedError::Malformed("Chunk data is longer than its size"));
+        }
+    }
+
+    while !input.read_line()?.is_empty() {}
+
+    *pending = input.into_rest();
+    Ok(body)
+}
+
+/// Parses the hexadecimal size of a chunk, ignoring chunk extensions.
+fn parse_chunk_size(line: &[u8]) -> Result<usize, ChunkedError> {
+    let size = line.split(|&byte| byte == b';').next().unwrap_or(line);
+    let size = String::from_utf8_lossy(size);
+    let size = size.trim_matches(|c| c == ' ' || c == '\t');
+
+    if size.is_empty() || !size.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ChunkedError::Malformed("Invalid chunk size"));
+    }
+
+    // All digits are valid, so the size only fails to parse if it doesn't fit.
+    usize::from_str_radix(size, 16).map_err(|_| ChunkedError::TooLarge)
+}
+
+/// Bytes read from the stream which are not decoded yet.
+struct Input<'r, R: 'r> {
+    stream: &'r mut R,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl<'r, R: Read> Input<'r, R> {
+    /// Reads a line terminated by CRLF and returns it without the terminator.
+    fn read_line(&mut self) -> Result<Vec<u8>, ChunkedError> {
+        loop {
+            if let Some(end) = self.buffer[self.position..].windows(2).position(|window| window == b"\r\n") {
+                let line = self.buffer[self.position..self.position + end].to_vec();
+                self.position += end + 2;
+                return Ok(line);
+            }
+
+            if self.buffer.len() - self.position > MAX_LINE_BYTES {
+                return Err(ChunkedError::Malformed("Line exceeds maximum length"));
+            }
+
+            self.fill()?;
+        }
+    }
+
+    /// Reads exactly `length` bytes and appends them to the output.
+    fn read_exact_into(&mut self, length: usize, output: &mut Vec<u8>) -> Result<(), ChunkedError> {
+        while self.buffer.len() - self.position < length {
+            self.fill()?;
+        }
+
+        output.extend_from_slice(&self.buffer[self.position..self.position + length]);
+        self.position += length;
+        Ok(())
+    }
+
+    /// Drops the decoded bytes and reads more from the stream.
+    fn fill(&mut self) -> Result<(), ChunkedError> {
+        self.buffer.drain(..self.position);
+        self.position = 0;
+        let mut chunk = [0; 4096];
+
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(ChunkedError::Malformed("Unexpected end of body")),
+                Ok(read) => {
+                    self.buffer.extend_from_slice(&chunk[..read]);
+                    return Ok(());
+                },
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(ChunkedError::Io(err.kind())),
+            }
+        }
+    }
+
+    /// Returns the bytes read beyond the body.
+    fn into_rest(mut self) -> Vec<u8> {
+        self.buffer.split_off(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+
+    fn decode(input: &[u8], max_bytes: usize) -> Result<Vec<u8>, ChunkedError> {
+        read_body(&mut io::Cursor::new(input.to_vec()), &mut Vec::new(), max_bytes)
+    }
+
+    #[test]
+    fn read_body_decodes_chunks_and_skips_extensions_and_trailers() {
+        assert_that!(
+            decode(b"5\r\nHello\r\n8;name=value\r\n, World!\r\n0\r\nExpires: never\r\n\r\n", 4096),
+            is(equal_to(Ok(b"Hello, World!".to_vec()))));
+        assert_that!(decode(b"0\r\n\r\n", 4096), is(equal_to(Ok(Vec::new()))));
+    }
+
+    #[test]
+    fn read_body_takes_pending_bytes_first_and_keeps_the_rest() {
+        let mut pending = b"5\r\nHel".to_vec();
+        let mut stream = io::Cursor::new(b"lo\r\n0\r\n\r\nGET / HTTP/1.1\r\n".to_vec());
+
+        assert_that!(read_body(&mut stream, &mut pending, 4096), is(equal_to(Ok(b"Hello".to_vec()))));
+        assert_that!(pending, is(equal_to(b"GET / HTTP/1.1\r\n".to_vec())));
+    }
+
+    #[test]
+    fn read_body_stops_at_maximum_size_before_reading_the_chunk() {
+        // The stream ends after the size line of the third chunk, which must not be read.
+        assert_that!(decode(b"4\r\naaaa\r\n4\r\nbbbb\r\n4\r\n", 10), is(equal_to(Err(ChunkedError::TooLarge))));
+        assert_that!(decode(b"ffffffffffffffffffff\r\n", 10), is(equal_to(Err(ChunkedError::TooLarge))));
+    }
+
+    #[test]
+    fn read_body_rejects_malformed_chunks() {
+        assert_that!(decode(b"x\r\n", 4096), is(equal_to(Err(ChunkedError::Malformed("Invalid chunk size")))));
+        assert_that!(
+            decode(b"2\r\nabc\r\n0\r\n\r\n", 4096),
+            is(equal_to(Err(ChunkedError::Malformed("Chunk data is longer than its size")))));
+        assert_that!(decode(b"5\r\nab", 4096), is(equal_to(Err(ChunkedError::Malformed("Unexpected end of body")))));
+    }
+}
@@ -0,0 +1,152 @@
+///! This module provides limits for concurrent client connections.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+/// Tracks the open connections in total and per client IP.
+///
+/// The limiter is cheap to clone. All clones share the same connection counts.
+#[derive(Debug, Clone)]
+pub struct ConnectionLimiter {
+    /// Maximum number of concurrent connections, unlimited if `None`.
+    max_connections: Option<usize>,
+    /// Maximum number of concurrent connections per client IP, unlimited if `None`.
+    max_connections_per_ip: Option<usize>,
+    /// Counts shared across all threads.
+    counts: Arc<Mutex<ConnectionCounts>>,
+}
+
+#[derive(Debug, Default)]
+struct ConnectionCounts {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+impl ConnectionLimiter {
+    /// Creates a new limiter without any open connections.
+    pub fn new(max_connections: Option<usize>, max_connections_per_ip: Option<usize>) -> ConnectionLimiter {
+        ConnectionLimiter {
+            max_connections,
+            max_connections_per_ip,
+            counts: Arc::new(Mutex::new(ConnectionCounts::default())),
+        }
+    }
+
+    /// Tries to register a new connection from the given client IP.
+    ///
+    /// Returns `None` if any of the limits is exceeded. Otherwise it returns a guard which
+    /// releases the connection when it is dropped.
+    pub fn acquire(&self, ip: IpAddr) -> Option<ConnectionGuard> {
+        let mut counts = self.counts.lock().unwrap();
+
+        if let Some(max) = self.max_connections {
+            if counts.total >= max {
+                debug!("Rejecting connection from {}: {} connections open.", ip, counts.total);
+                return None;
+            }
+        }
+
+        let open_by_ip = counts.per_ip.get(&ip).cloned().unwrap_or(0);
+
+        if let Some(max) = self.max_connections_per_ip {
+            if open_by_ip >= max {
+                debug!("Rejecting connection from {}: {} connections open by this IP.", ip, open_by_ip);
+                return None;
+            }
+        }
+
+        counts.total += 1;
+        counts.per_ip.insert(ip, open_by_ip + 1);
+
+        Some(ConnectionGuard { ip, counts: Arc::clone(&self.counts) })
+    }
+
+    /// Get the number of currently open connections.
+    pub fn open_connections(&self) -> usize {
+        self.counts.lock().unwrap().total
+    }
+
+    /// Get the number of currently open connections from the given client IP.
+    pub fn open_connections_by_ip(&self, ip: &IpAddr) -> usize {
+        self.counts.lock().unwrap().per_ip.get(ip).cloned().unwrap_or(0)
+    }
+}
+
+/// Represents an open connection registered at a [`ConnectionLimiter`](struct.ConnectionLimiter.html).
+///
+/// The connection is released when the guard is dropped.
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    ip: IpAddr,
+    counts: Arc<Mutex<ConnectionCounts>>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.total -= 1;
+
+        let remove = match counts.per_ip.get_mut(&self.ip) {
+            Some(open) => {
+                *open -= 1;
+                *open == 0
+            },
+            None => false,
+        };
+
+        if remove {
+            counts.per_ip.remove(&self.ip);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn acquire_rejects_more_connections_than_allowed_per_ip() {
+        let sut = ConnectionLimiter::new(None, Some(2));
+
+        let first = sut.acquire(ip("10.0.0.1"));
+        let second = sut.acquire(ip("10.0.0.1"));
+        let third = sut.acquire(ip("10.0.0.1"));
+
+        assert_that!(first.is_some(), is(true));
+        assert_that!(second.is_some(), is(true));
+        assert_that!(third.is_none(), is(true));
+        assert_that!(sut.acquire(ip("10.0.0.2")).is_some(), is(true));
+    }
+
+    #[test]
+    fn acquire_rejects_more_connections_than_allowed_in_total() {
+        let sut = ConnectionLimiter::new(Some(2), Some(2));
+
+        let _first = sut.acquire(ip("10.0.0.1"));
+        let _second = sut.acquire(ip("10.0.0.2"));
+
+        assert_that!(sut.acquire(ip("10.0.0.3")).is_none(), is(true));
+    }
+
+    #[test]
+    fn dropping_guard_releases_connection_and_cleans_up() {
+        let sut = ConnectionLimiter::new(None, Some(1));
+
+        let guard = sut.acquire(ip("10.0.0.1"));
+        assert_that!(sut.open_connections(), is(equal_to(1)));
+        assert_that!(sut.acquire(ip("10.0.0.1")).is_none(), is(true));
+
+        drop(guard);
+
+        assert_that!(sut.open_connections(), is(equal_to(0)));
+        assert_that!(sut.open_connections_by_ip(&ip("10.0.0.1")), is(equal_to(0)));
+        assert_that!(sut.counts.lock().unwrap().per_ip.is_empty(), is(true));
+        assert_that!(sut.acquire(ip("10.0.0.1")).is_some(), is(true));
+    }
+}
@@ -0,0 +1,274 @@
+///! This module provides the context a request is handled in.
+///!
+///! Handlers get everything they need to know about a request from its context: the request
+///! itself, the connection it came in on and the configuration it is served with.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::mem;
+use Config;
+use assets::AssetSource;
+use http::{self, Request, Response, ResponseHeader, Status};
+
+/// Creates the response to a request from its context.
+pub type Handler = fn(&mut Context) -> Response;
+
+/// Creates the response to a request from its context, or fails with an error answered by the server.
+///
+/// Errors of I/O can be propagated with `?`, see [`HandlerError`](enum.HandlerError.html).
+pub type FallibleHandler = fn(&mut Context) -> Result<Response, HandlerError>;
+
+/// Error of a [`FallibleHandler`](type.FallibleHandler.html), answered with its status.
+///
+/// The message is only logged. Clients get a generic message, so it may contain details
+/// like file paths.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandlerError {
+    /// The requested resource does not exist, answered with `404 Not Found`.
+    NotFound(String),
+    /// The client may not access the requested resource, answered with `403 Forbidden`.
+    Forbidden(String),
+    /// Anything else, answered with `500 Internal Server Error`.
+    Internal(String),
+}
+
+impl HandlerError {
+    /// Get the status the error is answered with.
+    pub fn status(&self) -> Status {
+        match *self {
+            HandlerError::NotFound(_) => Status::NotFound,
+            HandlerError::Forbidden(_) => Status::Forbidden,
+            HandlerError::Internal(_) => Status::InternalServerError,
+        }
+    }
+
+    /// Creates the response the error is answered with.
+    pub fn to_response(&self) -> Response {
+        let message = match *self {
+            HandlerError::NotFound(_) => "Not found!",
+            HandlerError::Forbidden(_) => "Forbidden!",
+            HandlerError::Internal(_) => "Internal server error!",
+        };
+        let mut response = Response::new(http::VERSION.to_string(), self.status(), message.as_bytes().to_vec());
+        response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+        response
+    }
+}
+
+impl fmt::Display for HandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HandlerError::NotFound(ref message) => write!(f, "Not found: {}", message),
+            HandlerError::Forbidden(ref message) => write!(f, "Forbidden: {}", message),
+            HandlerError::Internal(ref message) => write!(f, "Internal error: {}", message),
+        }
+    }
+}
+
+impl error::Error for HandlerError {}
+
+impl From<io::Error> for HandlerError {
+    /// Takes the status [`Response::from_io_error`](../http/struct.Response.html#method.from_io_error)
+    /// answers the error with, so both answer it the same way.
+    fn from(err: io::Error) -> HandlerError {
+        let message = err.to_string();
+
+        match *Response::from_io_error(&err).status() {
+            Status::NotFound => HandlerError::NotFound(message),
+            Status::Forbidden => HandlerError::Forbidden(message),
+            _ => HandlerError::Internal(message),
+        }
+    }
+}
+
+/// Everything known about a request while it is handled.
+#[derive(Debug)]
+pub struct Context<'a> {
+    config: Config,
+    assets: &'a AssetSource,
+    request: Request,
+    raw_request: &'a str,
+    peer: SocketAddr,
+    params: HashMap<String, String>,
+    headers: Vec<ResponseHeader>,
+    not_found_handler: Option<FallibleHandler>,
+}
+
+impl<'a> Context<'a> {
+    /// Creates the context of a request received from `peer`.
+    ///
+    /// The route params are empty and may be set with [`with_params`](#method.with_params).
+    pub fn new(config: Config, assets: &'a AssetSource, request: Request, raw_request: &'a str, peer: SocketAddr) -> Context<'a> {
+        Context {
+            config,
+            assets,
+            request,
+            raw_request,
+            peer,
+            params: HashMap::new(),
+            headers: Vec::new(),
+            not_found_handler: None,
+        }
+    }
+
+    /// Set the params of the route matched by the request.
+    pub fn with_params(self, params: HashMap<String, String>) -> Context<'a> {
+        Context { params, ..self }
+    }
+
+    /// Set the handler responding instead of the built-in 404 if nothing matches the request.
+    pub fn with_not_found_handler(self, not_found_handler: FallibleHandler) -> Context<'a> {
+        Context { not_found_handler: Some(not_found_handler), ..self }
+    }
+
+    /// Get the configuration the request is served with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Get the static assets the request may be served from.
+    pub fn assets(&self) -> &'a AssetSource {
+        self.assets
+    }
+
+    /// Get the parsed request.
+    pub fn request(&self) -> &Request {
+        &self.request
+    }
+
+    /// Get the request as received, without body.
+    pub fn raw_request(&self) -> &'a str {
+        self.raw_request
+    }
+
+    /// Get the address of the client.
+    pub fn peer(&self) -> &SocketAddr {
+        &self.peer
+    }
+
+    /// Get the params of the route matched by the request, empty if none.
+    pub fn params(&self) -> &HashMap<String, String> {
+        &self.params
+    }
+
+    /// Get the handler responding instead of the built-in 404, if any.
+    pub fn not_found_handler(&self) -> Option<FallibleHandler> {
+        self.not_found_handler
+    }
+
+    /// Adds a header to the response the handler creates.
+    ///
+    /// The headers are added after the ones the handler adds itself.
+    pub fn add_header(&mut self, header: ResponseHeader) {
+        self.headers.push(header);
+    }
+
+    /// Calls the handler and adds the headers collected in the context to its response.
+    pub fn handle(&mut self, handler: Handler) -> Response {
+        let response = handler(self);
+        self.add_collected_headers(response)
+    }
+
+    /// Calls the fallible handler and adds the headers collected in the context to its response.
+    ///
+    /// An error is logged and answered with its status instead.
+    pub fn try_handle(&mut self, handler: FallibleHandler) -> Response {
+        let response = match handler(self) {
+            Ok(response) => response,
+            Err(err) => {
+                warn!("Can't handle request for {}: {}", self.request.url(), err);
+                err.to_response()
+            },
+        };
+        self.add_collected_headers(response)
+    }
+
+    fn add_collected_headers(&mut self, mut response: Response) -> Response {
+        for header in mem::take(&mut self.headers) {
+            response.add_header(header);
+        }
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+
+    fn client_ip(context: &mut Context) -> Response {
+        let ip = context.peer().ip().to_string();
+        context.add_header(ResponseHeader::Custom(String::from("X-Client-Ip"), ip.clone()));
+
+        Response::new(http::VERSION.to_string(), Status::Ok, ip.into_bytes())
+    }
+
+    fn read_missing_file(context: &mut Context) -> Result<Response, HandlerError> {
+        context.add_header(ResponseHeader::Custom(String::from("X-Handled"), String::from("yes")));
+        let content = std::fs::read("test/web_dir/missing.html")?;
+
+        Ok(Response::new(http::VERSION.to_string(), Status::Ok, content))
+    }
+
+    fn test_context<'a>(assets: &'a AssetSource, raw_request: &'a str) -> Context<'a> {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("test/web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap();
+        let request = http::parse_request(raw_request).unwrap();
+
+        Context::new(config, assets, request, raw_request, "192.168.0.7:50123".parse().unwrap())
+    }
+
+    #[test]
+    fn try_handle_answers_io_error_of_handler_with_its_status() {
+        let assets = AssetSource::FileSystem;
+        let mut sut = test_context(&assets, "GET /missing.html HTTP/1.1\r\nHost: localhost:8080\r\n");
+
+        let rendered = sut.try_handle(read_missing_file).render();
+
+        assert_that!(
+            String::from_utf8_lossy(&rendered).into_owned(),
+            is(equal_to(String::from(
+                "HTTP/1.1 404 NOT FOUND\r\nContent-Type: text/plain; charset=utf-8\r\nX-Handled: yes\r\nContent-Length: 10\r\n\r\nNot found!"))));
+    }
+
+    #[test]
+    fn handler_error_maps_io_errors_to_statuses() {
+        let status_of = |kind| HandlerError::from(io::Error::new(kind, "failed")).status().code();
+
+        assert_that!(status_of(io::ErrorKind::NotFound), is(equal_to(404)));
+        assert_that!(status_of(io::ErrorKind::PermissionDenied), is(equal_to(403)));
+        assert_that!(status_of(io::ErrorKind::UnexpectedEof), is(equal_to(500)));
+    }
+
+    #[test]
+    fn handler_error_answers_io_errors_like_response() {
+        for kind in [io::ErrorKind::NotFound, io::ErrorKind::PermissionDenied, io::ErrorKind::UnexpectedEof].iter() {
+            let err = io::Error::new(*kind, "failed");
+            let expected = Response::from_io_error(&err).render();
+
+            assert_that!(HandlerError::from(err).to_response().render(), is(equal_to(expected)));
+        }
+    }
+
+    #[test]
+    fn handler_reads_client_ip_from_context() {
+        let assets = AssetSource::FileSystem;
+        let mut sut = test_context(&assets, "GET / HTTP/1.1\r\nHost: localhost:8080\r\n");
+
+        let rendered = sut.handle(client_ip).render();
+
+        assert_that!(
+            String::from_utf8_lossy(&rendered).into_owned(),
+            is(equal_to(String::from("HTTP/1.1 200 OK\r\nX-Client-Ip: 192.168.0.7\r\nContent-Length: 11\r\n\r\n192.168.0.7"))));
+        assert_that!(sut.params().is_empty(), is(true));
+    }
+}
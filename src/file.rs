@@ -1,33 +1,61 @@
 ///! This module provides various helpers to deal with files.
 
+use std::io;
 use std::io::prelude::*;
 use std::fs::File;
 use std::path::PathBuf;
 
 /// Reads the bytes from a givne file into a byte vector.
-pub fn read_bytes(file_name: &PathBuf) -> Vec<u8> {
+///
+/// Fails if the file does not exist, may not be read or reading it fails.
+pub fn read_bytes(file_name: &PathBuf) -> io::Result<Vec<u8>> {
     debug!("Reading file {:?}.", file_name);
-    let file = File::open(file_name)
-        .expect("Can't open file {}!");
+    let mut file = File::open(file_name)?;
     let mut buffer: Vec<u8> = Vec::new();
-
-    for byte in file.bytes() {
-        buffer.push(byte.expect("Can't read byte from file!"));
-    }
-
-    buffer
+    file.read_to_end(&mut buffer)?;
+    Ok(buffer)
 }
 
 /// Reads the bytes from a givne file into a string.
-pub fn read_string(file_name: &PathBuf) -> String {
+///
+/// Fails like [`read_bytes`](fn.read_bytes.html) and if the file is not valid UTF-8.
+pub fn read_string(file_name: &PathBuf) -> io::Result<String> {
     debug!("Reading file {:?}.", file_name);
-    let mut file = File::open(file_name)
-        .expect("Can't open file {}!");
+    let mut file = File::open(file_name)?;
     let mut buffer = String::new();
-    file.read_to_string(&mut buffer).unwrap_or_else(|err| {
-        panic!("Can't read file: {}", err);
-    });
-    buffer
+    file.read_to_string(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Magic numbers at the start of files and the content type they identify.
+static MAGIC_NUMBERS: &'static [(&'static [u8], &'static str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"\x1f\x8b", "application/gzip"),
+];
+
+/// Detects the content type from the magic number at the start of the content.
+///
+/// Only a few common binary types are detected. Returns `None` for anything else.
+pub fn detect_content_type(content: &[u8]) -> Option<&'static str> {
+    MAGIC_NUMBERS.iter()
+        .find(|&&(magic, _)| content.starts_with(magic))
+        .map(|&(_, content_type)| content_type)
+}
+
+/// Whether the content is consistent with the declared content type.
+///
+/// If the declared type can be detected, the content must start with its magic number. Otherwise the
+/// content must not start with the magic number of any detectable type.
+pub fn matches_content_type(content: &[u8], declared: &str) -> bool {
+    let expected = MAGIC_NUMBERS.iter()
+        .find(|&&(_, content_type)| content_type == declared)
+        .map(|&(_, content_type)| content_type);
+
+    detect_content_type(content) == expected
 }
 
 #[cfg(test)]
@@ -37,7 +65,7 @@ mod tests {
 
     #[test]
     fn test_read_bytes() {
-        let content = read_bytes(&PathBuf::from("test/fixtures/hello.txt"));
+        let content = read_bytes(&PathBuf::from("test/fixtures/hello.txt")).unwrap();
 
         assert_that!(
             content,
@@ -45,13 +73,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_detect_content_type() {
+        assert_that!(detect_content_type(b"\x89PNG\r\n\x1a\n\x00\x00"), is(equal_to(Some("image/png"))));
+        assert_that!(detect_content_type(b"\xff\xd8\xff\xe0"), is(equal_to(Some("image/jpeg"))));
+        assert_that!(detect_content_type(b"GIF89a\x01\x00"), is(equal_to(Some("image/gif"))));
+        assert_that!(detect_content_type(b"%PDF-1.4"), is(equal_to(Some("application/pdf"))));
+        assert_that!(detect_content_type(b"\x1f\x8b\x08"), is(equal_to(Some("application/gzip"))));
+        assert_that!(detect_content_type(b"Hello, World!"), is(equal_to(None)));
+        assert_that!(detect_content_type(b""), is(equal_to(None)));
+    }
+
+    #[test]
+    fn test_matches_content_type() {
+        let text_as_png = read_bytes(&PathBuf::from("test/fixtures/text_as.png")).unwrap();
+
+        assert_that!(matches_content_type(&text_as_png, "image/png"), is(false));
+        assert_that!(matches_content_type(&text_as_png, "text/plain"), is(true));
+        assert_that!(matches_content_type(b"\x89PNG\r\n\x1a\n", "image/png"), is(true));
+        assert_that!(matches_content_type(b"\x89PNG\r\n\x1a\n", "text/html"), is(false));
+    }
+
     #[test]
     fn test_read_string() {
-        let content = read_string(&PathBuf::from("test/fixtures/hello.txt"));
+        let content = read_string(&PathBuf::from("test/fixtures/hello.txt")).unwrap();
 
         assert_that!(
             content,
             is(equal_to(String::from("Hello, World!")))
         );
     }
+
+    #[test]
+    fn test_read_bytes_fails_for_missing_file() {
+        let err = read_bytes(&PathBuf::from("test/fixtures/missing.txt")).unwrap_err();
+
+        assert_that!(err.kind(), is(equal_to(io::ErrorKind::NotFound)));
+    }
 }
\ No newline at end of file
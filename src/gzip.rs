@@ -0,0 +1,463 @@
+///! This module provides decompression of gzip encoded data.
+///!
+///! It implements the gzip file format of [RFC 1952](https://tools.ietf.org/html/rfc1952) and
+///! the inflate algorithm of [RFC 1951](https://tools.ietf.org/html/rfc1951). Only decompression
+///! is supported.
+
+use std::fmt;
+
+/// Order in which the code length code lengths of a dynamic block are stored.
+static CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+/// Base lengths of the length symbols 257 to 285.
+static LENGTH_BASE: [usize; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258];
+/// Extra bits of the length symbols 257 to 285.
+static LENGTH_EXTRA: [u32; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+/// Base distances of the distance symbols 0 to 29.
+static DISTANCE_BASE: [usize; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049,
+    3073, 4097, 6145, 8193, 12289, 16385, 24577];
+/// Extra bits of the distance symbols 0 to 29.
+static DISTANCE_EXTRA: [u32; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// Longest Huffman code in bits.
+const MAX_CODE_LENGTH: usize = 15;
+
+const FLAG_HEADER_CRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+
+/// Error returned when gzip encoded data can't be decompressed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GzipError {
+    /// The data is not valid gzip.
+    Malformed(&'static str),
+    /// The decompressed data would exceed the given maximum size.
+    TooLarge,
+}
+
+impl fmt::Display for GzipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GzipError::Malformed(reason) => write!(f, "Malformed gzip data: {}", reason),
+            GzipError::TooLarge => write!(f, "Decompressed gzip data exceeds maximum size!"),
+        }
+    }
+}
+
+/// Decompresses gzip encoded data.
+///
+/// Decompression stops with [`GzipError::TooLarge`](enum.GzipError.html) as soon as the output
+/// would exceed `max_bytes`, so a small input can't expand into huge amounts of memory.
+/// Concatenated gzip members are decompressed one after another.
+pub fn decompress(input: &[u8], max_bytes: usize) -> Result<Vec<u8>, GzipError> {
+    let mut output = Vec::new();
+    let mut position = 0;
+
+    while position < input.len() {
+        position = decompress_member(input, position, &mut output, max_bytes)?;
+    }
+
+    Ok(output)
+}
+
+/// Decompresses the member starting at `position` and returns the position after it.
+fn decompress_member(input: &[u8], position: usize, output: &mut Vec<u8>, max_bytes: usize) -> Result<usize, GzipError> {
+    let start = output.len();
+    let mut position = skip_header(input, position)?;
+    let mut reader = BitReader::new(&input[position..]);
+    inflate(&mut reader, output, max_bytes)?;
+    position += reader.position;
+
+    if input.len() < position + 8 {
+        return Err(GzipError::Malformed("Missing trailer"));
+    }
+
+    let crc = read_u32(&input[position..]);
+    let size = read_u32(&input[position + 4..]);
+
+    if crc != crc32(&output[start..]) {
+        return Err(GzipError::Malformed("Checksum mismatch"));
+    }
+
+    if size != (output.len() - start) as u32 {
+        return Err(GzipError::Malformed("Size mismatch"));
+    }
+
+    Ok(position + 8)
+}
+
+/// Returns the position after the member header starting at `position`.
+fn skip_header(input: &[u8], position: usize) -> Result<usize, GzipError> {
+    let header = &input[position..];
+
+    if header.len() < 10 || header[0] != 0x1f || header[1] != 0x8b {
+        return Err(GzipError::Malformed("Missing magic number"));
+    }
+
+    if header[2] != 8 {
+        return Err(GzipError::Malformed("Unsupported compression method"));
+    }
+
+    let flags = header[3];
+    let mut offset = 10;
+
+    if flags & FLAG_EXTRA != 0 {
+        if header.len() < offset + 2 {
+            return Err(GzipError::Malformed("Truncated header"));
+        }
+
+        offset += 2 + (usize::from(header[offset]) | usize::from(header[offset + 1]) << 8);
+    }
+
+    for flag in &[FLAG_NAME, FLAG_COMMENT] {
+        if flags & flag != 0 {
+            match header.get(offset..).and_then(|rest| rest.iter().position(|&byte| byte == 0)) {
+                Some(end) => offset += end + 1,
+                None => return Err(GzipError::Malformed("Truncated header")),
+            }
+        }
+    }
+
+    if flags & FLAG_HEADER_CRC != 0 {
+        offset += 2;
+    }
+
+    if header.len() < offset {
+        return Err(GzipError::Malformed("Truncated header"));
+    }
+
+    Ok(position + offset)
+}
+
+fn read_u32(input: &[u8]) -> u32 {
+    u32::from(input[0]) | u32::from(input[1]) << 8 | u32::from(input[2]) << 16 | u32::from(input[3]) << 24
+}
+
+fn crc32(input: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in input {
+        crc ^= u32::from(byte);
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+/// Reads bits least significant first, as inflate stores them.
+struct BitReader<'a> {
+    input: &'a [u8],
+    /// Position of the next byte to load.
+    position: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(input: &'a [u8]) -> BitReader<'a> {
+        BitReader { input, position: 0, buffer: 0, count: 0 }
+    }
+
+    fn bits(&mut self, wanted: u32) -> Result<u32, GzipError> {
+        while self.count < wanted {
+            let byte = match self.input.get(self.position) {
+                Some(&byte) => byte,
+                None => return Err(GzipError::Malformed("Unexpected end of data")),
+            };
+            self.buffer |= u32::from(byte) << self.count;
+            self.position += 1;
+            self.count += 8;
+        }
+
+        let value = self.buffer & ((1 << wanted) - 1);
+        self.buffer >>= wanted;
+        self.count -= wanted;
+        Ok(value)
+    }
+
+    /// Discards the remaining bits of the current byte.
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], GzipError> {
+        let input = self.input;
+
+        match input.get(self.position..self.position + length) {
+            Some(bytes) => {
+                self.position += length;
+                Ok(bytes)
+            },
+            None => Err(GzipError::Malformed("Unexpected end of data")),
+        }
+    }
+}
+
+/// Canonical Huffman code given by the number of codes of each length and the symbols ordered by code.
+struct Huffman {
+    counts: [u16; MAX_CODE_LENGTH + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, GzipError> {
+        let mut counts = [0u16; MAX_CODE_LENGTH + 1];
+
+        for &length in lengths {
+            counts[usize::from(length)] += 1;
+        }
+
+        let mut left: i32 = 1;
+
+        for &count in &counts[1..] {
+            left = (left << 1) - i32::from(count);
+
+            if left < 0 {
+                return Err(GzipError::Malformed("Over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_CODE_LENGTH + 1];
+
+        for length in 1..MAX_CODE_LENGTH {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+
+        let mut symbols = vec![0u16; lengths.len()];
+
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[usize::from(offsets[usize::from(length)])] = symbol as u16;
+                offsets[usize::from(length)] += 1;
+            }
+        }
+
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<usize, GzipError> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+
+        for &count in &self.counts[1..] {
+            code |= reader.bits(1)? as i32;
+            let count = i32::from(count);
+
+            if code - count < first {
+                return Ok(usize::from(self.symbols[(index + code - first) as usize]));
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(GzipError::Malformed("Invalid Huffman code"))
+    }
+}
+
+fn inflate(reader: &mut BitReader, output: &mut Vec<u8>, max_bytes: usize) -> Result<(), GzipError> {
+    loop {
+        let last = reader.bits(1)? == 1;
+
+        match reader.bits(2)? {
+            0 => inflate_stored(reader, output, max_bytes)?,
+            1 => {
+                let (literals, distances) = fixed_codes()?;
+                inflate_codes(reader, output, max_bytes, &literals, &distances)?;
+            },
+            2 => {
+                let (literals, distances) = dynamic_codes(reader)?;
+                inflate_codes(reader, output, max_bytes, &literals, &distances)?;
+            },
+            _ => return Err(GzipError::Malformed("Invalid block type")),
+        }
+
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+fn inflate_stored(reader: &mut BitReader, output: &mut Vec<u8>, max_bytes: usize) -> Result<(), GzipError> {
+    reader.align();
+    let header = reader.bytes(4)?;
+    let length = usize::from(header[0]) | usize::from(header[1]) << 8;
+    let complement = usize::from(header[2]) | usize::from(header[3]) << 8;
+
+    if length != !complement & 0xffff {
+        return Err(GzipError::Malformed("Invalid stored block length"));
+    }
+
+    if output.len() + length > max_bytes {
+        return Err(GzipError::TooLarge);
+    }
+
+    output.extend_from_slice(reader.bytes(length)?);
+    Ok(())
+}
+
+fn inflate_codes(reader: &mut BitReader, output: &mut Vec<u8>, max_bytes: usize, literals: &Huffman, distances: &Huffman) -> Result<(), GzipError> {
+    loop {
+        let symbol = literals.decode(reader)?;
+
+        if symbol < 256 {
+            if output.len() >= max_bytes {
+                return Err(GzipError::TooLarge);
+            }
+
+            output.push(symbol as u8);
+            continue;
+        }
+
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let symbol = symbol - 257;
+
+        if symbol >= LENGTH_BASE.len() {
+            return Err(GzipError::Malformed("Invalid length symbol"));
+        }
+
+        let length = LENGTH_BASE[symbol] + reader.bits(LENGTH_EXTRA[symbol])? as usize;
+        let symbol = distances.decode(reader)?;
+
+        if symbol >= DISTANCE_BASE.len() {
+            return Err(GzipError::Malformed("Invalid distance symbol"));
+        }
+
+        let distance = DISTANCE_BASE[symbol] + reader.bits(DISTANCE_EXTRA[symbol])? as usize;
+
+        if distance > output.len() {
+            return Err(GzipError::Malformed("Distance too far back"));
+        }
+
+        if output.len() + length > max_bytes {
+            return Err(GzipError::TooLarge);
+        }
+
+        // Copy byte by byte, because the source may overlap the bytes being appended.
+        let from = output.len() - distance;
+
+        for index in from..from + length {
+            let byte = output[index];
+            output.push(byte);
+        }
+    }
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), GzipError> {
+    let mut lengths = [0u8; 288];
+
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), GzipError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_count = reader.bits(4)? as usize + 4;
+
+    if literal_count > 286 || distance_count > 30 {
+        return Err(GzipError::Malformed("Too many length or distance symbols"));
+    }
+
+    let mut lengths = [0u8; 19];
+
+    for &symbol in &CODE_LENGTH_ORDER[..code_count] {
+        lengths[symbol] = reader.bits(3)? as u8;
+    }
+
+    let code_lengths = Huffman::new(&lengths)?;
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut index = 0;
+
+    while index < lengths.len() {
+        let symbol = code_lengths.decode(reader)?;
+
+        if symbol < 16 {
+            lengths[index] = symbol as u8;
+            index += 1;
+            continue;
+        }
+
+        let (length, repeat) = match symbol {
+            16 if index == 0 => return Err(GzipError::Malformed("Repeat without previous length")),
+            16 => (lengths[index - 1], 3 + reader.bits(2)? as usize),
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+
+        if index + repeat > lengths.len() {
+            return Err(GzipError::Malformed("Too many code lengths"));
+        }
+
+        for length_slot in &mut lengths[index..index + repeat] {
+            *length_slot = length;
+        }
+
+        index += repeat;
+    }
+
+    if lengths[256] == 0 {
+        return Err(GzipError::Malformed("Missing end of block code"));
+    }
+
+    Ok((Huffman::new(&lengths[..literal_count])?, Huffman::new(&lengths[literal_count..])?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+    use file;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> Vec<u8> {
+        file::read_bytes(&PathBuf::from(format!("test/fixtures/{}", name))).unwrap()
+    }
+
+    #[test]
+    fn decompress_fixed_dynamic_and_stored_blocks() {
+        let hello = fixture("hello.txt");
+        let html = file::read_bytes(&PathBuf::from("test/web_dir/hello.html")).unwrap();
+
+        assert_that!(decompress(&fixture("hello.txt.gz"), 4096), is(equal_to(Ok(hello.clone()))));
+        assert_that!(decompress(&fixture("hello_stored.txt.gz"), 4096), is(equal_to(Ok(hello))));
+        assert_that!(decompress(&fixture("hello.html.gz"), 4096), is(equal_to(Ok(html))));
+    }
+
+    #[test]
+    fn decompress_stops_at_maximum_size() {
+        assert_that!(decompress(&fixture("zeros.gz"), 4096), is(equal_to(Err(GzipError::TooLarge))));
+    }
+
+    #[test]
+    fn decompress_rejects_corrupt_data() {
+        let mut corrupt = fixture("hello.txt.gz");
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xff;
+
+        assert_that!(decompress(b"hello", 4096).is_err(), is(true));
+        assert_that!(decompress(&corrupt, 4096), is(equal_to(Err(GzipError::Malformed("Size mismatch")))));
+    }
+}
@@ -1,19 +1,269 @@
 ///! This module provides abstractions to deal with HTTP requests and responses.
 
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
+use std::io;
+use std::net::Ipv6Addr;
+use std::time::Duration;
 
 /// Used HTTP version.
 pub static VERSION: &'static str = "1.1";
 /// Allowed HTTP methods.
 pub static ALLOWED_METHODS: &'static str = "GET, POST, HEAD";
+/// Media type of submitted HTML forms.
+pub static FORM_URLENCODED: &'static str = "application/x-www-form-urlencoded";
+/// Headers which only apply to a single connection and must not be forwarded by proxies, see
+/// [RFC 7230, section 6.1](https://tools.ietf.org/html/rfc7230#section-6.1).
+pub static HOP_BY_HOP_HEADERS: [&'static str; 8] = [
+    "connection", "keep-alive", "proxy-authenticate", "proxy-authorization", "te", "trailer",
+    "transfer-encoding", "upgrade"];
+
+/// Declares the [HTTP request methods](https://tools.ietf.org/html/rfc7231#section-4).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+    /// Any other method token.
+    Extension(String),
+}
+
+impl Method {
+    /// Creates the method from a method token. Method tokens are case-sensitive.
+    pub fn from_token(token: &str) -> Method {
+        match token {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "CONNECT" => Method::Connect,
+            "OPTIONS" => Method::Options,
+            "TRACE" => Method::Trace,
+            "PATCH" => Method::Patch,
+            _ => Method::Extension(token.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let printable = match *self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Connect => "CONNECT",
+            Method::Options => "OPTIONS",
+            Method::Trace => "TRACE",
+            Method::Patch => "PATCH",
+            Method::Extension(ref token) => token,
+        };
+        write!(f, "{}", printable)
+    }
+}
+
+/// Represents a request target in one of its [forms](https://tools.ietf.org/html/rfc7230#section-5.3).
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestTarget {
+    /// An absolute path with optional query, e.g. `/index.html?lang=en`.
+    Origin { path: String, query: Option<String> },
+    /// An absolute URI, e.g. `http://localhost:8080/index.html`. Sent to proxies.
+    Absolute(Uri),
+    /// Host and port only, e.g. `localhost:443`. Only used by CONNECT.
+    Authority(String),
+    /// A single `*` addressing the server as a whole. Only used by OPTIONS.
+    Asterisk,
+}
+
+impl RequestTarget {
+    /// Parses the given request target, `None` if it has none of the forms.
+    pub fn of(target: &str) -> Option<RequestTarget> {
+        if target == "*" {
+            Some(RequestTarget::Asterisk)
+        } else if target.starts_with('/') {
+            let (path, query) = split_query(target);
+            Some(RequestTarget::Origin { path, query })
+        } else if is_absolute_uri(target) {
+            Uri::parse(target).map(RequestTarget::Absolute)
+        } else if is_authority(target) {
+            Some(RequestTarget::Authority(target.to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// Checks if a request with the given method may use this form.
+    pub fn is_allowed_for(&self, method: &Method) -> bool {
+        match *self {
+            RequestTarget::Authority(_) => *method == Method::Connect,
+            RequestTarget::Asterisk => *method == Method::Options,
+            RequestTarget::Origin { .. } | RequestTarget::Absolute(_) => *method != Method::Connect,
+        }
+    }
+
+    /// Get the name of the form, e.g. `origin-form`.
+    pub fn form(&self) -> &'static str {
+        match *self {
+            RequestTarget::Origin { .. } => "origin-form",
+            RequestTarget::Absolute(_) => "absolute-form",
+            RequestTarget::Authority(_) => "authority-form",
+            RequestTarget::Asterisk => "asterisk-form",
+        }
+    }
+
+    /// Get the path of the origin-form or the absolute-form, `None` for the other forms.
+    pub fn path(&self) -> Option<&str> {
+        match *self {
+            RequestTarget::Origin { ref path, .. } => Some(path),
+            RequestTarget::Absolute(ref uri) => Some(uri.path()),
+            _ => None,
+        }
+    }
+
+    /// Get the [normalized](fn.normalize_path.html) path of the origin-form or the absolute-form,
+    /// `None` for the other forms.
+    pub fn normalized_path(&self) -> Option<String> {
+        self.path().map(normalize_path)
+    }
+
+    /// Get the query of the origin-form or the absolute-form, `None` if there is none.
+    pub fn query(&self) -> Option<&str> {
+        match *self {
+            RequestTarget::Origin { ref query, .. } => query.as_ref().map(String::as_str),
+            RequestTarget::Absolute(ref uri) => uri.query().map(String::as_str),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RequestTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RequestTarget::Origin { ref path, query: Some(ref query) } => write!(f, "{}?{}", path, query),
+            RequestTarget::Origin { ref path, query: None } => write!(f, "{}", path),
+            RequestTarget::Absolute(ref uri) => write!(f, "{}", uri),
+            RequestTarget::Authority(ref authority) => write!(f, "{}", authority),
+            RequestTarget::Asterisk => write!(f, "*"),
+        }
+    }
+}
+
+/// Represents an absolute URI of the absolute-form, e.g. `http://localhost:8080/index.html?lang=en`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Uri {
+    scheme: String,
+    authority: String,
+    path: String,
+    query: Option<String>,
+}
+
+impl Uri {
+    /// Parses an absolute URI, `None` if it has no scheme. A fragment is dropped.
+    pub fn parse(uri: &str) -> Option<Uri> {
+        if !is_absolute_uri(uri) {
+            return None;
+        }
+
+        let scheme_end = uri.find("://")?;
+        let rest = &uri[scheme_end + "://".len()..];
+        let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+        let path_and_query = rest[authority_end..].split('#').next().unwrap_or("");
+        let (path, query) = split_query(path_and_query);
+
+        Some(Uri {
+            scheme: uri[..scheme_end].to_ascii_lowercase(),
+            authority: rest[..authority_end].to_string(),
+            path,
+            query,
+        })
+    }
+
+    /// Get the scheme in lowercase, e.g. `http`.
+    pub fn scheme(&self) -> &String {
+        &self.scheme
+    }
+
+    /// Get the host and optional port, e.g. `localhost:8080`.
+    pub fn authority(&self) -> &String {
+        &self.authority
+    }
+
+    /// Get the path, empty if the URI has none.
+    pub fn path(&self) -> &String {
+        &self.path
+    }
+
+    /// Get the query without the leading `?`, `None` if there is none.
+    pub fn query(&self) -> Option<&String> {
+        self.query.as_ref()
+    }
+}
+
+impl fmt::Display for Uri {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}://{}{}", self.scheme, self.authority, self.path)?;
+
+        if let Some(ref query) = self.query {
+            write!(f, "?{}", query)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits a path with optional query at the first `?`.
+fn split_query(target: &str) -> (String, Option<String>) {
+    match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (target.to_string(), None),
+    }
+}
+
+/// Checks for a scheme followed by `://`, e.g. `http://`.
+fn is_absolute_uri(target: &str) -> bool {
+    match target.find("://") {
+        Some(index) => {
+            let scheme = &target[..index];
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+        },
+        None => false,
+    }
+}
+
+/// Checks for a host followed by a numeric port, e.g. `localhost:443`.
+fn is_authority(target: &str) -> bool {
+    match target.rfind(':') {
+        Some(index) => {
+            let host = &target[..index];
+            let port = &target[index + 1..];
+            !host.is_empty()
+                && !host.contains(['/', '?', '#', '@'])
+                && !port.is_empty()
+                && port.chars().all(|c| c.is_ascii_digit())
+        },
+        None => false,
+    }
+}
 
 /// Represents a HTTP request.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Request {
     /// Requested HTTP method.
     method: String,
-    /// Request HTTP URL.
+    /// Request HTTP URL as received.
     url: String,
+    /// Request URL parsed according to its form.
+    target: RequestTarget,
     /// Version of HTTP the client speaks.
     version: String,
     host: String,
@@ -24,8 +274,21 @@ pub struct Request {
     accept_encoding: String,
     cookie: String,
     connection: String,
+    upgrade: String,
     referer: String,
     cache_control: String,
+    expect: String,
+    content_length: String,
+    range: String,
+    if_range: String,
+    content_encoding: String,
+    content_type: String,
+    /// All header fields in the order received, by name as sent and value.
+    headers: Vec<(String, String)>,
+    /// Body of the request, decoded according to its content encoding.
+    body: Vec<u8>,
+    /// Header section exactly as received, empty unless retained.
+    raw_headers: Vec<u8>,
 }
 
 impl Request {
@@ -34,116 +297,511 @@ impl Request {
         &self.method
     }
 
-    /// Get the requested URL.
+    /// Whether the requested method is the given one, ignoring case.
+    ///
+    /// Methods are case-sensitive, so this is only meant for lenient handling of sloppy clients.
+    pub fn method_is(&self, method: &str) -> bool {
+        self.method.eq_ignore_ascii_case(method)
+    }
+
+    /// Get the requested URL as received.
     pub fn url(&self) -> &String {
         &self.url
     }
+
+    /// Get the requested URL parsed according to its form.
+    pub fn target(&self) -> &RequestTarget {
+        &self.target
+    }
+
+    /// Get the path of the target without query, e.g. `/index.html` for `http://localhost/index.html?lang=en`.
+    ///
+    /// Targets without path, like the `*` of `OPTIONS`, are returned as received.
+    pub fn path(&self) -> &str {
+        self.target.path().unwrap_or(&self.url)
+    }
+
+    /// Get the target in origin-form, e.g. `/index.html?lang=en` for `http://localhost/index.html?lang=en`.
+    pub fn path_and_query(&self) -> String {
+        match self.target.query() {
+            Some(query) => format!("{}?{}", self.path(), query),
+            None => self.path().to_string(),
+        }
+    }
+
+    /// Get the HTTP version the client speaks, e.g. `1.1`.
+    pub fn version(&self) -> &String {
+        &self.version
+    }
+
+    /// Get the host the request is addressed to, empty if none.
+    pub fn host(&self) -> &String {
+        &self.host
+    }
+
+    /// Get the connection options of the client, empty if none.
+    pub fn connection(&self) -> &String {
+        &self.connection
+    }
+
+    /// Get the protocols the client asks to switch to, empty if none.
+    pub fn upgrade(&self) -> &String {
+        &self.upgrade
+    }
+
+    /// Get the expectation of the client, empty if none.
+    pub fn expect(&self) -> &String {
+        &self.expect
+    }
+
+    /// Get the declared length of the request body, `None` if not declared.
+    ///
+    /// The value is validated while parsing, so a malformed length never gets here.
+    pub fn content_length(&self) -> Option<usize> {
+        self.content_length.parse().ok()
+    }
+
+    /// Get the requested byte range, empty if none.
+    pub fn range(&self) -> &String {
+        &self.range
+    }
+
+    /// Get the validator the requested range is conditional on, empty if none.
+    pub fn if_range(&self) -> &String {
+        &self.if_range
+    }
+
+    /// Get the encoding the request body is compressed with, empty if none.
+    pub fn content_encoding(&self) -> &String {
+        &self.content_encoding
+    }
+
+    /// Get the encodings the client accepts for the response, the most preferred first.
+    ///
+    /// Encodings without a quality value get `1.0`. Encodings with `q=0` are kept, because the
+    /// client explicitly refuses them, e.g. `identity;q=0` forbids an uncompressed response.
+    /// Malformed entries are skipped. Names are lower case.
+    pub fn accepted_encodings(&self) -> Vec<(String, f32)> {
+        let mut encodings: Vec<(String, f32)> = self.accept_encoding.split(',')
+            .filter_map(parse_quality_item)
+            .collect();
+        // The sort is stable, so encodings of equal quality stay in the order the client sent.
+        encodings.sort_by(|a, b| b.1.total_cmp(&a.1));
+        encodings
+    }
+
+    /// Get the media type of the request body, empty if none.
+    pub fn content_type(&self) -> &String {
+        &self.content_type
+    }
+
+    /// Get the decoded body of the request, empty if none was received.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Get the value of the first header field with the given name, `None` if there is none.
+    ///
+    /// Header field names are case-insensitive, so `content-type` finds `Content-Type`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Get all header fields as name and value in the order they were received.
+    pub fn headers(&self) -> &Vec<(String, String)> {
+        &self.headers
+    }
+
+    /// Replaces all header fields with the given name by a single one with the value.
+    ///
+    /// It takes the place of the first replaced field, or is appended if there was none.
+    /// Accessors of the header like [`host`](#method.host) return the new value.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        let position = self.headers.iter().position(|(header_name, _)| header_name.eq_ignore_ascii_case(name));
+        self.remove_header(name);
+        let header = (name.to_string(), value.to_string());
+
+        match position {
+            Some(position) => self.headers.insert(position, header),
+            None => self.headers.push(header),
+        }
+
+        if let Some(field) = self.header_field_mut(name) {
+            *field = value.to_string();
+        }
+    }
+
+    /// Removes all header fields with the given name.
+    ///
+    /// Accessors of the header like [`host`](#method.host) return an empty value afterwards.
+    pub fn remove_header(&mut self, name: &str) {
+        self.headers.retain(|(header_name, _)| !header_name.eq_ignore_ascii_case(name));
+
+        if let Some(field) = self.header_field_mut(name) {
+            field.clear();
+        }
+    }
+
+    /// Get the field holding the value of a header with an accessor of its own.
+    fn header_field_mut(&mut self, name: &str) -> Option<&mut String> {
+        match name.to_ascii_lowercase().as_str() {
+            "host" => Some(&mut self.host),
+            "user-agent" => Some(&mut self.user_agent),
+            "accept" => Some(&mut self.accept),
+            "accept-language" => Some(&mut self.accept_language),
+            "accept-encoding" => Some(&mut self.accept_encoding),
+            "cookie" => Some(&mut self.cookie),
+            "connection" => Some(&mut self.connection),
+            "upgrade" => Some(&mut self.upgrade),
+            "upgrade-insecure-requests" => Some(&mut self.upgrade_insecure_requests),
+            "referer" => Some(&mut self.referer),
+            "cache-control" => Some(&mut self.cache_control),
+            "expect" => Some(&mut self.expect),
+            "content-length" => Some(&mut self.content_length),
+            "range" => Some(&mut self.range),
+            "if-range" => Some(&mut self.if_range),
+            "content-encoding" => Some(&mut self.content_encoding),
+            "content-type" => Some(&mut self.content_type),
+            _ => None,
+        }
+    }
+
+    /// Parses the body of a submitted HTML form into its fields.
+    ///
+    /// Returns `None` unless the body is of type `application/x-www-form-urlencoded`. The body
+    /// is parsed like a [query string](fn.parse_query.html).
+    pub fn form(&self) -> Option<HashMap<String, String>> {
+        let media_type = self.content_type.split(';').next().unwrap_or("").trim();
+
+        if !media_type.eq_ignore_ascii_case(FORM_URLENCODED) {
+            return None;
+        }
+
+        Some(parse_query(&String::from_utf8_lossy(&self.body)))
+    }
+
+    /// Sets the decoded body of the request.
+    pub fn with_body(self, body: Vec<u8>) -> Request {
+        Request { body, ..self }
+    }
+
+    /// Get the header section exactly as received, empty unless the server retains it.
+    ///
+    /// Unlike the header values it is neither trimmed nor decoded, e.g. to verify a signature
+    /// computed over the headers a client sent.
+    pub fn raw_headers(&self) -> &[u8] {
+        &self.raw_headers
+    }
+
+    /// Sets the header section as received.
+    pub fn with_raw_headers(self, raw_headers: Vec<u8>) -> Request {
+        Request { raw_headers, ..self }
+    }
+}
+
+/// Represents a HTTP request whose fields borrow from the parsed input.
+///
+/// Parsing into this does not copy the header values. Use [`to_owned`](#method.to_owned) to get
+/// a [`Request`](struct.Request.html) which outlives the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestRef<'a> {
+    method: &'a str,
+    url: &'a str,
+    version: &'a str,
+    host: &'a str,
+    user_agent: &'a str,
+    accept: &'a str,
+    upgrade_insecure_requests: &'a str,
+    accept_language: &'a str,
+    accept_encoding: &'a str,
+    cookie: &'a str,
+    connection: &'a str,
+    upgrade: &'a str,
+    referer: &'a str,
+    cache_control: &'a str,
+    expect: &'a str,
+    content_length: &'a str,
+    range: &'a str,
+    if_range: &'a str,
+    content_encoding: &'a str,
+    content_type: &'a str,
+    headers: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> RequestRef<'a> {
+    /// Get the requested method.
+    pub fn method(&self) -> &'a str {
+        self.method
+    }
+
+    /// Get the requested URL.
+    pub fn url(&self) -> &'a str {
+        self.url
+    }
+
+    /// Parses the requested URL according to its form.
+    ///
+    /// The URL is not stored parsed to not copy it.
+    pub fn target(&self) -> RequestTarget {
+        // The form has been checked while scanning the request line.
+        RequestTarget::of(self.url)
+            .unwrap_or_else(|| RequestTarget::Origin { path: self.url.to_string(), query: None })
+    }
+
+    /// Get the HTTP version the client speaks, e.g. `1.1`.
+    pub fn version(&self) -> &'a str {
+        self.version
+    }
+
+    /// Get the host the request is addressed to, empty if none.
+    pub fn host(&self) -> &'a str {
+        self.host
+    }
+
+    /// Get the connection options of the client, empty if none.
+    pub fn connection(&self) -> &'a str {
+        self.connection
+    }
+
+    /// Get the protocols the client asks to switch to, empty if none.
+    pub fn upgrade(&self) -> &'a str {
+        self.upgrade
+    }
+
+    /// Get the expectation of the client, empty if none.
+    pub fn expect(&self) -> &'a str {
+        self.expect
+    }
+
+    /// Get the declared length of the request body, `None` if not declared.
+    pub fn content_length(&self) -> Option<usize> {
+        self.content_length.parse().ok()
+    }
+
+    /// Get the requested byte range, empty if none.
+    pub fn range(&self) -> &'a str {
+        self.range
+    }
+
+    /// Get the validator the requested range is conditional on, empty if none.
+    pub fn if_range(&self) -> &'a str {
+        self.if_range
+    }
+
+    /// Get the encoding the request body is compressed with, empty if none.
+    pub fn content_encoding(&self) -> &'a str {
+        self.content_encoding
+    }
+
+    /// Get the media type of the request body, empty if none.
+    pub fn content_type(&self) -> &'a str {
+        self.content_type
+    }
+
+    /// Get the value of the first header field with the given name, `None` if there is none.
+    ///
+    /// Header field names are case-insensitive, so `content-type` finds `Content-Type`.
+    pub fn header(&self, name: &str) -> Option<&'a str> {
+        self.headers.iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|&(_, value)| value)
+    }
+
+    /// Copies the borrowed fields into an owned request.
+    pub fn to_owned(&self) -> Request {
+        Request {
+            method: self.method.to_string(),
+            url: self.url.to_string(),
+            target: self.target(),
+            version: self.version.to_string(),
+            host: self.host.to_string(),
+            user_agent: self.user_agent.to_string(),
+            accept: self.accept.to_string(),
+            upgrade_insecure_requests: self.upgrade_insecure_requests.to_string(),
+            accept_language: self.accept_language.to_string(),
+            accept_encoding: self.accept_encoding.to_string(),
+            cookie: self.cookie.to_string(),
+            connection: self.connection.to_string(),
+            upgrade: self.upgrade.to_string(),
+            referer: self.referer.to_string(),
+            cache_control: self.cache_control.to_string(),
+            expect: self.expect.to_string(),
+            content_length: self.content_length.to_string(),
+            range: self.range.to_string(),
+            if_range: self.if_range.to_string(),
+            content_encoding: self.content_encoding.to_string(),
+            content_type: self.content_type.to_string(),
+            headers: self.headers.iter()
+                .map(|&(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: Vec::new(),
+            raw_headers: Vec::new(),
+        }
+    }
 }
 
 #[derive(Debug)]
-struct RequestBuilder {
-    method: String,
-    url: String,
-    version: String,
-    host: String,
-    user_agent: String,
-    accept: String,
-    upgrade_insecure_requests: String,
-    accept_language: String,
-    accept_encoding: String,
-    cookie: String,
-    connection: String,
-    referer: String,
-    cache_control: String,
+struct RequestBuilder<'a> {
+    method: &'a str,
+    url: &'a str,
+    version: &'a str,
+    host: &'a str,
+    user_agent: &'a str,
+    accept: &'a str,
+    upgrade_insecure_requests: &'a str,
+    accept_language: &'a str,
+    accept_encoding: &'a str,
+    cookie: &'a str,
+    connection: &'a str,
+    upgrade: &'a str,
+    referer: &'a str,
+    cache_control: &'a str,
+    expect: &'a str,
+    content_length: &'a str,
+    range: &'a str,
+    if_range: &'a str,
+    content_encoding: &'a str,
+    content_type: &'a str,
+    headers: Vec<(&'a str, &'a str)>,
 }
 
-impl RequestBuilder {
-    fn new() -> RequestBuilder {
+impl<'a> RequestBuilder<'a> {
+    fn new() -> RequestBuilder<'a> {
         RequestBuilder {
-            method: String::from(""),
-            url: String::from(""),
-            version: String::from(""),
-            host: String::from(""),
-            user_agent: String::from(""),
-            accept: String::from(""),
-            upgrade_insecure_requests: String::from(""),
-            accept_language: String::from(""),
-            accept_encoding: String::from(""),
-            cookie: String::from(""),
-            connection: String::from(""),
-            referer: String::from(""),
-            cache_control: String::from(""),
-        }
-    }
-
-    fn create(&self) -> Request {
-        Request {
-            method: self.method.clone(),
-            url: self.url.clone(),
-            version: self.version.clone(),
-            host: self.host.clone(),
-            user_agent: self.user_agent.clone(),
-            accept: self.accept.clone(),
-            upgrade_insecure_requests: self.upgrade_insecure_requests.clone(),
-            accept_language: self.accept_language.clone(),
-            accept_encoding: self.accept_encoding.clone(),
-            cookie: self.cookie.clone(),
-            connection: self.connection.clone(),
-            referer: self.referer.clone(),
-            cache_control: self.cache_control.clone(),
+            method: "",
+            url: "",
+            version: "",
+            host: "",
+            user_agent: "",
+            accept: "",
+            upgrade_insecure_requests: "",
+            accept_language: "",
+            accept_encoding: "",
+            cookie: "",
+            connection: "",
+            upgrade: "",
+            referer: "",
+            cache_control: "",
+            expect: "",
+            content_length: "",
+            range: "",
+            if_range: "",
+            content_encoding: "",
+            content_type: "",
+            headers: Vec::new(),
+        }
+    }
+
+    fn create(self) -> RequestRef<'a> {
+        RequestRef {
+            method: self.method,
+            url: self.url,
+            version: self.version,
+            host: self.host,
+            user_agent: self.user_agent,
+            accept: self.accept,
+            upgrade_insecure_requests: self.upgrade_insecure_requests,
+            accept_language: self.accept_language,
+            accept_encoding: self.accept_encoding,
+            cookie: self.cookie,
+            connection: self.connection,
+            upgrade: self.upgrade,
+            referer: self.referer,
+            cache_control: self.cache_control,
+            expect: self.expect,
+            content_length: self.content_length,
+            range: self.range,
+            if_range: self.if_range,
+            content_encoding: self.content_encoding,
+            content_type: self.content_type,
+            headers: self.headers,
         }
     }
 
-    fn with_method(&mut self, new_method: &String) {
-        self.method = new_method.clone();
+    fn with_method(&mut self, new_method: &'a str) {
+        self.method = new_method;
     }
 
-    fn with_url(&mut self, new_url: &String) {
-        self.url = new_url.clone();
+    fn with_url(&mut self, new_url: &'a str) {
+        self.url = new_url;
     }
 
-    fn with_version(&mut self, new_version: &String) {
-        self.version = new_version.clone();
+    fn with_version(&mut self, new_version: &'a str) {
+        self.version = new_version;
     }
 
-    fn with_host(&mut self, new_host: &String) {
-        self.host = new_host.clone();
+    fn with_host(&mut self, new_host: &'a str) {
+        self.host = new_host;
     }
 
-    fn with_user_agent(&mut self, new_user_agent: &String) {
-        self.user_agent = new_user_agent.clone();
+    fn with_user_agent(&mut self, new_user_agent: &'a str) {
+        self.user_agent = new_user_agent;
     }
 
-    fn with_accept(&mut self, new_accept: &String) {
-        self.accept = new_accept.clone();
+    fn with_accept(&mut self, new_accept: &'a str) {
+        self.accept = new_accept;
     }
 
-    fn with_accept_language(&mut self, new_accept_language: &String) {
-        self.accept_language = new_accept_language.clone();
+    fn with_upgrade_insecure_requests(&mut self, new_upgrade_insecure_requests: &'a str) {
+        self.upgrade_insecure_requests = new_upgrade_insecure_requests;
     }
 
-    fn with_accept_encoding(&mut self, new_accept_encoding: &String) {
-        self.accept_encoding = new_accept_encoding.clone();
+    fn with_accept_language(&mut self, new_accept_language: &'a str) {
+        self.accept_language = new_accept_language;
     }
 
-    fn with_cookie(&mut self, new_cookie: &String) {
-        self.cookie = new_cookie.clone();
+    fn with_accept_encoding(&mut self, new_accept_encoding: &'a str) {
+        self.accept_encoding = new_accept_encoding;
     }
 
-    fn with_connection(&mut self, new_connection: &String) {
-        self.connection = new_connection.clone();
+    fn with_cookie(&mut self, new_cookie: &'a str) {
+        self.cookie = new_cookie;
     }
 
-    fn with_upgrade_insecure_requests(&mut self, new_upgrade_insecure_requests: &String) {
-        self.upgrade_insecure_requests = new_upgrade_insecure_requests.clone();
+    fn with_connection(&mut self, new_connection: &'a str) {
+        self.connection = new_connection;
     }
 
-    fn with_referer(&mut self, new_referer: &String) {
-        self.referer = new_referer.clone();
+    fn with_upgrade(&mut self, new_upgrade: &'a str) {
+        self.upgrade = new_upgrade;
     }
 
-    fn with_cache_control(&mut self, new_cache_control: &String) {
-        self.cache_control = new_cache_control.clone();
+    fn with_referer(&mut self, new_referer: &'a str) {
+        self.referer = new_referer;
+    }
+
+    fn with_cache_control(&mut self, new_cache_control: &'a str) {
+        self.cache_control = new_cache_control;
+    }
+
+    fn with_expect(&mut self, new_expect: &'a str) {
+        self.expect = new_expect;
+    }
+
+    fn with_content_length(&mut self, new_content_length: &'a str) {
+        self.content_length = new_content_length;
+    }
+
+    fn with_range(&mut self, new_range: &'a str) {
+        self.range = new_range;
+    }
+
+    fn with_if_range(&mut self, new_if_range: &'a str) {
+        self.if_range = new_if_range;
+    }
+
+    fn with_content_encoding(&mut self, new_content_encoding: &'a str) {
+        self.content_encoding = new_content_encoding;
+    }
+
+    fn with_content_type(&mut self, new_content_type: &'a str) {
+        self.content_type = new_content_type;
+    }
+
+    fn with_header(&mut self, name: &'a str, value: &'a str) {
+        self.headers.push((name, value));
     }
 }
 
@@ -154,23 +812,78 @@ pub struct Response {
     status: Status,
     headers: Vec<ResponseHeader>,
     body: Vec<u8>,
+    length_decided: bool,
 }
 
 impl Response {
     pub fn new(version: String, status: Status, body: Vec<u8>) -> Response {
-        Response { version, status, headers: Vec::new(), body }
+        Response { version, status, headers: Vec::new(), body, length_decided: false }
+    }
+
+    /// Creates a response without body, e.g. `204 No Content` or `304 Not Modified`.
+    pub fn empty(version: String, status: Status) -> Response {
+        Response::new(version, status, Vec::new())
+    }
+
+    /// Creates a `204 No Content` response, which is rendered without `Content-Length` and body.
+    pub fn no_content() -> Response {
+        Response::empty(VERSION.to_string(), Status::NoContent)
+    }
+
+    /// Creates a permanent redirect to the same target on the `https` scheme.
+    ///
+    /// The port of the host is replaced by `port`, which is left out if it is the default port
+    /// 443 of HTTPS. A bare IPv6 address is put in brackets. The redirect is a `308`, so the
+    /// client repeats the request with the same method and body.
+    pub fn redirect_https(host: &str, port: u16, target: &str) -> Response {
+        let host = match host.trim().parse::<Ipv6Addr>() {
+            Ok(address) => format!("[{}]", address),
+            Err(_) => strip_port(host).to_string(),
+        };
+        let authority = if port == 443 { host } else { format!("{}:{}", host, port) };
+        let mut response = Response::new(VERSION.to_string(), Status::PermanentRedirect, Vec::new());
+        response.add_header(ResponseHeader::ContentLength(0));
+        response.add_header(ResponseHeader::Location(format!("https://{}{}", authority, target)));
+        response
+    }
+
+    /// Creates the error response for a failure reading a resource.
+    ///
+    /// A missing resource is reported as `404`, a resource the server may not read as `403` and
+    /// anything else as `500`.
+    pub fn from_io_error(err: &io::Error) -> Response {
+        let (status, message) = match err.kind() {
+            io::ErrorKind::NotFound => (Status::NotFound, "Not found!"),
+            io::ErrorKind::PermissionDenied => (Status::Forbidden, "Forbidden!"),
+            _ => (Status::InternalServerError, "Internal server error!"),
+        };
+        let mut response = Response::new(VERSION.to_string(), status, message.as_bytes().to_vec());
+        response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+        response
     }
 
     /// Renders the response into a byte vector to be written to  stream.
+    ///
+    /// If the status does not [allow a body](enum.Status.html#method.allows_body), neither the body
+    /// nor a `Content-Length` header is rendered. Otherwise a missing `Content-Length` header is
+    /// added, so the client knows where the body ends on a persistent connection, unless the
+    /// length was [decided already](#method.mark_length_decided).
     pub fn render(&self) -> Vec<u8> {
         let mut buffer: Vec<u8> = Vec::new();
         let first_line = format!("HTTP/{} {}\r\n", self.version, self.status);
+        let allows_body = self.status.allows_body();
 
         for byte in first_line.as_bytes() {
             buffer.push(*byte);
         }
 
         for header in self.headers.iter() {
+            if let ResponseHeader::ContentLength(_) = *header {
+                if !allows_body {
+                    continue;
+                }
+            }
+
             let header = format!("{}\r\n", header);
 
             for byte in header.as_bytes() {
@@ -178,9 +891,17 @@ impl Response {
             }
         }
 
+        if allows_body && !self.length_decided && !self.has_content_length() {
+            buffer.extend_from_slice(format!("{}\r\n", ResponseHeader::ContentLength(self.body.len())).as_bytes());
+        }
+
         buffer.push('\r' as u8);
         buffer.push('\n' as u8);
 
+        if !allows_body {
+            return buffer;
+        }
+
         for byte in &self.body {
             buffer.push(*byte);
         }
@@ -188,13 +909,98 @@ impl Response {
         buffer
     }
 
+    /// Get the HTTP version of the response, e.g. `1.1`.
+    pub fn version(&self) -> &String {
+        &self.version
+    }
+
+    /// Get the response status.
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    /// Get the headers in the order they are rendered.
+    pub fn headers(&self) -> &Vec<ResponseHeader> {
+        &self.headers
+    }
+
+    /// Get the response body.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Get the length of the response body in bytes.
+    pub fn body_length(&self) -> usize {
+        self.body.len()
+    }
+
+    /// Removes the body, e.g. to answer a `HEAD` request. The headers are kept.
+    ///
+    /// A missing `Content-Length` header is added with the length of the dropped body first.
+    pub fn clear_body(&mut self) {
+        if self.status.allows_body() && !self.length_decided && !self.has_content_length() {
+            let length = self.body.len();
+            self.add_header(ResponseHeader::ContentLength(length));
+        }
+
+        self.body.clear();
+    }
+
+    /// Turns the response into the one for a `HEAD` request by dropping the body.
+    ///
+    /// All headers are kept, including `Content-Length` of the dropped body.
+    pub fn into_head(mut self) -> Response {
+        self.clear_body();
+        self
+    }
+
+    /// Marks the headers as deciding the body length, so no `Content-Length` header is added.
+    ///
+    /// Used for a relayed `HEAD` response, whose upstream may not have sent a length at all.
+    pub fn mark_length_decided(&mut self) {
+        self.length_decided = true;
+    }
+
+    fn has_content_length(&self) -> bool {
+        self.headers.iter().any(|header| matches!(*header, ResponseHeader::ContentLength(_)))
+    }
+
+    /// Forbids browsers and proxies to cache the response, e.g. because it is generated.
+    ///
+    /// `Pragma` is added for HTTP/1.0 caches which don't know `Cache-Control`.
+    pub fn no_cache(&mut self) {
+        self.add_header(ResponseHeader::CacheControl(String::from("no-store, no-cache, must-revalidate")));
+        self.add_header(ResponseHeader::Pragma(String::from("no-cache")));
+    }
+
+    /// Appends a header.
+    ///
+    /// Headers are rendered in the order they were added. Repeated headers like `Set-Cookie`
+    /// are neither merged nor deduplicated.
     pub fn add_header(&mut self, header: ResponseHeader) {
         self.headers.push(header);
     }
 }
 
+/// Removes the port from a host like `example.com:8080` or `[::1]:8080`.
+fn strip_port(host: &str) -> &str {
+    let host = host.trim();
+
+    if host.starts_with('[') {
+        return match host.find(']') {
+            Some(end) => &host[..end + 1],
+            None => host,
+        };
+    }
+
+    match host.rfind(':') {
+        Some(colon) => &host[..colon],
+        None => host,
+    }
+}
+
 /// This enum declares some [HTTP response headers](https://www.w3.org/Protocols/rfc2616/rfc2616-sec14.html).
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ResponseHeader {
     /// Allowed HTTP methods: `Allow: GET, POST, HEAD`.
     Allow(String),
@@ -206,6 +1012,64 @@ pub enum ResponseHeader {
     ContentLength(usize),
     // Date: Wed, 14 Feb 2018 11:27:44 GMT
     Date(String),
+    // Retry-After: 120
+    RetryAfter(u64),
+    // ETag: "1f4-5a842f2c"
+    ETag(String),
+    // Last-Modified: Wed, 14 Feb 2018 11:27:44 GMT
+    LastModified(String),
+    // Content-Range: bytes 0-499/1234
+    ContentRange(String),
+    // Connection: keep-alive
+    Connection(String),
+    // Cache-Control: public, max-age=604800
+    CacheControl(String),
+    /// Cache directive for HTTP/1.0 caches: `Pragma: no-cache`.
+    Pragma(String),
+    // Location: /docs/
+    Location(String),
+    /// Time the server spent on the request: `Server-Timing: app;dur=12.3`, in milliseconds.
+    ServerTiming(Duration),
+    /// File name suggested for saving the attachment: `Content-Disposition: attachment; filename="a.zip"`.
+    ContentDisposition(String),
+    /// Protocol the server switches to: `Upgrade: websocket`.
+    Upgrade(String),
+    /// Proof that the server read the WebSocket handshake: `Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=`.
+    SecWebSocketAccept(String),
+    /// Idle timeout in seconds and remaining requests of a persistent connection: `Keep-Alive: timeout=5, max=100`.
+    KeepAlive(u64, usize),
+    /// Any other header given by name and value: `Set-Cookie: id=42`.
+    Custom(String, String),
+}
+
+impl ResponseHeader {
+    /// Creates the header for a name and value as received.
+    ///
+    /// Header names are case-insensitive. Headers without a declared variant, or whose value
+    /// does not fit it, are kept as [`Custom`](#variant.Custom).
+    pub fn from_name_value(name: &str, value: &str) -> ResponseHeader {
+        let value = value.to_string();
+
+        match name.to_ascii_lowercase().as_str() {
+            "allow" => ResponseHeader::Allow(value),
+            "server" => ResponseHeader::Server(value),
+            "accept-ranges" => ResponseHeader::AcceptRanges(value),
+            "content-type" => ResponseHeader::ContentType(value),
+            "content-length" if value.parse::<usize>().is_ok() => ResponseHeader::ContentLength(value.parse().unwrap()),
+            "date" => ResponseHeader::Date(value),
+            "retry-after" if value.parse::<u64>().is_ok() => ResponseHeader::RetryAfter(value.parse().unwrap()),
+            "etag" => ResponseHeader::ETag(value),
+            "last-modified" => ResponseHeader::LastModified(value),
+            "content-range" => ResponseHeader::ContentRange(value),
+            "connection" => ResponseHeader::Connection(value),
+            "cache-control" => ResponseHeader::CacheControl(value),
+            "pragma" => ResponseHeader::Pragma(value),
+            "location" => ResponseHeader::Location(value),
+            "upgrade" => ResponseHeader::Upgrade(value),
+            "sec-websocket-accept" => ResponseHeader::SecWebSocketAccept(value),
+            _ => ResponseHeader::Custom(name.to_string(), value),
+        }
+    }
 }
 
 impl fmt::Display for ResponseHeader {
@@ -217,138 +1081,846 @@ impl fmt::Display for ResponseHeader {
             ResponseHeader::ContentType(ref value) => format!("Content-Type: {}", value),
             ResponseHeader::ContentLength(ref value) => format!("Content-Length: {}", value),
             ResponseHeader::Date(ref value) => format!("Date: {}", value),
+            ResponseHeader::RetryAfter(ref value) => format!("Retry-After: {}", value),
+            ResponseHeader::ETag(ref value) => format!("ETag: {}", value),
+            ResponseHeader::LastModified(ref value) => format!("Last-Modified: {}", value),
+            ResponseHeader::ContentRange(ref value) => format!("Content-Range: {}", value),
+            ResponseHeader::Connection(ref value) => format!("Connection: {}", value),
+            ResponseHeader::CacheControl(ref value) => format!("Cache-Control: {}", value),
+            ResponseHeader::Pragma(ref value) => format!("Pragma: {}", value),
+            ResponseHeader::Location(ref value) => format!("Location: {}", value),
+            ResponseHeader::ServerTiming(ref duration) =>
+                format!("Server-Timing: app;dur={:.1}", duration.as_secs_f64() * 1000.0),
+            ResponseHeader::ContentDisposition(ref file_name) if file_name.is_ascii() =>
+                format!("Content-Disposition: attachment; filename=\"{}\"", quote_file_name(file_name)),
+            ResponseHeader::ContentDisposition(ref file_name) => format!(
+                "Content-Disposition: attachment; filename=\"{}\"; filename*=UTF-8''{}",
+                quote_file_name(file_name), encode_ext_value(file_name)),
+            ResponseHeader::Upgrade(ref value) => format!("Upgrade: {}", value),
+            ResponseHeader::SecWebSocketAccept(ref value) => format!("Sec-WebSocket-Accept: {}", value),
+            ResponseHeader::KeepAlive(timeout, max) => format!("Keep-Alive: timeout={}, max={}", timeout, max),
+            ResponseHeader::Custom(ref name, ref value) => format!("{}: {}", name, value),
         };
         write!(f, "{}", printable)
     }
 }
 
+/// Escapes quotes and backslashes of a file name for a quoted string.
+///
+/// Control characters would break the header and non-ASCII characters are not allowed in it,
+/// so both are replaced by underscores.
+fn quote_file_name(file_name: &str) -> String {
+    let mut quoted = String::with_capacity(file_name.len());
+
+    for character in file_name.chars() {
+        match character {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(character);
+            },
+            _ if character.is_control() || !character.is_ascii() => quoted.push('_'),
+            _ => quoted.push(character),
+        }
+    }
+
+    quoted
+}
+
+/// Percent-encodes the UTF-8 bytes of a value for an extended parameter like `filename*`.
+///
+/// Only the characters allowed unencoded are kept, see [RFC 5987](https://tools.ietf.org/html/rfc5987#section-3.2.1).
+fn encode_ext_value(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+
+    for &byte in value.as_bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' |
+            b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
+
 /// This enum declares some [HTTP stats codes](https://en.wikipedia.org/wiki/List_of_HTTP_status_codes).
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Status {
+    // Informational 100 - 199:
+    /// The client should continue sending the body of the request.
+    Continue,
+    /// The server switches to the protocol the client asked for in the `Upgrade` header.
+    SwitchingProtocols,
     // Success 200 - 299:
     /// Standard response for successful HTTP requests.
     Ok,
+    /// The request has been fulfilled and a new resource has been created.
+    Created,
+    /// The server successfully processed the request and is not returning any content.
+    NoContent,
+    /// The server is delivering only part of the resource due to a range header sent by the client.
+    PartialContent,
+    // Redirection 300 - 399:
+    /// The resource has been moved to the URL given by the location header.
+    MovedPermanently,
+    /// The resource is temporarily available at the URL given by the location header.
+    Found,
+    /// The resource has not been modified since the version the client has cached.
+    NotModified,
+    /// The resource has been moved permanently and must be requested with the same method.
+    PermanentRedirect,
     // Client errors 400 - 499:
+    /// The server cannot process the request due to a client error.
+    BadRequest,
+    /// The client must authenticate to get the requested resource.
+    Unauthorized,
+    /// The client is not allowed to access the requested resource.
+    Forbidden,
     /// The requested resource could not be found.
     NotFound,
     /// A request method is not supported for the requested resource.
     MethodNotAllowed,
+    /// The resource is not available in a format the client accepts.
+    NotAcceptable,
+    /// The client did not send a complete request in time.
+    RequestTimeout,
+    /// The request body is larger than the server is willing to process.
+    PayloadTooLarge,
+    /// The request target is longer than the server is willing to interpret.
+    UriTooLong,
+    /// The request body is encoded in a format the server does not support.
+    UnsupportedMediaType,
+    /// The client has asked for a portion of the file, but the server cannot supply that portion.
+    RangeNotSatisfiable,
+    /// The server cannot meet the requirements of the `Expect` request header.
+    ExpectationFailed,
+    /// The client has sent too many requests in a given amount of time.
+    TooManyRequests,
+    /// The header fields of the request are larger than the server is willing to process.
+    RequestHeaderFieldsTooLarge,
+    // Server errors 500 - 599:
+    /// The server failed to handle the request due to an unexpected condition.
+    InternalServerError,
+    /// The server does not support the functionality required to fulfill the request.
+    NotImplemented,
+    /// The server got an invalid response from an upstream server while acting as a gateway.
+    BadGateway,
+    /// The server is currently unable to handle the request.
+    ServiceUnavailable,
+    /// The server does not support the HTTP version of the request.
+    HttpVersionNotSupported,
+    /// Any other status given by code and reason phrase, e.g. relayed from an upstream server.
+    Custom(u16, String),
+}
+
+impl Status {
+    /// Creates the status for a numeric status code, `None` if it is not declared.
+    pub fn from_code(code: u16) -> Option<Status> {
+        match code {
+            100 => Some(Status::Continue),
+            101 => Some(Status::SwitchingProtocols),
+            200 => Some(Status::Ok),
+            201 => Some(Status::Created),
+            204 => Some(Status::NoContent),
+            206 => Some(Status::PartialContent),
+            301 => Some(Status::MovedPermanently),
+            302 => Some(Status::Found),
+            304 => Some(Status::NotModified),
+            308 => Some(Status::PermanentRedirect),
+            400 => Some(Status::BadRequest),
+            401 => Some(Status::Unauthorized),
+            403 => Some(Status::Forbidden),
+            404 => Some(Status::NotFound),
+            405 => Some(Status::MethodNotAllowed),
+            406 => Some(Status::NotAcceptable),
+            408 => Some(Status::RequestTimeout),
+            413 => Some(Status::PayloadTooLarge),
+            414 => Some(Status::UriTooLong),
+            415 => Some(Status::UnsupportedMediaType),
+            416 => Some(Status::RangeNotSatisfiable),
+            417 => Some(Status::ExpectationFailed),
+            429 => Some(Status::TooManyRequests),
+            431 => Some(Status::RequestHeaderFieldsTooLarge),
+            500 => Some(Status::InternalServerError),
+            501 => Some(Status::NotImplemented),
+            502 => Some(Status::BadGateway),
+            503 => Some(Status::ServiceUnavailable),
+            505 => Some(Status::HttpVersionNotSupported),
+            _ => None,
+        }
+    }
+
+    /// Get the numeric status code, e.g. `404`.
+    pub fn code(&self) -> u16 {
+        match *self {
+            Status::Continue => 100,
+            Status::SwitchingProtocols => 101,
+            Status::Ok => 200,
+            Status::Created => 201,
+            Status::NoContent => 204,
+            Status::PartialContent => 206,
+            Status::MovedPermanently => 301,
+            Status::Found => 302,
+            Status::NotModified => 304,
+            Status::PermanentRedirect => 308,
+            Status::BadRequest => 400,
+            Status::Unauthorized => 401,
+            Status::Forbidden => 403,
+            Status::NotFound => 404,
+            Status::MethodNotAllowed => 405,
+            Status::NotAcceptable => 406,
+            Status::RequestTimeout => 408,
+            Status::PayloadTooLarge => 413,
+            Status::UriTooLong => 414,
+            Status::UnsupportedMediaType => 415,
+            Status::RangeNotSatisfiable => 416,
+            Status::ExpectationFailed => 417,
+            Status::TooManyRequests => 429,
+            Status::RequestHeaderFieldsTooLarge => 431,
+            Status::InternalServerError => 500,
+            Status::NotImplemented => 501,
+            Status::BadGateway => 502,
+            Status::ServiceUnavailable => 503,
+            Status::HttpVersionNotSupported => 505,
+            Status::Custom(code, _) => code,
+        }
+    }
+
+    /// Whether a response with this status may have a body.
+    ///
+    /// Responses with an informational status (`1xx`), `204 No Content` or `304 Not Modified`
+    /// never have one, see [RFC 7230, section 3.3](https://tools.ietf.org/html/rfc7230#section-3.3).
+    pub fn allows_body(&self) -> bool {
+        let code = self.code();
+        code >= 200 && code != 204 && code != 304
+    }
+
+    /// Get the reason phrase, e.g. `NOT FOUND`.
+    pub fn reason(&self) -> &str {
+        match *self {
+            Status::Continue => "CONTINUE",
+            Status::SwitchingProtocols => "SWITCHING PROTOCOLS",
+            Status::Ok => "OK",
+            Status::Created => "CREATED",
+            Status::NoContent => "NO CONTENT",
+            Status::PartialContent => "PARTIAL CONTENT",
+            Status::MovedPermanently => "MOVED PERMANENTLY",
+            Status::Found => "FOUND",
+            Status::NotModified => "NOT MODIFIED",
+            Status::PermanentRedirect => "PERMANENT REDIRECT",
+            Status::BadRequest => "BAD REQUEST",
+            Status::Unauthorized => "UNAUTHORIZED",
+            Status::Forbidden => "FORBIDDEN",
+            Status::NotFound => "NOT FOUND",
+            Status::MethodNotAllowed => "METHOD NOT ALLOWED",
+            Status::NotAcceptable => "NOT ACCEPTABLE",
+            Status::RequestTimeout => "REQUEST TIMEOUT",
+            Status::PayloadTooLarge => "PAYLOAD TOO LARGE",
+            Status::UriTooLong => "URI TOO LONG",
+            Status::UnsupportedMediaType => "UNSUPPORTED MEDIA TYPE",
+            Status::RangeNotSatisfiable => "RANGE NOT SATISFIABLE",
+            Status::ExpectationFailed => "EXPECTATION FAILED",
+            Status::TooManyRequests => "TOO MANY REQUESTS",
+            Status::RequestHeaderFieldsTooLarge => "REQUEST HEADER FIELDS TOO LARGE",
+            Status::InternalServerError => "INTERNAL SERVER ERROR",
+            Status::NotImplemented => "NOT IMPLEMENTED",
+            Status::BadGateway => "BAD GATEWAY",
+            Status::ServiceUnavailable => "SERVICE UNAVAILABLE",
+            Status::HttpVersionNotSupported => "HTTP VERSION NOT SUPPORTED",
+            Status::Custom(_, ref reason) => reason,
+        }
+    }
+}
+
+impl TryFrom<u16> for Status {
+    type Error = &'static str;
+
+    /// Creates the declared status for the code, or a custom one without reason phrase.
+    ///
+    /// Fails for codes outside of 100 to 599.
+    fn try_from(code: u16) -> Result<Status, &'static str> {
+        if !(100..=599).contains(&code) {
+            return Err("Status code must be between 100 and 599!");
+        }
+
+        Ok(Status::from_code(code).unwrap_or_else(|| Status::Custom(code, String::new())))
+    }
 }
 
 impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.reason())
+    }
+}
+
+/// Result of evaluating a `Range` request header against a resource.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ByteRange {
+    /// The whole resource should be served.
+    Full,
+    /// Only the bytes from the first to the second position (both inclusive) should be served.
+    Partial(usize, usize),
+    /// None of the requested bytes is part of the resource.
+    Unsatisfiable,
+}
+
+/// Parses the value of a `Range` header for a resource with the given length in bytes.
+///
+/// Only a single range of bytes is supported. Anything else is ignored and the whole
+/// resource should be served, as [RFC 7233](https://tools.ietf.org/html/rfc7233#section-3.1) allows.
+pub fn parse_range(value: &str, length: usize) -> ByteRange {
+    let value = value.trim();
+
+    if !value.starts_with("bytes=") {
+        return ByteRange::Full;
+    }
+
+    let spec = value[6..].trim();
+
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+
+    let dash_position = match spec.find('-') {
+        Some(position) => position,
+        None => return ByteRange::Full,
+    };
+    let first = spec[..dash_position].trim();
+    let last = spec[dash_position + 1..].trim();
+
+    if first.is_empty() {
+        // Suffix range: the last N bytes.
+        return match last.parse::<usize>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if length == 0 => ByteRange::Unsatisfiable,
+            Ok(suffix) => ByteRange::Partial(length - suffix.min(length), length - 1),
+            Err(_) => ByteRange::Full,
+        };
+    }
+
+    let first = match first.parse::<usize>() {
+        Ok(first) => first,
+        Err(_) => return ByteRange::Full,
+    };
+    let last = if last.is_empty() {
+        usize::MAX
+    } else {
+        match last.parse::<usize>() {
+            Ok(last) if last >= first => last,
+            _ => return ByteRange::Full,
+        }
+    };
+
+    if first >= length {
+        return ByteRange::Unsatisfiable;
+    }
+
+    ByteRange::Partial(first, last.min(length - 1))
+}
+
+/// Parses a query string like `a=1&b=hello+world` into its key/value pairs.
+///
+/// Keys and values are percent-decoded and `+` is decoded as space. A key without `=` gets an
+/// empty value. If a key is repeated the last value wins.
+pub fn parse_query(query: &str) -> HashMap<String, String> {
+    query.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.find('=') {
+            Some(position) => (decode_query_component(&pair[..position]), decode_query_component(&pair[position + 1..])),
+            None => (decode_query_component(pair), String::new()),
+        })
+        .collect()
+}
+
+/// Decodes `+` as space and `%XX` as the byte it encodes. Malformed escapes are kept as they are.
+fn decode_query_component(component: &str) -> String {
+    String::from_utf8_lossy(&percent_decode(component, true)).into_owned()
+}
+
+/// Decodes `%XX` in a URL path as the byte it encodes, `+` stays as it is in a path.
+///
+/// Returns `None` if the decoded path is no valid UTF-8. Malformed escapes are kept as they are.
+pub fn decode_path(path: &str) -> Option<String> {
+    String::from_utf8(percent_decode(path, false)).ok()
+}
+
+/// Normalizes a URL path so that different spellings of the same path compare equal.
+///
+/// `%XX` is decoded, invalid UTF-8 replaced, empty and `.` segments are removed and `..` removes
+/// the segment before it. A trailing slash is kept, e.g. `//docs/./%61pi/../` becomes `/docs/`.
+pub fn normalize_path(path: &str) -> String {
+    let decoded = String::from_utf8_lossy(&percent_decode(path, false)).into_owned();
+    let mut segments: Vec<&str> = Vec::new();
+
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {},
+            ".." => {
+                segments.pop();
+            },
+            _ => segments.push(segment),
+        }
+    }
+
+    let mut normalized = format!("/{}", segments.join("/"));
+
+    if !segments.is_empty() && (decoded.ends_with('/') || decoded.ends_with("/.") || decoded.ends_with("/..")) {
+        normalized.push('/');
+    }
+
+    normalized
+}
+
+/// Decodes `%XX` as the byte it encodes and, if asked to, `+` as space.
+fn percent_decode(input: &str, plus_as_space: bool) -> Vec<u8> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let escaped = match bytes.get(index + 1..index + 3) {
+            Some(hex) if bytes[index] == b'%' && hex.iter().all(u8::is_ascii_hexdigit) =>
+                Some(hex_value(hex[0]) << 4 | hex_value(hex[1])),
+            _ => None,
+        };
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            },
+            None => {
+                decoded.push(if plus_as_space && bytes[index] == b'+' { b' ' } else { bytes[index] });
+                index += 1;
+            },
+        }
+    }
+
+    decoded
+}
+
+fn hex_value(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
+
+/// Maximum number of characters of the offending input kept in a [`ParseError`](struct.ParseError.html).
+const SNIPPET_LENGTH: usize = 32;
+
+/// Declares the reasons why a request can't be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    /// The request contains nothing but whitespace.
+    EmptyRequest,
+    /// The request line does not consist of method, target and version.
+    MalformedRequestLine,
+    /// The method in the request line is no token, e.g. it contains control characters.
+    InvalidMethod,
+    /// The version in the request line does not start with `HTTP/`.
+    MalformedVersion,
+    /// A header line has no colon separating name and value.
+    MissingColon,
+    /// A header line starts with whitespace to continue the previous header value.
+    ObsoleteLineFolding,
+    /// The value of the `Content-Length` header is not a non-negative number.
+    InvalidContentLength,
+    /// The request target has none of the forms allowed by HTTP.
+    InvalidRequestTarget,
+    /// A header name is empty or contains characters not allowed in a token. Only checked in strict mode.
+    InvalidHeaderName,
+    /// A header value contains control characters. Only checked in strict mode.
+    InvalidHeaderValue,
+    /// The status line of a response does not consist of version, status code and reason phrase.
+    MalformedStatusLine,
+    /// The request is not valid UTF-8.
+    InvalidUtf8,
+}
+
+impl fmt::Display for ParseErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let printable = match *self {
-            Status::Ok => "200 OK",
-            Status::NotFound => "404 NOT FOUND",
-            Status::MethodNotAllowed => "405 METHOD NOT ALLOWED",
+            ParseErrorKind::EmptyRequest => "Empty request",
+            ParseErrorKind::MalformedRequestLine => "Malformed request line",
+            ParseErrorKind::InvalidMethod => "Invalid method",
+            ParseErrorKind::MalformedVersion => "Malformed HTTP version",
+            ParseErrorKind::MissingColon => "No colon found in header",
+            ParseErrorKind::ObsoleteLineFolding => "Obsolete line folding in header is not supported",
+            ParseErrorKind::InvalidContentLength => "Invalid content length",
+            ParseErrorKind::InvalidRequestTarget => "Invalid request target",
+            ParseErrorKind::InvalidHeaderName => "Invalid header name",
+            ParseErrorKind::InvalidHeaderValue => "Control character in header value",
+            ParseErrorKind::MalformedStatusLine => "Malformed status line",
+            ParseErrorKind::InvalidUtf8 => "Invalid UTF-8",
         };
         write!(f, "{}", printable)
     }
 }
 
+/// Describes why and where parsing a request failed.
 #[derive(Debug, Clone, PartialEq)]
-enum RequestToken {
-    Method(String),
-    Url(String),
-    Version(String),
-    HeaderName(String),
-    HeaderValue(String),
+pub struct ParseError {
+    /// Reason of the error.
+    kind: ParseErrorKind,
+    /// Position of the offending input in bytes from the start of the request.
+    offset: usize,
+    /// Beginning of the offending input.
+    snippet: String,
+}
+
+impl ParseError {
+    fn new(kind: ParseErrorKind, offset: usize, input: &str) -> ParseError {
+        ParseError { kind, offset, snippet: input.chars().take(SNIPPET_LENGTH).collect() }
+    }
+
+    /// Get the reason of the error.
+    pub fn kind(&self) -> &ParseErrorKind {
+        &self.kind
+    }
+
+    /// Get the position of the offending input in bytes from the start of the request.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Get the beginning of the offending input.
+    pub fn snippet(&self) -> &String {
+        &self.snippet
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}: {:?}", self.kind, self.offset, self.snippet)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum RequestToken<'a> {
+    Method(&'a str),
+    Url(&'a str),
+    Version(&'a str),
+    HeaderName(&'a str),
+    HeaderValue(&'a str),
+    /// The blank line after the headers. Anything following it belongs to the body.
+    EndOfHeaders,
     EndOfText,
 }
 
 /// Parses a HTTP request from string into a request object.
-pub fn parse_request(request: &str) -> Request {
-    if request.is_empty() {
-        panic!("Empty request input!");
+///
+/// Returns an error describing the problem and its location if the request is malformed.
+pub fn parse_request(request: &str) -> Result<Request, ParseError> {
+    parse_request_ref(request).map(|request| request.to_owned())
+}
+
+/// Checks that the request read from the socket is valid UTF-8.
+///
+/// Returns an error locating the first invalid byte otherwise.
+pub fn validate_utf8(request: &[u8]) -> Result<(), ParseError> {
+    match std::str::from_utf8(request) {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            let offset = err.valid_up_to();
+            Err(ParseError::new(ParseErrorKind::InvalidUtf8, offset, &String::from_utf8_lossy(&request[offset..])))
+        },
+    }
+}
+
+/// Parses a HTTP request like [`parse_request`](fn.parse_request.html), but rejects headers
+/// which are tolerated otherwise.
+///
+/// Header names must be [tokens](https://tools.ietf.org/html/rfc7230#section-3.2.6) directly
+/// followed by the colon and header values must not contain control characters other than tabs.
+pub fn parse_request_strict(request: &str) -> Result<Request, ParseError> {
+    let parsed = parse_request(request)?;
+    check_header_lines(request)?;
+    Ok(parsed)
+}
+
+/// Parses a HTTP request from string into a request object borrowing from the string.
+///
+/// Returns an error describing the problem and its location if the request is malformed.
+pub fn parse_request_ref(request: &str) -> Result<RequestRef<'_>, ParseError> {
+    if request.trim().is_empty() {
+        return Err(ParseError::new(ParseErrorKind::EmptyRequest, 0, request));
     }
 
     let mut builder = RequestBuilder::new();
-    let tokens = scan_request(request);
-    let mut tokens_iterator = tokens.iter();
+    let tokens = scan_request(request)?;
+    let mut tokens_iterator = tokens.into_iter();
 
     loop {
         let token = tokens_iterator.next()
             .expect("No more tokens_iterator, but expected more!");
 
         match token {
-            &RequestToken::Method(ref method) => builder.with_method(&method),
-            &RequestToken::Url(ref url) => builder.with_url(&url),
-            &RequestToken::Version(ref version) => builder.with_version(&version),
-            &RequestToken::HeaderName(ref name) => {
+            RequestToken::Method(method) => builder.with_method(method),
+            RequestToken::Url(url) => builder.with_url(url),
+            RequestToken::Version(version) => builder.with_version(version),
+            RequestToken::HeaderName(name) => {
                 let value_token = tokens_iterator.next()
-                    .expect(format!("Expecting a value for header '{}'!", &name).as_str());
-
-                if let &RequestToken::HeaderValue(ref value) = value_token {
-                    match name.as_str() {
-                        "Host" => builder.with_host(&value.clone()),
-                        "User-Agent" => builder.with_user_agent(&value.clone()),
-                        "Accept" => builder.with_accept(&value.clone()),
-                        "Accept-Language" => builder.with_accept_language(&value.clone()),
-                        "Accept-Encoding" => builder.with_accept_encoding(&value.clone()),
-                        "Cookie" => builder.with_cookie(&value.clone()),
-                        "Connection" => builder.with_connection(&value.clone()),
-                        "Upgrade-Insecure-Requests" => builder.with_upgrade_insecure_requests(&value.clone()),
-                        "Referer" => builder.with_referer(&value.clone()),
-                        "Cache-Control" => builder.with_cache_control(&value.clone()),
+                    .unwrap_or_else(|| panic!("Expecting a value for header '{}'!", &name));
+
+                if let RequestToken::HeaderValue(value) = value_token {
+                    builder.with_header(name, value);
+
+                    // Header field names are case-insensitive.
+                    match name.to_ascii_lowercase().as_str() {
+                        "host" => builder.with_host(value),
+                        "user-agent" => builder.with_user_agent(value),
+                        "accept" => builder.with_accept(value),
+                        "accept-language" => builder.with_accept_language(value),
+                        "accept-encoding" => builder.with_accept_encoding(value),
+                        "cookie" => builder.with_cookie(value),
+                        "connection" => builder.with_connection(value),
+                        "upgrade" => builder.with_upgrade(value),
+                        "upgrade-insecure-requests" => builder.with_upgrade_insecure_requests(value),
+                        "referer" => builder.with_referer(value),
+                        "cache-control" => builder.with_cache_control(value),
+                        "expect" => builder.with_expect(value),
+                        "content-length" => builder.with_content_length(value),
+                        "range" => builder.with_range(value),
+                        "if-range" => builder.with_if_range(value),
+                        "content-encoding" => builder.with_content_encoding(value),
+                        "content-type" => builder.with_content_type(value),
                         _ => debug!("Unexpected header name '{}'!", name),
                     }
                 }
             },
-            &RequestToken::EndOfText => break,
+            // The body is read from the stream separately.
+            RequestToken::EndOfHeaders | RequestToken::EndOfText => break,
             _ => panic!("Should not happen!"),
         }
     }
 
-    builder.create()
+    Ok(builder.create())
+}
+
+/// Parses a HTTP response from string into a response object, e.g. to check responses of a server.
+///
+/// Everything after the blank line ending the headers is the body. Returns an error describing
+/// the problem and its location if the response is malformed.
+pub fn parse_response(response: &str) -> Result<Response, ParseError> {
+    if response.trim().is_empty() {
+        return Err(ParseError::new(ParseErrorKind::EmptyRequest, 0, response));
+    }
+
+    let mut offset = response.len() - response.trim_start().len();
+    let mut lines = split_lines(response);
+    let status_line = lines.next().unwrap_or("");
+    let (version, status) = parse_status_line(status_line, offset)?;
+    let mut parsed = Response::new(version.to_string(), status, Vec::new());
+    offset += status_line.len() + "\r\n".len();
+
+    for line in lines {
+        if line.is_empty() {
+            // The body starts after the line break of the blank line.
+            parsed.body = response.as_bytes()[(offset + "\r\n".len()).min(response.len())..].to_vec();
+            break;
+        }
+
+        if let (RequestToken::HeaderName(name), RequestToken::HeaderValue(value)) = parse_non_first_line(line, offset)? {
+            parsed.add_header(ResponseHeader::from_name_value(name, value));
+        }
+
+        offset += line.len() + "\r\n".len();
+    }
+
+    Ok(parsed)
+}
+
+/// Parses the status line of a response starting at the given offset.
+fn parse_status_line(line: &str, offset: usize) -> Result<(&str, Status), ParseError> {
+    let mut parts = line.splitn(3, ' ');
+    let full_version = parts.next().unwrap_or("");
+    let code = parts.next().unwrap_or("");
+    let reason = parts.next().unwrap_or("");
+
+    if !full_version.starts_with("HTTP/") {
+        return Err(ParseError::new(ParseErrorKind::MalformedVersion, offset, full_version));
+    }
+
+    let status = match code.parse::<u16>().map(Status::try_from) {
+        // Relays the reason phrase of codes which are not declared.
+        Ok(Ok(Status::Custom(code, _))) => Status::Custom(code, reason.to_string()),
+        Ok(Ok(status)) => status,
+        _ => return Err(ParseError::new(ParseErrorKind::MalformedStatusLine, offset, line)),
+    };
+
+    Ok((&full_version[5..], status))
 }
 
-fn scan_request(request: &str) -> Vec<RequestToken> {
+fn scan_request(request: &str) -> Result<Vec<RequestToken<'_>>, ParseError> {
     let lines = split_lines(request);
     let mut tokens: Vec<RequestToken> = Vec::new();
     let mut is_first_line = true;
+    // The lines are split from the trimmed input, so the first one starts after the leading whitespace.
+    let mut offset = request.len() - request.trim_start().len();
 
     for line in lines {
+        if line.is_empty() && !is_first_line {
+            tokens.push(RequestToken::EndOfHeaders);
+            break;
+        }
+
         if is_first_line {
-            let (method, uri, version) = parse_first_line(line);
+            let (method, uri, version) = parse_first_line(line, offset)?;
             tokens.push(method);
             tokens.push(uri);
             tokens.push(version);
             is_first_line = false;
         } else {
-            let (name, value) = parse_non_first_line(line);
+            let (name, value) = parse_non_first_line(line, offset)?;
             tokens.push(name);
             tokens.push(value);
         }
+
+        offset += line.len() + "\r\n".len();
     };
 
     tokens.push(RequestToken::EndOfText);
-    tokens
+    Ok(tokens)
 }
 
-fn split_lines(input: &str) -> Vec<&str> {
-    input.trim().split("\r\n").collect::<Vec<&str>>()
+/// Lazily splits the input without leading whitespace into lines.
+///
+/// The blank line ending the headers is yielded as an empty line.
+fn split_lines(input: &str) -> impl Iterator<Item = &str> {
+    input.trim_start().split_terminator("\r\n")
 }
 
-fn parse_first_line(line: &str) -> (RequestToken, RequestToken, RequestToken) {
+/// Parses the request line starting at the given offset of the request.
+fn parse_first_line(line: &str, offset: usize) -> Result<(RequestToken<'_>, RequestToken<'_>, RequestToken<'_>), ParseError> {
     let parts: Vec<&str> = line.split(" ").collect::<Vec<&str>>();
+
+    if parts.len() != 3 {
+        return Err(ParseError::new(ParseErrorKind::MalformedRequestLine, offset, line));
+    }
+
     let method = parts[0].trim();
     let url = parts[1].trim();
     let full_version = parts[2].trim();
+
+    if method.is_empty() || !parts[0].chars().all(is_token_char) {
+        return Err(ParseError::new(ParseErrorKind::InvalidMethod, offset, parts[0]));
+    }
+
+    if !full_version.starts_with("HTTP/") || !is_version_number(&full_version[5..]) {
+        let version_offset = offset + parts[0].len() + parts[1].len() + 2;
+        return Err(ParseError::new(ParseErrorKind::MalformedVersion, version_offset, parts[2]));
+    }
+
+    if RequestTarget::of(url).is_none() {
+        let url_offset = offset + parts[0].len() + 1;
+        return Err(ParseError::new(ParseErrorKind::InvalidRequestTarget, url_offset, parts[1]));
+    }
+
     let version = &full_version[5..];
 
-    (RequestToken::Method(method.to_string()),
-        RequestToken::Url(url.to_string()),
-        RequestToken::Version(version.to_string()))
+    Ok((RequestToken::Method(method),
+        RequestToken::Url(url),
+        RequestToken::Version(version)))
+}
+
+/// Whether the version is a major and a minor digit separated by a dot, e.g. `1.1`.
+fn is_version_number(version: &str) -> bool {
+    match version.as_bytes() {
+        [major, b'.', minor] => major.is_ascii_digit() && minor.is_ascii_digit(),
+        _ => false,
+    }
 }
 
-fn parse_non_first_line(line: &str) -> (RequestToken, RequestToken) {
-    let colon_position = line.find(":")
-        .expect("No colon found in line!");
+/// Parses a header line starting at the given offset of the request.
+fn parse_non_first_line(line: &str, offset: usize) -> Result<(RequestToken<'_>, RequestToken<'_>), ParseError> {
+    // Obsolete line folding (RFC 7230, section 3.2.4) continues a header value on a line
+    // starting with whitespace. We reject it instead of guessing where the value belongs.
+    if line.starts_with(" ") || line.starts_with("\t") {
+        return Err(ParseError::new(ParseErrorKind::ObsoleteLineFolding, offset, line));
+    }
+
+    let colon_position = match line.find(":") {
+        Some(colon_position) => colon_position,
+        None => return Err(ParseError::new(ParseErrorKind::MissingColon, offset, line)),
+    };
     let header_name = line[0..colon_position].trim();
-    let header_value = line[colon_position + 1..].trim();
+    let raw_value = &line[colon_position + 1..];
+    let header_value = trim_optional_whitespace(raw_value);
+
+    if header_name.eq_ignore_ascii_case("Content-Length") && header_value.parse::<usize>().is_err() {
+        let value_offset = offset + colon_position + 1 + raw_value.find(header_value).unwrap_or(0);
+        return Err(ParseError::new(ParseErrorKind::InvalidContentLength, value_offset, header_value));
+    }
 
-    (RequestToken::HeaderName(header_name.to_string()),
-        RequestToken::HeaderValue(header_value.to_string()))
+    Ok((RequestToken::HeaderName(header_name),
+        RequestToken::HeaderValue(header_value)))
+}
+
+/// Checks the header lines of a request for the strict mode.
+fn check_header_lines(request: &str) -> Result<(), ParseError> {
+    let mut offset = request.len() - request.trim_start().len();
+
+    for (index, line) in split_lines(request).enumerate() {
+        if index > 0 {
+            if line.is_empty() {
+                break;
+            }
+
+            if let Some(colon_position) = line.find(':') {
+                let name = &line[..colon_position];
+                let value = &line[colon_position + 1..];
+
+                if name.is_empty() || !name.chars().all(is_token_char) {
+                    return Err(ParseError::new(ParseErrorKind::InvalidHeaderName, offset, name));
+                }
+
+                if value.chars().any(|c| c.is_control() && c != '\t') {
+                    return Err(ParseError::new(ParseErrorKind::InvalidHeaderValue, offset + colon_position + 1, value));
+                }
+            }
+        }
+
+        offset += line.len() + "\r\n".len();
+    }
+
+    Ok(())
+}
+
+/// Checks if the character may be part of a token, e.g. a method or a header name.
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
+
+/// Parses an item like `gzip;q=0.5` of a header value with quality values.
+///
+/// Returns `None` if the item is empty or its quality value is not between 0 and 1.
+fn parse_quality_item(item: &str) -> Option<(String, f32)> {
+    let mut parameters = item.split(';');
+    let name = trim_optional_whitespace(parameters.next().unwrap_or(""));
+
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut quality = 1.0;
+
+    for parameter in parameters {
+        let (key, value) = parameter.split_once('=')?;
+
+        if trim_optional_whitespace(key).eq_ignore_ascii_case("q") {
+            quality = trim_optional_whitespace(value).parse::<f32>().ok()
+                .filter(|quality| (0.0..=1.0).contains(quality))?;
+        }
+    }
+
+    Some((name.to_ascii_lowercase(), quality))
+}
+
+/// Trims optional whitespace (spaces and horizontal tabs) around a header value.
+fn trim_optional_whitespace(value: &str) -> &str {
+    value.trim_matches(|c| c == ' ' || c == '\t')
 }
 
 #[cfg(test)]
@@ -361,7 +1933,7 @@ mod tests {
         let request_fixture = "GET /foo HTTP/1.1\r\nHost: localhost:8080\r\nUser-Agent: curl/7.54.0\r\nAccept: */*\r\n";
 
         assert_that!(
-            split_lines(request_fixture),
+            split_lines(request_fixture).collect::<Vec<&str>>(),
             is(equal_to(
                 vec!(
                     "GET /foo HTTP/1.1",
@@ -372,32 +1944,227 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_split_lines_yields_blank_line_ending_headers() {
+        let request_fixture = "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nConnection: keep-alive\r\n\r\nHello";
+
+        assert_that!(
+            split_lines(request_fixture).collect::<Vec<&str>>(),
+            is(equal_to(vec!("GET /hello.html HTTP/1.1", "Host: localhost:8080", "Connection: keep-alive", "", "Hello"))));
+    }
+
     #[test]
     fn test_parse_first_line() {
         let first_line_fixture = "GET /foo HTTP/1.1";
 
         assert_that!(
-            parse_first_line(first_line_fixture),
-            is(equal_to(
+            parse_first_line(first_line_fixture, 0),
+            is(equal_to(Ok(
                 (
-                    RequestToken::Method(String::from("GET")),
-                    RequestToken::Url(String::from("/foo")),
-                    RequestToken::Version(String::from("1.1"))
+                    RequestToken::Method("GET"),
+                    RequestToken::Url("/foo"),
+                    RequestToken::Version("1.1")
                 )
-            ))
+            )))
         )
     }
 
+    #[test]
+    fn test_parse_first_line_accepts_custom_method_tokens() {
+        assert_that!(
+            parse_first_line("PURGE /foo HTTP/1.1", 0).unwrap().0,
+            is(equal_to(RequestToken::Method("PURGE"))));
+        assert_that!(
+            parse_first_line("X-MY_METHOD! /foo HTTP/1.1", 0).unwrap().0,
+            is(equal_to(RequestToken::Method("X-MY_METHOD!"))));
+    }
+
+    #[test]
+    fn test_parse_first_line_rejects_methods_which_are_no_tokens() {
+        let error = parse_first_line("G\u{1}T /foo HTTP/1.1", 0).unwrap_err();
+        assert_that!(error.kind(), is(equal_to(&ParseErrorKind::InvalidMethod)));
+        assert_that!(error.offset(), is(equal_to(0)));
+        assert_that!(error.snippet().as_str(), is(equal_to("G\u{1}T")));
+        assert_that!(
+            parse_first_line("G@T /foo HTTP/1.1", 0).unwrap_err().kind(),
+            is(equal_to(&ParseErrorKind::InvalidMethod)));
+    }
+
+    #[test]
+    fn test_parse_first_line_rejects_malformed_lines() {
+        assert_that!(
+            parse_first_line("GET /foo", 0).unwrap_err().kind(),
+            is(equal_to(&ParseErrorKind::MalformedRequestLine)));
+        let error = parse_first_line("GET /foo FTP/1.1", 0).unwrap_err();
+        assert_that!(error.kind(), is(equal_to(&ParseErrorKind::MalformedVersion)));
+        assert_that!(error.offset(), is(equal_to(9)));
+        assert_that!(error.snippet().as_str(), is(equal_to("FTP/1.1")));
+
+        for version in &["HTTP", "HTTP/", "HTTP/1", "HTTP/1.", "HTTP/a.b", "HTTP/1.10", "HTTP/11"] {
+            assert_that!(
+                parse_first_line(&format!("GET /foo {}", version), 0).unwrap_err().kind(),
+                is(equal_to(&ParseErrorKind::MalformedVersion)));
+        }
+    }
+
+    #[test]
+    fn test_parse_first_line_rejects_invalid_request_target() {
+        let error = parse_first_line("GET foo HTTP/1.1", 0).unwrap_err();
+        assert_that!(error.kind(), is(equal_to(&ParseErrorKind::InvalidRequestTarget)));
+        assert_that!(error.offset(), is(equal_to(4)));
+        assert_that!(error.snippet().as_str(), is(equal_to("foo")));
+    }
+
+    #[test]
+    fn test_parse_request_strict_rejects_malformed_headers() {
+        let lenient = "GET /foo HTTP/1.1\r\nHost: localhost:8080\r\nX Forwarded : 1\r\nX-Note: a\x01b\r\n";
+        assert_that!(parse_request(lenient).is_ok(), is(true));
+
+        let error = parse_request_strict("GET /foo HTTP/1.1\r\nHost: localhost:8080\r\nX Forwarded : 1\r\n").unwrap_err();
+        assert_that!(error.kind(), is(equal_to(&ParseErrorKind::InvalidHeaderName)));
+        assert_that!(error.offset(), is(equal_to(41)));
+
+        let error = parse_request_strict("GET /foo HTTP/1.1\r\nX-Note: a\x01b\r\n").unwrap_err();
+        assert_that!(error.kind(), is(equal_to(&ParseErrorKind::InvalidHeaderValue)));
+        assert_that!(error.offset(), is(equal_to(26)));
+
+        let valid = "GET /foo HTTP/1.1\r\nHost: localhost:8080\r\nX-Note:\ttabbed\r\n\r\nbody\x01";
+        assert_that!(parse_request_strict(valid), is(equal_to(parse_request(valid))));
+    }
+
+    fn origin(path: &str, query: Option<&str>) -> RequestTarget {
+        RequestTarget::Origin { path: path.to_string(), query: query.map(str::to_string) }
+    }
+
+    #[test]
+    fn test_request_target_of_origin_form() {
+        let target = RequestTarget::of("/index.html?lang=en").unwrap();
+
+        assert_that!(target.clone(), is(equal_to(origin("/index.html", Some("lang=en")))));
+        assert_that!(target.form(), is(equal_to("origin-form")));
+        assert_that!(target.path(), is(equal_to(Some("/index.html"))));
+        assert_that!(target.query(), is(equal_to(Some("lang=en"))));
+        assert_that!(format!("{}", target).as_str(), is(equal_to("/index.html?lang=en")));
+        assert_that!(RequestTarget::of("/"), is(equal_to(Some(origin("/", None)))));
+    }
+
+    #[test]
+    fn test_request_target_of_absolute_form() {
+        let target = RequestTarget::of("HTTP://localhost:8080/index.html?lang=en#top").unwrap();
+        let uri = match target {
+            RequestTarget::Absolute(ref uri) => uri.clone(),
+            _ => panic!("Expected absolute-form, got {:?}!", target),
+        };
+
+        assert_that!(uri.scheme().as_str(), is(equal_to("http")));
+        assert_that!(uri.authority().as_str(), is(equal_to("localhost:8080")));
+        assert_that!(uri.path().as_str(), is(equal_to("/index.html")));
+        assert_that!(uri.query().map(String::as_str), is(equal_to(Some("lang=en"))));
+        assert_that!(target.form(), is(equal_to("absolute-form")));
+        assert_that!(format!("{}", target).as_str(), is(equal_to("http://localhost:8080/index.html?lang=en")));
+        assert_that!(RequestTarget::of("http://localhost").unwrap().path(), is(equal_to(Some(""))));
+        assert_that!(RequestTarget::of("1http://localhost/"), is(equal_to(None)));
+    }
+
+    #[test]
+    fn test_request_target_of_authority_form() {
+        assert_that!(
+            RequestTarget::of("localhost:443"),
+            is(equal_to(Some(RequestTarget::Authority(String::from("localhost:443"))))));
+        assert_that!(
+            RequestTarget::of("[::1]:443"),
+            is(equal_to(Some(RequestTarget::Authority(String::from("[::1]:443"))))));
+        assert_that!(RequestTarget::of("localhost:443").unwrap().path(), is(equal_to(None)));
+        assert_that!(RequestTarget::of("localhost:https"), is(equal_to(None)));
+        assert_that!(RequestTarget::of("index.html"), is(equal_to(None)));
+    }
+
+    #[test]
+    fn test_request_target_of_asterisk_form() {
+        assert_that!(RequestTarget::of("*"), is(equal_to(Some(RequestTarget::Asterisk))));
+        assert_that!(RequestTarget::Asterisk.form(), is(equal_to("asterisk-form")));
+        assert_that!(RequestTarget::Asterisk.path(), is(equal_to(None)));
+        assert_that!(format!("{}", RequestTarget::Asterisk).as_str(), is(equal_to("*")));
+    }
+
+    #[test]
+    fn test_request_target_is_allowed_for() {
+        let absolute = RequestTarget::of("http://localhost/").unwrap();
+        let authority = RequestTarget::Authority(String::from("localhost:443"));
+
+        assert_that!(origin("/", None).is_allowed_for(&Method::Get), is(true));
+        assert_that!(absolute.is_allowed_for(&Method::Post), is(true));
+        assert_that!(origin("/", None).is_allowed_for(&Method::Connect), is(false));
+        assert_that!(authority.is_allowed_for(&Method::Connect), is(true));
+        assert_that!(authority.is_allowed_for(&Method::Get), is(false));
+        assert_that!(RequestTarget::Asterisk.is_allowed_for(&Method::Options), is(true));
+        assert_that!(RequestTarget::Asterisk.is_allowed_for(&Method::Head), is(false));
+    }
+
+    #[test]
+    fn test_request_method_is_ignores_case() {
+        let request = parse_request("get /foo HTTP/1.1\r\nHost: localhost:8080\r\n").unwrap();
+
+        assert_that!(request.method_is("GET"), is(true));
+        assert_that!(request.method_is("get"), is(true));
+        assert_that!(request.method_is("HEAD"), is(false));
+    }
+
+    #[test]
+    fn test_request_accepted_encodings_sorted_by_quality() {
+        fn accepted(accept_encoding: &str) -> Vec<(String, f32)> {
+            parse_request(&format!("GET / HTTP/1.1\r\nHost: localhost:8080\r\nAccept-Encoding: {}\r\n", accept_encoding))
+                .unwrap()
+                .accepted_encodings()
+        }
+
+        assert_that!(
+            accepted("gzip;q=0.5, br;q=1.0, deflate"),
+            is(equal_to(vec!((String::from("br"), 1.0), (String::from("deflate"), 1.0), (String::from("gzip"), 0.5)))));
+        assert_that!(
+            accepted("GZIP, identity;q=0"),
+            is(equal_to(vec!((String::from("gzip"), 1.0), (String::from("identity"), 0.0)))));
+        assert_that!(
+            accepted("gzip;q=2, br;q=x, , deflate ; Q=0.8"),
+            is(equal_to(vec!((String::from("deflate"), 0.8)))));
+        assert_that!(
+            parse_request("GET / HTTP/1.1\r\nHost: localhost:8080\r\n").unwrap().accepted_encodings(),
+            is(equal_to(Vec::new())));
+    }
+
+    #[test]
+    fn test_parse_request_exposes_target() {
+        let request = parse_request("GET http://localhost:8080/foo HTTP/1.1\r\nHost: localhost:8080\r\n").unwrap();
+        assert_that!(request.target().form(), is(equal_to("absolute-form")));
+        assert_that!(request.target().path(), is(equal_to(Some("/foo"))));
+
+        let request = parse_request("OPTIONS * HTTP/1.1\r\nHost: localhost:8080\r\n").unwrap();
+        assert_that!(request.target(), is(equal_to(&RequestTarget::Asterisk)));
+    }
+
+    #[test]
+    fn test_request_path_ignores_query_and_target_form() {
+        let request = parse_request("GET http://localhost:8080/foo?bar=1 HTTP/1.1\r\nHost: localhost:8080\r\n").unwrap();
+        assert_that!(request.path(), is(equal_to("/foo")));
+        assert_that!(request.path_and_query().as_str(), is(equal_to("/foo?bar=1")));
+
+        let request = parse_request("GET /foo HTTP/1.1\r\nHost: localhost:8080\r\n").unwrap();
+        assert_that!(request.path_and_query().as_str(), is(equal_to("/foo")));
+
+        let request = parse_request("OPTIONS * HTTP/1.1\r\nHost: localhost:8080\r\n").unwrap();
+        assert_that!(request.path(), is(equal_to("*")));
+    }
+
     #[test]
     fn test_parse_non_first_line_host_header() {
         let host_header_fixture = "Host: localhost:8080";
 
         assert_that!(
-            parse_non_first_line(host_header_fixture),
+            parse_non_first_line(host_header_fixture, 0).unwrap(),
             is(equal_to(
                 (
-                    RequestToken::HeaderName(String::from("Host")),
-                    RequestToken::HeaderValue(String::from("localhost:8080"))
+                    RequestToken::HeaderName("Host"),
+                    RequestToken::HeaderValue("localhost:8080")
                 )
             ))
         );
@@ -408,11 +2175,11 @@ mod tests {
         let user_agent_header_fixture = "User-Agent: curl/7.54.0";
 
         assert_that!(
-            parse_non_first_line(user_agent_header_fixture),
+            parse_non_first_line(user_agent_header_fixture, 0).unwrap(),
             is(equal_to(
                 (
-                    RequestToken::HeaderName(String::from("User-Agent")),
-                    RequestToken::HeaderValue(String::from("curl/7.54.0"))
+                    RequestToken::HeaderName("User-Agent"),
+                    RequestToken::HeaderValue("curl/7.54.0")
                 )
             ))
         );
@@ -423,49 +2190,136 @@ mod tests {
         let accept_header_fixture = "Accept: */*";
 
         assert_that!(
-            parse_non_first_line(accept_header_fixture),
+            parse_non_first_line(accept_header_fixture, 0).unwrap(),
+            is(equal_to(
+                (
+                    RequestToken::HeaderName("Accept"),
+                    RequestToken::HeaderValue("*/*")
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_non_first_line_trims_optional_whitespace() {
+        assert_that!(
+            parse_non_first_line("Accept:\t */* \t", 0).unwrap(),
             is(equal_to(
                 (
-                    RequestToken::HeaderName(String::from("Accept")),
-                    RequestToken::HeaderValue(String::from("*/*"))
+                    RequestToken::HeaderName("Accept"),
+                    RequestToken::HeaderValue("*/*")
                 )
             ))
         );
     }
 
+    #[test]
+    fn test_parse_non_first_line_rejects_obsolete_line_folding() {
+        assert_that!(
+            parse_non_first_line(" continued value", 0).unwrap_err().kind(),
+            is(equal_to(&ParseErrorKind::ObsoleteLineFolding)));
+        assert_that!(
+            parse_non_first_line("\tcontinued value", 0).unwrap_err().kind(),
+            is(equal_to(&ParseErrorKind::ObsoleteLineFolding)));
+    }
+
+    #[test]
+    fn test_parse_request_rejects_obsolete_line_folding() {
+        let request_fixture = "GET /foo HTTP/1.1\r\nHost: localhost:8080\r\nUser-Agent: curl/7.54.0\r\n  (folded)\r\nAccept: */*\r\n";
+
+        assert_that!(
+            parse_request(request_fixture),
+            is(equal_to(Err(ParseError {
+                kind: ParseErrorKind::ObsoleteLineFolding,
+                offset: 66,
+                snippet: String::from("  (folded)"),
+            }))));
+    }
+
+    #[test]
+    fn test_parse_request_reports_offset_of_header_without_colon() {
+        let request_fixture = "GET /foo HTTP/1.1\r\nHost: localhost:8080\r\nNo colon here\r\nAccept: */*\r\n";
+
+        let error = parse_request(request_fixture).unwrap_err();
+
+        assert_that!(error.kind(), is(equal_to(&ParseErrorKind::MissingColon)));
+        assert_that!(error.offset(), is(equal_to(41)));
+        assert_that!(&request_fixture[error.offset()..error.offset() + 8], is(equal_to("No colon")));
+        assert_that!(error.snippet().as_str(), is(equal_to("No colon here")));
+        assert_that!(
+            format!("{}", error).as_str(),
+            is(equal_to("No colon found in header at byte 41: \"No colon here\"")));
+    }
+
+    #[test]
+    fn test_parse_request_offsets_account_for_leading_whitespace() {
+        let error = parse_request("\r\n\r\nGET /foo HTTP/1.1\r\nbroken\r\n").unwrap_err();
+
+        assert_that!(error.offset(), is(equal_to(23)));
+    }
+
+    #[test]
+    fn test_parse_request_rejects_empty_request() {
+        assert_that!(
+            parse_request(" \r\n").unwrap_err().kind(),
+            is(equal_to(&ParseErrorKind::EmptyRequest)));
+    }
+
     #[test]
     fn test_scan_request() {
         let request_fixture = "GET /foo HTTP/1.1\r\nHost: localhost:8080\r\nUser-Agent: curl/7.54.0\r\nAccept: */*\r\n";
 
         assert_that!(
-            scan_request(request_fixture),
+            scan_request(request_fixture).unwrap(),
             is(equal_to(
                 vec!(
-                    RequestToken::Method(String::from("GET")),
-                    RequestToken::Url(String::from("/foo")),
-                    RequestToken::Version(String::from("1.1")),
-                    RequestToken::HeaderName(String::from("Host")),
-                    RequestToken::HeaderValue(String::from("localhost:8080")),
-                    RequestToken::HeaderName(String::from("User-Agent")),
-                    RequestToken::HeaderValue(String::from("curl/7.54.0")),
-                    RequestToken::HeaderName(String::from("Accept")),
-                    RequestToken::HeaderValue(String::from("*/*")),
+                    RequestToken::Method("GET"),
+                    RequestToken::Url("/foo"),
+                    RequestToken::Version("1.1"),
+                    RequestToken::HeaderName("Host"),
+                    RequestToken::HeaderValue("localhost:8080"),
+                    RequestToken::HeaderName("User-Agent"),
+                    RequestToken::HeaderValue("curl/7.54.0"),
+                    RequestToken::HeaderName("Accept"),
+                    RequestToken::HeaderValue("*/*"),
                     RequestToken::EndOfText
                 )
             ))
         )
     }
 
+    #[test]
+    fn test_scan_request_emits_end_of_headers_at_blank_line() {
+        let request_fixture = "POST /foo HTTP/1.1\r\nHost: localhost:8080\r\n\r\nname=value\r\n";
+
+        assert_that!(
+            scan_request(request_fixture).unwrap(),
+            is(equal_to(
+                vec!(
+                    RequestToken::Method("POST"),
+                    RequestToken::Url("/foo"),
+                    RequestToken::Version("1.1"),
+                    RequestToken::HeaderName("Host"),
+                    RequestToken::HeaderValue("localhost:8080"),
+                    RequestToken::EndOfHeaders,
+                    RequestToken::EndOfText
+                )
+            ))
+        );
+        assert_that!(parse_request(request_fixture).unwrap().host().as_str(), is(equal_to("localhost:8080")));
+    }
+
     #[test]
     fn test_parse_request() {
         let request_fixture = "GET /foo HTTP/1.1\r\nHost: localhost:8080\r\nUser-Agent: curl/7.54.0\r\nAccept: */*\r\n";
 
         assert_that!(
-            parse_request(request_fixture),
+            parse_request(request_fixture).unwrap(),
             is(equal_to(
                 Request {
                     method: String::from("GET"),
                     url: String::from("/foo"),
+                    target: RequestTarget::Origin { path: String::from("/foo"), query: None },
                     version: String::from("1.1"),
                     host: String::from("localhost:8080"),
                     user_agent: String::from("curl/7.54.0"),
@@ -475,23 +2329,79 @@ mod tests {
                     accept_encoding: String::from(""),
                     cookie: String::from(""),
                     connection: String::from(""),
+                    upgrade: String::from(""),
                     referer: String::from(""),
                     cache_control: String::from(""),
+                    expect: String::from(""),
+                    content_length: String::from(""),
+                    range: String::from(""),
+                    if_range: String::from(""),
+                    content_encoding: String::from(""),
+                    content_type: String::from(""),
+                    headers: vec!(
+                        (String::from("Host"), String::from("localhost:8080")),
+                        (String::from("User-Agent"), String::from("curl/7.54.0")),
+                        (String::from("Accept"), String::from("*/*"))),
+                    body: Vec::new(),
+                    raw_headers: Vec::new(),
                 }
             ))
         );
     }
 
+    #[test]
+    fn test_parse_request_upgrade_header() {
+        let request_fixture = "GET /chat HTTP/1.1\r\nHost: localhost:8080\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n";
+
+        let request = parse_request(request_fixture).unwrap();
+
+        assert_that!(request.upgrade().as_str(), is(equal_to("websocket")));
+    }
+
+    #[test]
+    fn test_request_builder_creates_borrowing_request() {
+        let input = String::from("GET /foo localhost:8080");
+        let mut builder = RequestBuilder::new();
+        builder.with_method(&input[0..3]);
+        builder.with_url(&input[4..8]);
+        builder.with_host(&input[9..]);
+
+        let request = builder.create();
+
+        assert_that!(request.method(), is(equal_to("GET")));
+        assert_that!(request.url(), is(equal_to("/foo")));
+        assert_that!(request.host, is(equal_to("localhost:8080")));
+    }
+
+    #[test]
+    fn test_parse_request_ref_borrows_fields_matching_owned_request() {
+        let request_fixture = "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nUser-Agent: curl/7.54.0\r\nAccept: */*\r\nConnection: keep-alive\r\nRange: bytes=0-4\r\n";
+
+        let borrowed = parse_request_ref(request_fixture).unwrap();
+        let owned = parse_request(request_fixture).unwrap();
+
+        assert_that!(borrowed.to_owned(), is(equal_to(owned.clone())));
+        assert_that!(borrowed.method(), is(equal_to(owned.method().as_str())));
+        assert_that!(borrowed.url(), is(equal_to(owned.url().as_str())));
+        assert_that!(borrowed.connection(), is(equal_to(owned.connection().as_str())));
+        assert_that!(borrowed.upgrade(), is(equal_to(owned.upgrade().as_str())));
+        assert_that!(borrowed.range(), is(equal_to(owned.range().as_str())));
+        // The fields point into the input instead of copies of it.
+        let input_range = request_fixture.as_bytes().as_ptr_range();
+        assert_that!(input_range.contains(&borrowed.host.as_ptr()), is(true));
+    }
+
     #[test]
     fn test_parse_request_firefox() {
         let request_fixture = "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nUser-Agent: Mozilla/5.0 (Macintosh; Intel Mac OS X 10.12; rv:58.0) Gecko/20100101 Firefox/58.0\r\nAccept: text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8\r\nAccept-Language: en,en-US;q=0.7,de;q=0.3\r\nAccept-Encoding: gzip, deflate\r\nReferer: http://localhost:8080/index.html\r\nCookie: JSESSIONID=node0ag061949mqugevd0gpoadofu2.node0;\r\nConnection: keep-alive\r\nUpgrade-Insecure-Requests: 1\r\nCache-Control: max-age=0\r\n\r\n";
 
         assert_that!(
-            parse_request(request_fixture),
+            parse_request(request_fixture).unwrap(),
             is(equal_to(
                 Request {
                     method: String::from("GET"),
                     url: String::from("/hello.html"),
+                    target: RequestTarget::Origin { path: String::from("/hello.html"), query: None },
                     version: String::from("1.1"),
                     host: String::from("localhost:8080"),
                     user_agent: String::from("Mozilla/5.0 (Macintosh; Intel Mac OS X 10.12; rv:58.0) Gecko/20100101 Firefox/58.0"),
@@ -501,15 +2411,218 @@ mod tests {
                     accept_encoding: String::from("gzip, deflate"),
                     cookie: String::from("JSESSIONID=node0ag061949mqugevd0gpoadofu2.node0;"),
                     connection: String::from("keep-alive"),
+                    upgrade: String::from(""),
                     referer: String::from("http://localhost:8080/index.html"),
                     cache_control: String::from("max-age=0"),
+                    expect: String::from(""),
+                    content_length: String::from(""),
+                    range: String::from(""),
+                    if_range: String::from(""),
+                    content_encoding: String::from(""),
+                    content_type: String::from(""),
+                    headers: vec!(
+                        (String::from("Host"), String::from("localhost:8080")),
+                        (String::from("User-Agent"), String::from("Mozilla/5.0 (Macintosh; Intel Mac OS X 10.12; rv:58.0) Gecko/20100101 Firefox/58.0")),
+                        (String::from("Accept"), String::from("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8")),
+                        (String::from("Accept-Language"), String::from("en,en-US;q=0.7,de;q=0.3")),
+                        (String::from("Accept-Encoding"), String::from("gzip, deflate")),
+                        (String::from("Referer"), String::from("http://localhost:8080/index.html")),
+                        (String::from("Cookie"), String::from("JSESSIONID=node0ag061949mqugevd0gpoadofu2.node0;")),
+                        (String::from("Connection"), String::from("keep-alive")),
+                        (String::from("Upgrade-Insecure-Requests"), String::from("1")),
+                        (String::from("Cache-Control"), String::from("max-age=0"))),
+                    body: Vec::new(),
+                    raw_headers: Vec::new(),
                 }
             ))
         );
     }
 
     #[test]
-    fn test_render_response_without_headers() {
+    fn test_header_lookup_is_case_insensitive() {
+        let request_fixture = "POST /form HTTP/1.1\r\nHost: localhost:8080\r\nContent-type: text/plain\r\nX-Custom: first\r\nx-custom: second\r\n";
+
+        let borrowed = parse_request_ref(request_fixture).unwrap();
+        let owned = parse_request(request_fixture).unwrap();
+
+        assert_that!(owned.header("content-type"), is(equal_to(Some("text/plain"))));
+        assert_that!(owned.header("Content-Type"), is(equal_to(Some("text/plain"))));
+        assert_that!(owned.content_type().as_str(), is(equal_to("text/plain")));
+        assert_that!(owned.header("X-CUSTOM"), is(equal_to(Some("first"))));
+        assert_that!(owned.header("Content-Length"), is(equal_to(None)));
+        assert_that!(borrowed.header("content-type"), is(equal_to(Some("text/plain"))));
+        assert_that!(borrowed.header("Content-Type"), is(equal_to(Some("text/plain"))));
+    }
+
+    #[test]
+    fn test_parse_request_with_expect_and_content_length() {
+        let request_fixture = "PUT /upload HTTP/1.1\r\nHost: localhost:8080\r\nContent-Length: 1024\r\nExpect: 100-continue\r\n";
+
+        let request = parse_request(request_fixture).unwrap();
+
+        assert_that!(request.expect().as_str(), is(equal_to("100-continue")));
+        assert_that!(request.content_length(), is(equal_to(Some(1024))));
+    }
+
+    #[test]
+    fn test_parse_request_without_content_length() {
+        let request = parse_request("GET /foo HTTP/1.1\r\nHost: localhost:8080\r\n").unwrap();
+
+        assert_that!(request.content_length(), is(equal_to(None)));
+    }
+
+    #[test]
+    fn test_parse_request_rejects_invalid_content_length() {
+        let error = parse_request("POST /foo HTTP/1.1\r\nContent-Length: abc\r\n").unwrap_err();
+
+        assert_that!(error.kind(), is(equal_to(&ParseErrorKind::InvalidContentLength)));
+        assert_that!(error.offset(), is(equal_to(36)));
+        assert_that!(error.snippet().as_str(), is(equal_to("abc")));
+
+        assert_that!(
+            parse_request("POST /foo HTTP/1.1\r\nContent-Length: -1\r\n").unwrap_err().kind(),
+            is(equal_to(&ParseErrorKind::InvalidContentLength)));
+        assert_that!(
+            parse_request("POST /foo HTTP/1.1\r\nContent-Length:\r\n").unwrap_err().kind(),
+            is(equal_to(&ParseErrorKind::InvalidContentLength)));
+    }
+
+    #[test]
+    fn test_parse_request_with_range_and_if_range() {
+        let request_fixture = "GET /foo HTTP/1.1\r\nHost: localhost:8080\r\nRange: bytes=0-99\r\nIf-Range: \"1f4-5a842f2c\"\r\n";
+
+        let request = parse_request(request_fixture).unwrap();
+
+        assert_that!(request.range().as_str(), is(equal_to("bytes=0-99")));
+        assert_that!(request.if_range().as_str(), is(equal_to("\"1f4-5a842f2c\"")));
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_that!(parse_range("bytes=0-99", 1000), is(equal_to(ByteRange::Partial(0, 99))));
+        assert_that!(parse_range("bytes=500-", 1000), is(equal_to(ByteRange::Partial(500, 999))));
+        assert_that!(parse_range("bytes=900-2000", 1000), is(equal_to(ByteRange::Partial(900, 999))));
+        assert_that!(parse_range("bytes=-100", 1000), is(equal_to(ByteRange::Partial(900, 999))));
+        assert_that!(parse_range("bytes=-2000", 1000), is(equal_to(ByteRange::Partial(0, 999))));
+        assert_that!(parse_range("bytes=1000-", 1000), is(equal_to(ByteRange::Unsatisfiable)));
+        assert_that!(parse_range("bytes=-0", 1000), is(equal_to(ByteRange::Unsatisfiable)));
+        assert_that!(parse_range("bytes=0-1,5-6", 1000), is(equal_to(ByteRange::Full)));
+        assert_that!(parse_range("bytes=5-1", 1000), is(equal_to(ByteRange::Full)));
+        assert_that!(parse_range("items=0-1", 1000), is(equal_to(ByteRange::Full)));
+        assert_that!(parse_range("bytes=foo", 1000), is(equal_to(ByteRange::Full)));
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query = parse_query("a=1&b=hello+world&c=%C3%A4%2B&flag&a=2&d=%zz&&");
+
+        assert_that!(query.len(), is(equal_to(5)));
+        assert_that!(query["a"].as_str(), is(equal_to("2")));
+        assert_that!(query["b"].as_str(), is(equal_to("hello world")));
+        assert_that!(query["c"].as_str(), is(equal_to("ä+")));
+        assert_that!(query["flag"].as_str(), is(equal_to("")));
+        assert_that!(query["d"].as_str(), is(equal_to("%zz")));
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_that!(normalize_path("/search").as_str(), is(equal_to("/search")));
+        assert_that!(normalize_path("/%73earch").as_str(), is(equal_to("/search")));
+        assert_that!(normalize_path("//search").as_str(), is(equal_to("/search")));
+        assert_that!(normalize_path("/./docs/../search/").as_str(), is(equal_to("/search/")));
+        assert_that!(normalize_path("/%2e%2E/search").as_str(), is(equal_to("/search")));
+        assert_that!(normalize_path("/docs/..").as_str(), is(equal_to("/")));
+        assert_that!(normalize_path("/").as_str(), is(equal_to("/")));
+        assert_that!(normalize_path("/%FF").as_str(), is(equal_to("/\u{FFFD}")));
+    }
+
+    #[test]
+    fn test_request_target_normalized_path() {
+        let path = |target: &str| RequestTarget::of(target).and_then(|target| target.normalized_path());
+
+        assert_that!(path("/%73earch?q=1"), is(equal_to(Some(String::from("/search")))));
+        assert_that!(path("http://localhost:8080//search"), is(equal_to(Some(String::from("/search")))));
+        assert_that!(path("*"), is(equal_to(None)));
+    }
+
+    #[test]
+    fn test_decode_path() {
+        assert_that!(decode_path("/100%20sure+safe.html"), is(equal_to(Some(String::from("/100 sure+safe.html")))));
+        assert_that!(decode_path("/%2e%2E/etc"), is(equal_to(Some(String::from("/../etc")))));
+        assert_that!(decode_path("/%C3%A4%zz%"), is(equal_to(Some(String::from("/ä%zz%")))));
+        assert_that!(decode_path("/%FF"), is(equal_to(None)));
+    }
+
+    #[test]
+    fn test_validate_utf8_locates_first_invalid_byte() {
+        assert_that!(validate_utf8("GET /ä HTTP/1.1\r\n".as_bytes()), is(equal_to(Ok(()))));
+
+        let err = validate_utf8(b"GET / HTTP/1.1\r\nX-Name: Ren\xe9\r\n").unwrap_err();
+        assert_that!(err.kind(), is(equal_to(&ParseErrorKind::InvalidUtf8)));
+        assert_that!(err.offset(), is(equal_to(27)));
+    }
+
+    #[test]
+    fn test_set_and_remove_headers() {
+        let mut request = parse_request(
+            "GET / HTTP/1.1\r\nHost: example.com\r\nX-Tag: a\r\nAccept: */*\r\nx-tag: b\r\n").unwrap();
+
+        request.set_header("X-Tag", "c");
+        request.set_header("Host", "localhost:3000");
+        request.set_header("X-New", "d");
+        assert_that!(
+            request.headers().clone(),
+            is(equal_to(vec!(
+                (String::from("Host"), String::from("localhost:3000")),
+                (String::from("X-Tag"), String::from("c")),
+                (String::from("Accept"), String::from("*/*")),
+                (String::from("X-New"), String::from("d"))))));
+        assert_that!(request.host().as_str(), is(equal_to("localhost:3000")));
+
+        request.remove_header("accept");
+        request.remove_header("X-Missing");
+        assert_that!(request.header("Accept"), is(equal_to(None)));
+        assert_that!(request.headers().len(), is(equal_to(3)));
+    }
+
+    #[test]
+    fn test_parse_form_from_post_body() {
+        let request_fixture = "POST /form HTTP/1.1\r\nHost: localhost:8080\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: 17\r\n";
+        let request = parse_request(request_fixture).unwrap()
+            .with_body(b"a=1&b=hello+world".to_vec());
+
+        let form = request.form().unwrap();
+
+        assert_that!(form.len(), is(equal_to(2)));
+        assert_that!(form["a"].as_str(), is(equal_to("1")));
+        assert_that!(form["b"].as_str(), is(equal_to("hello world")));
+    }
+
+    #[test]
+    fn test_parse_form_only_for_urlencoded_body() {
+        let request_fixture = "POST /form HTTP/1.1\r\nHost: localhost:8080\r\nContent-Type: text/plain\r\nContent-Length: 3\r\n";
+        let request = parse_request(request_fixture).unwrap()
+            .with_body(b"a=1".to_vec());
+
+        assert_that!(request.form(), is(equal_to(None)));
+    }
+
+    #[test]
+    fn method_from_token() {
+        assert_that!(Method::from_token("GET"), is(equal_to(Method::Get)));
+        assert_that!(Method::from_token("TRACE"), is(equal_to(Method::Trace)));
+        assert_that!(Method::from_token("trace"), is(equal_to(Method::Extension(String::from("trace")))));
+        assert_that!(Method::from_token("BREW"), is(equal_to(Method::Extension(String::from("BREW")))));
+    }
+
+    #[test]
+    fn method_fmt() {
+        assert_that!(format!("{}", Method::Trace).as_str(), is(equal_to("TRACE")));
+        assert_that!(format!("{}", Method::Extension(String::from("BREW"))).as_str(), is(equal_to("BREW")));
+    }
+
+    #[test]
+    fn test_render_response_without_headers_adds_content_length() {
         let sut = Response::new(
             String::from("1.1"),
             Status::Ok,
@@ -519,11 +2632,52 @@ mod tests {
         assert_that!(
             sut.render(),
             is(equal_to(
-                "HTTP/1.1 200 OK\r\n\r\nHello, World!".as_bytes().to_vec()
+                "HTTP/1.1 200 OK\r\nContent-Length: 13\r\n\r\nHello, World!".as_bytes().to_vec()
             ))
         );
     }
 
+    #[test]
+    fn test_render_response_without_body_for_not_modified_and_no_content() {
+        for status in [Status::NotModified, Status::NoContent].iter().cloned() {
+            let mut sut = Response::empty(String::from("1.1"), status);
+            sut.add_header(ResponseHeader::ContentLength(42));
+            sut.add_header(ResponseHeader::ETag(String::from("\"abc\"")));
+
+            let rendered = sut.render();
+            let rendered = String::from_utf8_lossy(&rendered);
+
+            assert_that!(rendered.contains("Content-Length"), is(false));
+            assert_that!(rendered.ends_with("ETag: \"abc\"\r\n\r\n"), is(true));
+        }
+    }
+
+    #[test]
+    fn test_render_no_content_without_content_length_and_body() {
+        let rendered = Response::no_content().render();
+
+        assert_that!(
+            String::from_utf8_lossy(&rendered).into_owned(),
+            is(equal_to(String::from("HTTP/1.1 204 NO CONTENT\r\n\r\n"))));
+    }
+
+    #[test]
+    fn test_render_drops_body_if_status_allows_none() {
+        let sut = Response::new(String::from("1.1"), Status::NotModified, b"stale".to_vec());
+
+        assert_that!(sut.render(), is(equal_to(b"HTTP/1.1 304 NOT MODIFIED\r\n\r\n".to_vec())));
+    }
+
+    #[test]
+    fn test_status_allows_body() {
+        assert_that!(Status::Continue.allows_body(), is(false));
+        assert_that!(Status::SwitchingProtocols.allows_body(), is(false));
+        assert_that!(Status::NoContent.allows_body(), is(false));
+        assert_that!(Status::NotModified.allows_body(), is(false));
+        assert_that!(Status::Ok.allows_body(), is(true));
+        assert_that!(Status::NotFound.allows_body(), is(true));
+    }
+
     #[test]
     fn test_render_response_with_headers() {
         let mut sut = Response::new(
@@ -537,22 +2691,235 @@ mod tests {
         assert_that!(
             sut.render(),
             is(equal_to(
-                "HTTP/1.1 405 METHOD NOT ALLOWED\r\nAllow: GET, POST, HEAD\r\n\r\nThis is not allowed!".as_bytes().to_vec()
+                "HTTP/1.1 405 METHOD NOT ALLOWED\r\nAllow: GET, POST, HEAD\r\nContent-Length: 20\r\n\r\nThis is not allowed!"
+                    .as_bytes().to_vec()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_clear_body_keeps_length_of_dropped_body() {
+        let mut sut = Response::new(String::from("1.1"), Status::NotFound, b"Not found!".to_vec());
+
+        sut.clear_body();
+
+        assert_that!(
+            String::from_utf8_lossy(&sut.render()).into_owned(),
+            is(equal_to(String::from("HTTP/1.1 404 NOT FOUND\r\nContent-Length: 10\r\n\r\n"))));
+    }
+
+    #[test]
+    fn test_render_response_preserves_header_order() {
+        let mut sut = Response::new(String::from("1.1"), Status::Ok, Vec::new());
+
+        sut.add_header(ResponseHeader::Custom(String::from("Set-Cookie"), String::from("a=1")));
+        sut.add_header(ResponseHeader::Custom(String::from("Vary"), String::from("Accept-Encoding")));
+        sut.add_header(ResponseHeader::Custom(String::from("Set-Cookie"), String::from("b=2")));
+
+        assert_that!(
+            sut.render(),
+            is(equal_to(
+                "HTTP/1.1 200 OK\r\nSet-Cookie: a=1\r\nVary: Accept-Encoding\r\nSet-Cookie: b=2\r\nContent-Length: 0\r\n\r\n"
+                    .as_bytes().to_vec()
             ))
         );
     }
 
+    #[test]
+    fn redirect_https_replaces_port_of_host() {
+        let location = |host: &str, port: u16| {
+            let rendered = Response::redirect_https(host, port, "/docs/?q=1").render();
+            String::from_utf8_lossy(&rendered).into_owned()
+        };
+
+        assert_that!(
+            location("example.com", 443),
+            is(equal_to(String::from("HTTP/1.1 308 PERMANENT REDIRECT\r\nContent-Length: 0\r\nLocation: https://example.com/docs/?q=1\r\n\r\n"))));
+        assert_that!(location("example.com:8080", 443).contains("\r\nLocation: https://example.com/docs/?q=1\r\n"), is(true));
+        assert_that!(location("example.com:8080", 8443).contains("\r\nLocation: https://example.com:8443/docs/?q=1\r\n"), is(true));
+        assert_that!(location("[::1]:8080", 443).contains("\r\nLocation: https://[::1]/docs/?q=1\r\n"), is(true));
+        assert_that!(location("[::1]", 8443).contains("\r\nLocation: https://[::1]:8443/docs/?q=1\r\n"), is(true));
+        assert_that!(location("::1", 443).contains("\r\nLocation: https://[::1]/docs/?q=1\r\n"), is(true));
+    }
+
+    #[test]
+    fn from_io_error_maps_error_kind_to_status() {
+        let status = |kind: io::ErrorKind| format!("{}", Response::from_io_error(&io::Error::from(kind)).status());
+
+        assert_that!(status(io::ErrorKind::NotFound).as_str(), is(equal_to("404 NOT FOUND")));
+        assert_that!(status(io::ErrorKind::PermissionDenied).as_str(), is(equal_to("403 FORBIDDEN")));
+        assert_that!(status(io::ErrorKind::InvalidData).as_str(), is(equal_to("500 INTERNAL SERVER ERROR")));
+        assert_that!(status(io::ErrorKind::Other).as_str(), is(equal_to("500 INTERNAL SERVER ERROR")));
+    }
+
+    #[test]
+    fn status_code_and_reason() {
+        assert_that!(Status::NotFound.code(), is(equal_to(404)));
+        assert_that!(Status::NotFound.reason(), is(equal_to("NOT FOUND")));
+        assert_that!(Status::SwitchingProtocols.code(), is(equal_to(101)));
+        assert_that!(Status::HttpVersionNotSupported.reason(), is(equal_to("HTTP VERSION NOT SUPPORTED")));
+    }
+
+    #[test]
+    fn status_from_code_matches_code() {
+        for code in 100..600 {
+            if let Some(status) = Status::from_code(code) {
+                assert_that!(status.code(), is(equal_to(code)));
+            }
+        }
+
+        assert_that!(Status::from_code(404).map(|status| status.code()), is(equal_to(Some(404))));
+        assert_that!(Status::from_code(299).is_none(), is(true));
+    }
+
+    #[test]
+    fn parse_response_with_headers_and_body() {
+        let response_fixture = "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 13\r\nX-Request-Id: 42\r\n\r\nHello,\r\nWorld!";
+
+        let response = parse_response(response_fixture).unwrap();
+
+        assert_that!(response.version().as_str(), is(equal_to("1.1")));
+        assert_that!(response.status().code(), is(equal_to(200)));
+        assert_that!(response.headers().len(), is(equal_to(3)));
+        assert_that!(response.body(), is(equal_to(&b"Hello,\r\nWorld!"[..])));
+        assert_that!(
+            String::from_utf8_lossy(&response.render()).into_owned(),
+            is(equal_to(String::from(response_fixture))));
+    }
+
+    #[test]
+    fn parse_response_without_body() {
+        let response = parse_response("HTTP/1.1 204 No Content\r\nServer: test\r\n").unwrap();
+
+        assert_that!(response.status().code(), is(equal_to(204)));
+        assert_that!(response.headers().len(), is(equal_to(1)));
+        assert_that!(response.body_length(), is(equal_to(0)));
+    }
+
+    #[test]
+    fn parse_response_rejects_malformed_status_lines() {
+        assert_that!(
+            parse_response("HTTP/1.1 abc OK\r\n").unwrap_err().kind(),
+            is(equal_to(&ParseErrorKind::MalformedStatusLine)));
+        assert_that!(
+            parse_response("FTP/1.1 200 OK\r\n").unwrap_err().kind(),
+            is(equal_to(&ParseErrorKind::MalformedVersion)));
+
+        assert_that!(
+            parse_response("HTTP/1.1 600 Too Far\r\n").unwrap_err().kind(),
+            is(equal_to(&ParseErrorKind::MalformedStatusLine)));
+
+        let response = parse_response("HTTP/1.1 299 Whatever\r\n").unwrap();
+        assert_that!(format!("{}", response.status()).as_str(), is(equal_to("299 Whatever")));
+    }
+
+    #[test]
+    fn status_try_from_code() {
+        assert_that!(format!("{}", Status::try_from(404).unwrap()).as_str(), is(equal_to("404 NOT FOUND")));
+        assert_that!(format!("{}", Status::try_from(299).unwrap()).as_str(), is(equal_to("299 ")));
+        assert_that!(Status::try_from(99).is_err(), is(true));
+        assert_that!(Status::try_from(600).unwrap_err(), is(equal_to("Status code must be between 100 and 599!")));
+    }
+
     #[test]
     fn status_fmt() {
         assert_that!(
             format!("{}", Status::Ok).as_str(),
             is(equal_to("200 OK")));
+        assert_that!(
+            format!("{}", Status::BadRequest).as_str(),
+            is(equal_to("400 BAD REQUEST")));
+        assert_that!(
+            format!("{}", Status::NoContent).as_str(),
+            is(equal_to("204 NO CONTENT")));
+        assert_that!(
+            format!("{}", Status::PartialContent).as_str(),
+            is(equal_to("206 PARTIAL CONTENT")));
+        assert_that!(
+            format!("{}", Status::MovedPermanently).as_str(),
+            is(equal_to("301 MOVED PERMANENTLY")));
+        assert_that!(
+            format!("{}", Status::PayloadTooLarge).as_str(),
+            is(equal_to("413 PAYLOAD TOO LARGE")));
+        assert_that!(
+            format!("{}", Status::UnsupportedMediaType).as_str(),
+            is(equal_to("415 UNSUPPORTED MEDIA TYPE")));
+        assert_that!(
+            format!("{}", Status::RangeNotSatisfiable).as_str(),
+            is(equal_to("416 RANGE NOT SATISFIABLE")));
         assert_that!(
             format!("{}", Status::NotFound).as_str(),
             is(equal_to("404 NOT FOUND")));
         assert_that!(
             format!("{}", Status::MethodNotAllowed).as_str(),
             is(equal_to("405 METHOD NOT ALLOWED")));
+        assert_that!(
+            format!("{}", Status::ExpectationFailed).as_str(),
+            is(equal_to("417 EXPECTATION FAILED")));
+        assert_that!(
+            format!("{}", Status::TooManyRequests).as_str(),
+            is(equal_to("429 TOO MANY REQUESTS")));
+        assert_that!(
+            format!("{}", Status::NotImplemented).as_str(),
+            is(equal_to("501 NOT IMPLEMENTED")));
+        assert_that!(
+            format!("{}", Status::Continue).as_str(),
+            is(equal_to("100 CONTINUE")));
+        assert_that!(
+            format!("{}", Status::SwitchingProtocols).as_str(),
+            is(equal_to("101 SWITCHING PROTOCOLS")));
+        assert_that!(
+            format!("{}", Status::Created).as_str(),
+            is(equal_to("201 CREATED")));
+        assert_that!(
+            format!("{}", Status::Found).as_str(),
+            is(equal_to("302 FOUND")));
+        assert_that!(
+            format!("{}", Status::NotModified).as_str(),
+            is(equal_to("304 NOT MODIFIED")));
+        assert_that!(
+            format!("{}", Status::Unauthorized).as_str(),
+            is(equal_to("401 UNAUTHORIZED")));
+        assert_that!(
+            format!("{}", Status::Forbidden).as_str(),
+            is(equal_to("403 FORBIDDEN")));
+        assert_that!(
+            format!("{}", Status::NotAcceptable).as_str(),
+            is(equal_to("406 NOT ACCEPTABLE")));
+        assert_that!(
+            format!("{}", Status::RequestTimeout).as_str(),
+            is(equal_to("408 REQUEST TIMEOUT")));
+        assert_that!(
+            format!("{}", Status::UriTooLong).as_str(),
+            is(equal_to("414 URI TOO LONG")));
+        assert_that!(
+            format!("{}", Status::RequestHeaderFieldsTooLarge).as_str(),
+            is(equal_to("431 REQUEST HEADER FIELDS TOO LARGE")));
+        assert_that!(
+            format!("{}", Status::InternalServerError).as_str(),
+            is(equal_to("500 INTERNAL SERVER ERROR")));
+        assert_that!(
+            format!("{}", Status::BadGateway).as_str(),
+            is(equal_to("502 BAD GATEWAY")));
+        assert_that!(
+            format!("{}", Status::HttpVersionNotSupported).as_str(),
+            is(equal_to("505 HTTP VERSION NOT SUPPORTED")));
+        assert_that!(
+            format!("{}", Status::ServiceUnavailable).as_str(),
+            is(equal_to("503 SERVICE UNAVAILABLE")));
+    }
+
+    #[test]
+    fn response_no_cache_forbids_caching() {
+        let mut sut = Response::new(String::from("1.1"), Status::Ok, Vec::new());
+
+        sut.no_cache();
+
+        assert_that!(
+            String::from_utf8_lossy(&sut.render()).into_owned(),
+            is(equal_to(String::from(
+                "HTTP/1.1 200 OK\r\nCache-Control: no-store, no-cache, must-revalidate\r\nPragma: no-cache\r\n\
+                 Content-Length: 0\r\n\r\n"))));
     }
 
     #[test]
@@ -560,5 +2927,92 @@ mod tests {
         assert_that!(
             format!("{}", ResponseHeader::Allow(String::from("GET, POST, HEAD"))).as_str(),
             is(equal_to("Allow: GET, POST, HEAD")));
+        assert_that!(
+            format!("{}", ResponseHeader::RetryAfter(120)).as_str(),
+            is(equal_to("Retry-After: 120")));
+        assert_that!(
+            format!("{}", ResponseHeader::KeepAlive(5, 100)).as_str(),
+            is(equal_to("Keep-Alive: timeout=5, max=100")));
+        assert_that!(
+            format!("{}", ResponseHeader::CacheControl(String::from("public, max-age=604800"))).as_str(),
+            is(equal_to("Cache-Control: public, max-age=604800")));
+        assert_that!(
+            format!("{}", ResponseHeader::ServerTiming(Duration::from_micros(12_345))).as_str(),
+            is(equal_to("Server-Timing: app;dur=12.3")));
+        assert_that!(
+            format!("{}", ResponseHeader::ContentDisposition(String::from("say \"hi\" \\ bye.zip"))).as_str(),
+            is(equal_to("Content-Disposition: attachment; filename=\"say \\\"hi\\\" \\\\ bye.zip\"")));
+        assert_that!(
+            format!("{}", ResponseHeader::ContentDisposition(String::from("Übersicht 2024.pdf"))).as_str(),
+            is(equal_to("Content-Disposition: attachment; filename=\"_bersicht 2024.pdf\"; filename*=UTF-8''%C3%9Cbersicht%202024.pdf")));
+    }
+
+    /// Pseudo random numbers with a fixed seed, so a failing input can be reproduced.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, bound: usize) -> usize {
+            (self.next() % bound as u64) as usize
+        }
+    }
+
+    /// A valid request with random mutations, so most inputs get past the first checks.
+    fn random_request(random: &mut XorShift) -> String {
+        static PIECES: [&'static str; 14] = [
+            " ", "/", "*", "%2F", "%", "?", ":", ";q=", ",", "=", "\r\n", "\r\n\r\n", "-", "HTTP/"];
+        let mut bytes = b"GET /a?b=c HTTP/1.1\r\nHost: a:1\r\nAccept-Encoding: gzip;q=0.5\r\n\
+                          Content-Type: application/x-www-form-urlencoded\r\nContent-Length: 3\r\n\
+                          Range: bytes=0-1\r\n\r\n".to_vec();
+
+        for _ in 0..random.below(4) {
+            let position = random.below(bytes.len() + 1);
+
+            match random.below(3) {
+                0 => {
+                    let piece = PIECES[random.below(PIECES.len())].as_bytes();
+                    bytes.splice(position..position, piece.iter().cloned());
+                },
+                1 => {
+                    let end = (position + random.below(8)).min(bytes.len());
+                    bytes.drain(position..end);
+                },
+                _ => bytes.insert(position, random.below(256) as u8),
+            }
+        }
+
+        // Like the server, map every byte to a char, so multi-byte chars show up as well.
+        bytes.iter().map(|&byte| byte as char).collect()
+    }
+
+    #[test]
+    fn test_parse_request_never_panics_on_random_input() {
+        let mut random = XorShift(0x2545_F491_4F6C_DD1D);
+
+        for _ in 0..20_000 {
+            let input = random_request(&mut random);
+
+            if let Ok(request) = parse_request(&input) {
+                request.target().path();
+                request.accepted_encodings();
+                request.content_length();
+                request.form();
+                parse_range(request.range(), random.below(100));
+            }
+
+            if let Err(err) = parse_request_strict(&input) {
+                assert_that!(format!("{}", err).is_empty(), is(false));
+            }
+
+            if let Ok(request) = parse_request_ref(&input) {
+                request.to_owned();
+            }
+        }
     }
-}
\ No newline at end of file
+}
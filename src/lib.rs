@@ -14,13 +14,33 @@ extern crate time;
 extern crate serde_derive;
 extern crate serde;
 extern crate toml;
+#[cfg(unix)]
+extern crate libc;
 
-use std::path::PathBuf;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 
 pub mod file;
 pub mod http;
 pub mod threads;
 pub mod server;
+pub mod connections;
+pub mod rate_limit;
+pub mod access_log;
+pub mod reload;
+pub mod assets;
+pub mod gzip;
+pub mod chunked;
+pub mod metrics;
+pub mod context;
+pub mod stream;
+pub mod websocket;
+pub mod proxy;
+pub mod middleware;
 
 /// Name of the application
 pub static APPLICATION_NAME: &'static str = "webserver";
@@ -29,6 +49,288 @@ pub static APPLICATION_DESCRIPTION: &'static str = "Weltraumschaf's Webserver";
 /// Version of the application.
 pub static APPLICATION_VERSION: &'static str = "1.0.0";
 
+/// Default address to listen on.
+pub static DEFAULT_ADDRESS: &'static str = "127.0.0.1";
+/// Default port to listen on.
+pub const DEFAULT_PORT: u16 = 8080;
+/// Default number of worker threads.
+pub const DEFAULT_THREADS: usize = 4;
+/// Default directory with the content to serve.
+pub static DEFAULT_WEB_DIR: &'static str = "web_dir";
+/// Default number of seconds to wait for the next request on a persistent connection.
+pub const DEFAULT_KEEP_ALIVE_TIMEOUT: u64 = 5;
+/// Default number of seconds to wait for the rest of a request once it started to arrive.
+pub const DEFAULT_READ_TIMEOUT: u64 = 30;
+/// Default level of messages to log.
+pub static DEFAULT_LOG_LEVEL: &'static str = "debug";
+/// Default directory to store log files in.
+pub static DEFAULT_LOG_DIR: &'static str = "log_files";
+/// Default maximum number of requests served on a persistent connection.
+pub const DEFAULT_KEEP_ALIVE_MAX_REQUESTS: usize = 100;
+/// Default names of header fields whose values are redacted in logs.
+pub static DEFAULT_REDACTED_HEADERS: [&'static str; 3] = ["Authorization", "Cookie", "Set-Cookie"];
+/// Default names of the files served for a directory, in the order they are looked up.
+pub static DEFAULT_INDEX_FILES: [&'static str; 2] = ["index.html", "index.htm"];
+/// Default maximum size of a request in bytes.
+pub const DEFAULT_MAX_REQUEST_BYTES: usize = 4096;
+/// Default maximum size of a decoded request body in bytes.
+pub const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+/// Whether to disable Nagle's algorithm on accepted TCP connections by default.
+pub const DEFAULT_TCP_NODELAY: bool = true;
+/// Default port of HTTPS redirects.
+pub const DEFAULT_HTTPS_PORT: u16 = 443;
+/// Default fraction of successful requests written to the access log.
+pub const DEFAULT_LOG_SAMPLE_RATE: f64 = 1.0;
+
+/// Maps a URL path prefix to a directory other than the web root directory.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct Mount {
+    /// URL path prefix, e.g. `/static`.
+    /// Must start with a slash.
+    prefix: String,
+    /// Directory with the content to serve for the prefix.
+    root: String,
+}
+
+impl Mount {
+    /// Creates a new mount.
+    pub fn new(prefix: String, root: String) -> Result<Mount, &'static str> {
+        Mount { prefix, root }.validate()
+    }
+
+    fn validate(self) -> Result<Mount, &'static str> {
+        if !self.prefix.starts_with('/') {
+            return Err("Mount value 'prefix' must start with a slash!");
+        }
+
+        if self.root.is_empty() {
+            return Err("Mount value 'root' must not be empty!");
+        }
+
+        Ok(self)
+    }
+
+    /// Get the URL path prefix.
+    pub fn prefix(&self) -> &String {
+        &self.prefix
+    }
+
+    /// Get the directory with the content to serve for the prefix.
+    pub fn root(&self) -> &String {
+        &self.root
+    }
+
+    /// Returns the rest of the URL if it is below the prefix.
+    ///
+    /// The prefix only matches whole path segments: `/static` matches `/static/main.css`
+    /// but not `/statical`.
+    pub fn strip_prefix<'a>(&self, url: &'a str) -> Option<&'a str> {
+        strip_path_prefix(&self.prefix, url)
+    }
+}
+
+/// Limits the request rate of each client IP to the URLs below a path prefix.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct RouteLimit {
+    /// URL path prefix, e.g. `/search`, matched against the percent-decoded and normalized request path.
+    /// Must start with a slash.
+    prefix: String,
+    /// Number of requests per second a single client IP may send to the URLs below the prefix.
+    /// Must not be zero or less.
+    per_second: u32,
+    /// Number of requests a single client IP may send at once to the URLs below the prefix.
+    /// Defaults to the requests per second if not set.
+    #[serde(default)]
+    burst: Option<u32>,
+}
+
+impl RouteLimit {
+    /// Creates a new route limit.
+    pub fn new(prefix: String, per_second: u32, burst: Option<u32>) -> Result<RouteLimit, &'static str> {
+        RouteLimit { prefix, per_second, burst }.validate()
+    }
+
+    fn validate(self) -> Result<RouteLimit, &'static str> {
+        if !self.prefix.starts_with('/') {
+            return Err("Route limit value 'prefix' must start with a slash!");
+        }
+
+        if self.per_second < 1 {
+            return Err("Route limit value 'per_second' must be grater than 0!");
+        }
+
+        if self.burst == Some(0) {
+            return Err("Route limit value 'burst' must be grater than 0!");
+        }
+
+        Ok(self)
+    }
+
+    /// Get the URL path prefix.
+    pub fn prefix(&self) -> &String {
+        &self.prefix
+    }
+
+    /// Get the number of requests per second.
+    pub fn per_second(&self) -> u32 {
+        self.per_second
+    }
+
+    /// Get the number of requests allowed at once.
+    pub fn burst(&self) -> u32 {
+        self.burst.unwrap_or(self.per_second)
+    }
+
+    /// Checks if the URL, ignoring its query, is below the prefix.
+    pub fn matches(&self, url: &str) -> bool {
+        let path = url.split('?').next().unwrap_or(url);
+        strip_path_prefix(&self.prefix, path).is_some()
+    }
+}
+
+/// Forwards the requests below a URL path prefix to an upstream HTTP server.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct Proxy {
+    /// URL path prefix, e.g. `/api`.
+    /// Must start with a slash.
+    prefix: String,
+    /// URL of the upstream server the prefix is replaced with, e.g. `http://127.0.0.1:3000/v1`.
+    /// Must use the `http` scheme.
+    upstream: String,
+}
+
+impl Proxy {
+    /// Creates a new proxy.
+    pub fn new(prefix: String, upstream: String) -> Result<Proxy, &'static str> {
+        Proxy { prefix, upstream }.validate()
+    }
+
+    fn validate(self) -> Result<Proxy, &'static str> {
+        if !self.prefix.starts_with('/') {
+            return Err("Proxy value 'prefix' must start with a slash!");
+        }
+
+        if !self.upstream.starts_with("http://") || self.upstream_authority().is_empty() {
+            return Err("Proxy value 'upstream' must be an http:// URL!");
+        }
+
+        Ok(self)
+    }
+
+    /// Get the URL path prefix.
+    pub fn prefix(&self) -> &String {
+        &self.prefix
+    }
+
+    /// Get the URL of the upstream server.
+    pub fn upstream(&self) -> &String {
+        &self.upstream
+    }
+
+    /// Get the host and optional port of the upstream server, e.g. `127.0.0.1:3000`.
+    pub fn upstream_authority(&self) -> &str {
+        let rest = self.upstream.trim_start_matches("http://");
+        rest.split('/').next().unwrap_or(rest)
+    }
+
+    /// Get the address to connect to the upstream server, port 80 if none is given.
+    pub fn upstream_address(&self) -> String {
+        let authority = self.upstream_authority();
+
+        if authority.rsplit(':').next().is_some_and(|port| !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()))
+            && !authority.ends_with(']') {
+            authority.to_string()
+        } else {
+            format!("{}:80", authority)
+        }
+    }
+
+    /// Returns the request target on the upstream server if the URL is below the prefix.
+    ///
+    /// The prefix is replaced with the path of the upstream URL and the query is kept, e.g.
+    /// `/api/items?page=2` becomes `/v1/items?page=2`.
+    pub fn upstream_target(&self, url: &str) -> Option<String> {
+        let (path, query) = url.split_at(url.find('?').unwrap_or(url.len()));
+        let rest = strip_path_prefix(&self.prefix, path)?;
+        let base = &self.upstream["http://".len() + self.upstream_authority().len()..];
+        let target = format!("{}{}", base.trim_end_matches('/'), rest);
+
+        if target.is_empty() {
+            Some(format!("/{}", query))
+        } else {
+            Some(format!("{}{}", target, query))
+        }
+    }
+}
+
+/// Returns the rest of the URL if it is below the prefix, which only matches whole path segments.
+fn strip_path_prefix<'a>(prefix: &str, url: &'a str) -> Option<&'a str> {
+    let prefix = prefix.trim_end_matches('/');
+
+    if !url.starts_with(prefix) {
+        return None;
+    }
+
+    let rest = &url[prefix.len()..];
+
+    if rest.is_empty() || rest.starts_with('/') {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+/// Direction in which URLs are redirected to normalize their trailing slash.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrailingSlash {
+    /// Redirects directories without trailing slash, e.g. `/dir` to `/dir/`.
+    Add,
+    /// Redirects files with trailing slash, e.g. `/index.html/` to `/index.html`.
+    Remove,
+}
+
+/// Handling of request heads which are not valid UTF-8.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum InvalidUtf8 {
+    /// Rejects the request with `400 Bad Request`.
+    Reject,
+    /// Replaces invalid bytes with the replacement character `U+FFFD`.
+    Replace,
+}
+
+/// Error returned when the configuration can't be read from a file.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file can't be read.
+    Io(io::Error),
+    /// The file is not valid TOML or does not match the expected structure.
+    Parse(toml::de::Error),
+    /// A value is invalid. The message names the offending field.
+    Validation(&'static str),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref err) => write!(f, "Can't read config file: {}", err),
+            ConfigError::Parse(ref err) => write!(f, "Can't parse config file: {}", err),
+            ConfigError::Validation(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ConfigError::Io(ref err) => Some(err),
+            ConfigError::Parse(ref err) => Some(err),
+            ConfigError::Validation(_) => None,
+        }
+    }
+}
+
 /// Configuration of the server.
 #[derive(Debug, Clone, Deserialize, PartialEq)]
 pub struct Config {
@@ -42,10 +344,222 @@ pub struct Config {
     threads: usize,
     /// Directory with the content to serve.
     web_dir: String,
-    /// Defines which messages to log.
+    /// Defines which messages to log, e.g. `info`.
+    /// Logs debug messages if not set.
+    #[serde(default = "default_log_level")]
     log_level: String,
     /// Location to store log files.
+    /// `log_files` if not set.
+    #[serde(default = "default_log_dir")]
     log_dir: String,
+    /// Maximum number of concurrent connections.
+    /// Unlimited if not set.
+    #[serde(default)]
+    max_connections: Option<usize>,
+    /// Maximum number of concurrent connections from a single client IP.
+    /// Unlimited if not set.
+    #[serde(default)]
+    max_connections_per_ip: Option<usize>,
+    /// Number of requests per second a single client IP may send.
+    /// Requests are not rate limited if not set.
+    #[serde(default)]
+    rate_limit_per_second: Option<u32>,
+    /// Number of requests a single client IP may send at once.
+    /// Defaults to the requests per second if not set.
+    #[serde(default)]
+    rate_limit_burst: Option<u32>,
+    /// Rate limits for URL path prefixes, applied in addition to the rate limit for all requests.
+    /// Only the first matching route limit applies.
+    #[serde(default)]
+    route_limits: Vec<RouteLimit>,
+    /// File to write the access log to.
+    /// No access log is written if not set.
+    #[serde(default)]
+    access_log: Option<String>,
+    /// Fraction of successful requests written to the access log, from 0.0 to 1.0.
+    /// Failed requests are always logged. All requests are logged if not set.
+    #[serde(default = "default_log_sample_rate")]
+    log_sample_rate: f64,
+    /// Maximum size of the request line and headers in bytes, larger requests are answered with 431,
+    /// or with 414 if the request line alone is larger.
+    /// Must not be zero or less.
+    #[serde(default = "default_max_request_bytes")]
+    max_request_bytes: usize,
+    /// Maximum size of a request body in bytes, after decoding its content encoding.
+    /// Must not be zero or less.
+    #[serde(default = "default_max_body_bytes")]
+    max_body_bytes: usize,
+    /// Whether to serve a built-in welcome page for `/` if the web root directory is empty.
+    /// Disabled if not set.
+    #[serde(default)]
+    welcome_page: bool,
+    /// Whether to echo `TRACE` requests instead of rejecting them.
+    /// Disabled if not set.
+    #[serde(default)]
+    allow_trace: bool,
+    /// URL path prefixes forwarded to upstream HTTP servers instead of being served from files.
+    /// Only the first matching proxy applies.
+    #[serde(default)]
+    proxies: Vec<Proxy>,
+    /// URL path prefixes served from other directories than the web root directory.
+    /// Consulted in order before the web root directory.
+    #[serde(default)]
+    mounts: Vec<Mount>,
+    /// Whether to warn about files whose content does not match the content type of their extension.
+    /// Disabled if not set.
+    #[serde(default)]
+    validate_content_type: bool,
+    /// Number of seconds to wait for the next request on a persistent connection.
+    /// Must not be zero or less.
+    #[serde(default = "default_keep_alive_timeout")]
+    keep_alive_timeout: u64,
+    /// Number of seconds to wait for the first request of a connection and for the rest of a
+    /// request once it started to arrive. Must not be zero or less.
+    #[serde(default = "default_read_timeout")]
+    read_timeout: u64,
+    /// Number of seconds a request may take to be handled before it is answered with
+    /// `503 Service Unavailable`. The handler keeps running in the background after the timeout.
+    /// Disabled if not set.
+    #[serde(default)]
+    handler_timeout: Option<u64>,
+    /// Maximum number of requests served on a persistent connection.
+    /// Must not be zero or less. One disables persistent connections.
+    #[serde(default = "default_keep_alive_max_requests")]
+    keep_alive_max_requests: usize,
+    /// Value of the `Server` response header.
+    /// The application description if not set. An empty value suppresses the header.
+    #[serde(default)]
+    server_token: Option<String>,
+    /// URL path to serve the metrics at.
+    /// No metrics are served if not set.
+    #[serde(default)]
+    metrics_path: Option<String>,
+    /// Stack size of the worker threads in bytes.
+    /// The platform default if not set.
+    #[serde(default)]
+    worker_stack_size: Option<usize>,
+    /// File served for `/favicon.ico`, regardless of the web root directory.
+    /// Served from the static assets if not set.
+    #[serde(default)]
+    favicon: Option<String>,
+    /// File served for HTML requests of paths without extension which are not found, e.g. the
+    /// `index.html` of a single page application doing client side routing.
+    /// Disabled if not set.
+    #[serde(default)]
+    spa_fallback: Option<String>,
+    /// Direction to redirect URLs in to normalize their trailing slash.
+    /// No redirects if not set.
+    #[serde(default)]
+    trailing_slash: Option<TrailingSlash>,
+    /// Extensions of files served as attachment, e.g. `zip`, which browsers offer to download.
+    /// No attachments if not set.
+    #[serde(default)]
+    attachment_extensions: Vec<String>,
+    /// Names of request header fields whose values are replaced by `***` in logs.
+    /// `Authorization`, `Cookie` and `Set-Cookie` if not set.
+    #[serde(default = "default_redacted_headers")]
+    redacted_headers: Vec<String>,
+    /// Names of the files served for a directory, the first existing one is served.
+    /// `index.html` and `index.htm` if not set.
+    #[serde(default = "default_index_files")]
+    index_files: Vec<String>,
+    /// Whether to serve files through symlinks leading outside of the directory they are served from.
+    /// Disabled if not set.
+    #[serde(default)]
+    follow_symlinks: bool,
+    /// Maximum number of connections waiting to be accepted.
+    /// The operating system caps it, e.g. at `net.core.somaxconn` on Linux and
+    /// `kern.ipc.somaxconn` on macOS. Only applied on Unix. The platform default if not set.
+    #[serde(default)]
+    listen_backlog: Option<u32>,
+    /// Path of a Unix domain socket to listen on instead of the address and port.
+    /// All clients of the socket count as `127.0.0.1` for the per-IP connection limit and the rate limits.
+    /// Only supported on Unix. Listens on the address and port if not set.
+    #[serde(default)]
+    unix_socket: Option<String>,
+    /// Whether to send small responses immediately instead of buffering them (`TCP_NODELAY`).
+    /// Enabled if not set.
+    #[serde(default = "default_tcp_nodelay")]
+    tcp_nodelay: bool,
+    /// Whether to report the time spent on a request in a `Server-Timing` response header.
+    /// Disabled if not set.
+    #[serde(default)]
+    server_timing: bool,
+    /// Whether to redirect all requests to the same URL on HTTPS, e.g. behind a TLS terminating proxy.
+    /// Disabled if not set.
+    #[serde(default)]
+    force_https: bool,
+    /// Port of the HTTPS redirects, left out of the location if it is the default port 443.
+    #[serde(default = "default_https_port")]
+    https_port: u16,
+    /// IP addresses of proxies whose `X-Forwarded-Proto` header is trusted, e.g. a TLS terminating proxy.
+    /// Requests these proxies forward over HTTPS are not redirected. No proxy is trusted if not set.
+    #[serde(default)]
+    trusted_proxies: Vec<String>,
+    /// Whether to reject requests with malformed header names or control characters in header values.
+    /// Disabled if not set.
+    #[serde(default)]
+    strict: bool,
+    /// Whether to accept methods regardless of their case, e.g. `get` as `GET`.
+    /// Methods are case-sensitive, so this is disabled if not set.
+    #[serde(default)]
+    lenient_methods: bool,
+    /// Whether to keep the header section of requests exactly as received, e.g. to verify signed webhooks.
+    /// Disabled if not set.
+    #[serde(default)]
+    retain_raw_headers: bool,
+    /// Handling of request heads which are not valid UTF-8.
+    /// Every byte is taken as the character of the same code point, like ISO-8859-1, if not set.
+    #[serde(default)]
+    invalid_utf8: Option<InvalidUtf8>,
+}
+
+fn default_log_level() -> String {
+    DEFAULT_LOG_LEVEL.to_string()
+}
+
+fn default_log_dir() -> String {
+    DEFAULT_LOG_DIR.to_string()
+}
+
+fn default_max_request_bytes() -> usize {
+    DEFAULT_MAX_REQUEST_BYTES
+}
+
+fn default_max_body_bytes() -> usize {
+    DEFAULT_MAX_BODY_BYTES
+}
+
+fn default_https_port() -> u16 {
+    DEFAULT_HTTPS_PORT
+}
+
+fn default_tcp_nodelay() -> bool {
+    DEFAULT_TCP_NODELAY
+}
+
+fn default_log_sample_rate() -> f64 {
+    DEFAULT_LOG_SAMPLE_RATE
+}
+
+fn default_keep_alive_timeout() -> u64 {
+    DEFAULT_KEEP_ALIVE_TIMEOUT
+}
+
+fn default_redacted_headers() -> Vec<String> {
+    DEFAULT_REDACTED_HEADERS.iter().map(|name| name.to_string()).collect()
+}
+
+fn default_index_files() -> Vec<String> {
+    DEFAULT_INDEX_FILES.iter().map(|name| name.to_string()).collect()
+}
+
+fn default_read_timeout() -> u64 {
+    DEFAULT_READ_TIMEOUT
+}
+
+fn default_keep_alive_max_requests() -> usize {
+    DEFAULT_KEEP_ALIVE_MAX_REQUESTS
 }
 
 impl Config {
@@ -61,106 +575,1227 @@ impl Config {
     /// log_level = 'debug'
     /// log_dir = 'logs/'
     /// ```
-    pub fn from_file(file_name: &PathBuf) -> Result<Config, &'static str> {
-        let config = file::read_string(&file_name);
-
-        match toml::from_str::<Config>(config.as_ref()) {
-            // Make a copy here to invoke the constructor which validates some fields.
-            Ok(config) => Config::new(
-                config.address,
-                config.port,
-                config.threads,
-                config.web_dir,
-                config.log_level,
-                config.log_dir
-            ),
-            Err(err) => {
-                // FIXME Return appropriate error result.
-                panic!("{}", err);
-            },
-        }
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ConfigError`](enum.ConfigError.html) if the file can't be read, is not valid
+    /// TOML or contains an invalid value.
+    pub fn from_file(file_name: &PathBuf) -> Result<Config, ConfigError> {
+        debug!("Reading config file {:?}.", file_name);
+        let config = fs::read_to_string(file_name).map_err(ConfigError::Io)?;
+        let config = toml::from_str::<Config>(config.as_ref()).map_err(ConfigError::Parse)?;
+
+        config.validate().map_err(ConfigError::Validation)
     }
 
     /// Creates a new configuration object.
+    ///
+    /// Optional settings are left at their defaults and may be changed with the `with_*` methods.
     pub fn new(address: String, port: u16, threads: usize, web_dir: String, log_level: String, log_dir: String) -> Result<Config, &'static str> {
-        if address.is_empty() {
+        Config {
+            address,
+            port,
+            threads,
+            web_dir,
+            log_level,
+            log_dir,
+            max_connections: None,
+            max_connections_per_ip: None,
+            rate_limit_per_second: None,
+            rate_limit_burst: None,
+            route_limits: Vec::new(),
+            access_log: None,
+            log_sample_rate: DEFAULT_LOG_SAMPLE_RATE,
+            max_request_bytes: DEFAULT_MAX_REQUEST_BYTES,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            welcome_page: false,
+            allow_trace: false,
+            proxies: Vec::new(),
+            mounts: Vec::new(),
+            validate_content_type: false,
+            keep_alive_timeout: DEFAULT_KEEP_ALIVE_TIMEOUT,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            handler_timeout: None,
+            keep_alive_max_requests: DEFAULT_KEEP_ALIVE_MAX_REQUESTS,
+            server_token: None,
+            metrics_path: None,
+            worker_stack_size: None,
+            favicon: None,
+            spa_fallback: None,
+            trailing_slash: None,
+            attachment_extensions: Vec::new(),
+            redacted_headers: default_redacted_headers(),
+            index_files: default_index_files(),
+            follow_symlinks: false,
+            listen_backlog: None,
+            unix_socket: None,
+            tcp_nodelay: DEFAULT_TCP_NODELAY,
+            server_timing: false,
+            force_https: false,
+            https_port: DEFAULT_HTTPS_PORT,
+            trusted_proxies: Vec::new(),
+            strict: false,
+            lenient_methods: false,
+            retain_raw_headers: false,
+            invalid_utf8: None,
+        }.validate()
+    }
+
+    /// Set the address to listen on.
+    pub fn with_address(self, address: String) -> Result<Config, &'static str> {
+        Config { address, ..self }.validate()
+    }
+
+    /// Set the port to listen on.
+    pub fn with_port(self, port: u16) -> Result<Config, &'static str> {
+        Config { port, ..self }.validate()
+    }
+
+    /// Set the number of worker threads.
+    pub fn with_threads(self, threads: usize) -> Result<Config, &'static str> {
+        Config { threads, ..self }.validate()
+    }
+
+    /// Set the directory with the content to serve.
+    pub fn with_web_dir(self, web_dir: String) -> Result<Config, &'static str> {
+        Config { web_dir, ..self }.validate()
+    }
+
+    /// Set the level of messages to log.
+    pub fn with_log_level(self, log_level: String) -> Result<Config, &'static str> {
+        Config { log_level, ..self }.validate()
+    }
+
+    /// Set the directory to store log files in.
+    pub fn with_log_dir(self, log_dir: String) -> Result<Config, &'static str> {
+        Config { log_dir, ..self }.validate()
+    }
+
+    /// Set the maximum number of concurrent connections.
+    pub fn with_max_connections(self, max_connections: usize) -> Result<Config, &'static str> {
+        Config { max_connections: Some(max_connections), ..self }.validate()
+    }
+
+    /// Set the maximum number of concurrent connections from a single client IP.
+    pub fn with_max_connections_per_ip(self, max_connections_per_ip: usize) -> Result<Config, &'static str> {
+        Config { max_connections_per_ip: Some(max_connections_per_ip), ..self }.validate()
+    }
+
+    /// Set the number of requests per second and the burst a single client IP may send.
+    pub fn with_rate_limit(self, per_second: u32, burst: u32) -> Result<Config, &'static str> {
+        Config { rate_limit_per_second: Some(per_second), rate_limit_burst: Some(burst), ..self }.validate()
+    }
+
+    /// Set the file to write the access log to.
+    pub fn with_access_log(self, access_log: String) -> Result<Config, &'static str> {
+        Config { access_log: Some(access_log), ..self }.validate()
+    }
+
+    /// Set the fraction of successful requests written to the access log.
+    pub fn with_log_sample_rate(self, log_sample_rate: f64) -> Result<Config, &'static str> {
+        Config { log_sample_rate, ..self }.validate()
+    }
+
+    /// Set the maximum size of a request in bytes.
+    pub fn with_max_request_bytes(self, max_request_bytes: usize) -> Result<Config, &'static str> {
+        Config { max_request_bytes, ..self }.validate()
+    }
+
+    /// Set the maximum size of a decoded request body in bytes.
+    pub fn with_max_body_bytes(self, max_body_bytes: usize) -> Result<Config, &'static str> {
+        Config { max_body_bytes, ..self }.validate()
+    }
+
+    /// Set whether to serve a built-in welcome page for `/` if the web root directory is empty.
+    pub fn with_welcome_page(self, welcome_page: bool) -> Config {
+        Config { welcome_page, ..self }
+    }
+
+    /// Set whether to echo `TRACE` requests instead of rejecting them.
+    pub fn with_allow_trace(self, allow_trace: bool) -> Config {
+        Config { allow_trace, ..self }
+    }
+
+    /// Set whether to warn about files whose content does not match the content type of their extension.
+    pub fn with_validate_content_type(self, validate_content_type: bool) -> Config {
+        Config { validate_content_type, ..self }
+    }
+
+    /// Set the idle timeout in seconds and the maximum number of requests of persistent connections.
+    pub fn with_keep_alive(self, keep_alive_timeout: u64, keep_alive_max_requests: usize) -> Result<Config, &'static str> {
+        Config { keep_alive_timeout, keep_alive_max_requests, ..self }.validate()
+    }
+
+    /// Set the timeout in seconds for reading a request once it started to arrive.
+    pub fn with_read_timeout(self, read_timeout: u64) -> Result<Config, &'static str> {
+        Config { read_timeout, ..self }.validate()
+    }
+
+    /// Set the timeout in seconds for handling a request.
+    pub fn with_handler_timeout(self, handler_timeout: u64) -> Result<Config, &'static str> {
+        Config { handler_timeout: Some(handler_timeout), ..self }.validate()
+    }
+
+    /// Set the value of the `Server` response header. An empty value suppresses the header.
+    pub fn with_server_token(self, server_token: String) -> Config {
+        Config { server_token: Some(server_token), ..self }
+    }
+
+    /// Set the URL path to serve the metrics at.
+    pub fn with_metrics_path(self, metrics_path: String) -> Result<Config, &'static str> {
+        Config { metrics_path: Some(metrics_path), ..self }.validate()
+    }
+
+    /// Set the stack size of the worker threads in bytes.
+    pub fn with_worker_stack_size(self, worker_stack_size: usize) -> Result<Config, &'static str> {
+        Config { worker_stack_size: Some(worker_stack_size), ..self }.validate()
+    }
+
+    /// Set the file served for `/favicon.ico`.
+    pub fn with_favicon(self, favicon: String) -> Result<Config, &'static str> {
+        Config { favicon: Some(favicon), ..self }.validate()
+    }
+
+    /// Set the file served for HTML requests of paths without extension which are not found.
+    pub fn with_spa_fallback(self, spa_fallback: String) -> Result<Config, &'static str> {
+        Config { spa_fallback: Some(spa_fallback), ..self }.validate()
+    }
+
+    /// Set the direction to redirect URLs in to normalize their trailing slash.
+    pub fn with_trailing_slash(self, trailing_slash: TrailingSlash) -> Config {
+        Config { trailing_slash: Some(trailing_slash), ..self }
+    }
+
+    /// Set the extensions of files served as attachment.
+    pub fn with_attachment_extensions(self, attachment_extensions: Vec<String>) -> Config {
+        Config { attachment_extensions, ..self }
+    }
+
+    /// Set the names of request header fields whose values are redacted in logs.
+    pub fn with_redacted_headers(self, redacted_headers: Vec<String>) -> Config {
+        Config { redacted_headers, ..self }
+    }
+
+    /// Set whether to serve files through symlinks leading outside of the directory they are served from.
+    pub fn with_follow_symlinks(self, follow_symlinks: bool) -> Config {
+        Config { follow_symlinks, ..self }
+    }
+
+    /// Set the names of the files served for a directory, in the order they are looked up.
+    pub fn with_index_files(self, index_files: Vec<String>) -> Result<Config, &'static str> {
+        Config { index_files, ..self }.validate()
+    }
+
+    /// Set the maximum number of connections waiting to be accepted.
+    pub fn with_listen_backlog(self, listen_backlog: u32) -> Result<Config, &'static str> {
+        Config { listen_backlog: Some(listen_backlog), ..self }.validate()
+    }
+
+    /// Set the path of a Unix domain socket to listen on instead of the address and port.
+    pub fn with_unix_socket(self, unix_socket: String) -> Result<Config, &'static str> {
+        Config { unix_socket: Some(unix_socket), ..self }.validate()
+    }
+
+    /// Set whether to send small responses immediately instead of buffering them.
+    pub fn with_tcp_nodelay(self, tcp_nodelay: bool) -> Config {
+        Config { tcp_nodelay, ..self }
+    }
+
+    /// Set whether to report the time spent on a request in a `Server-Timing` response header.
+    pub fn with_server_timing(self, server_timing: bool) -> Config {
+        Config { server_timing, ..self }
+    }
+
+    /// Set whether to redirect all requests to the same URL on HTTPS.
+    pub fn with_force_https(self, force_https: bool) -> Config {
+        Config { force_https, ..self }
+    }
+
+    /// Set the port of the HTTPS redirects.
+    pub fn with_https_port(self, https_port: u16) -> Result<Config, &'static str> {
+        Config { https_port, ..self }.validate()
+    }
+
+    /// Set the IP addresses of proxies whose `X-Forwarded-Proto` header is trusted.
+    pub fn with_trusted_proxies(self, trusted_proxies: Vec<String>) -> Result<Config, &'static str> {
+        Config { trusted_proxies, ..self }.validate()
+    }
+
+    /// Set whether to reject requests with malformed headers.
+    pub fn with_strict(self, strict: bool) -> Config {
+        Config { strict, ..self }
+    }
+
+    /// Set whether to accept methods regardless of their case.
+    pub fn with_lenient_methods(self, lenient_methods: bool) -> Config {
+        Config { lenient_methods, ..self }
+    }
+
+    /// Set whether to keep the header section of requests as received.
+    pub fn with_retain_raw_headers(self, retain_raw_headers: bool) -> Config {
+        Config { retain_raw_headers, ..self }
+    }
+
+    /// Set the handling of request heads which are not valid UTF-8.
+    pub fn with_invalid_utf8(self, invalid_utf8: InvalidUtf8) -> Config {
+        Config { invalid_utf8: Some(invalid_utf8), ..self }
+    }
+
+    /// Adds a route limit consulted after all previously added ones.
+    pub fn with_route_limit(mut self, route_limit: RouteLimit) -> Config {
+        self.route_limits.push(route_limit);
+        self
+    }
+
+    /// Adds a proxy consulted after all previously added ones.
+    pub fn with_proxy(mut self, proxy: Proxy) -> Config {
+        self.proxies.push(proxy);
+        self
+    }
+
+    /// Adds a mount consulted after all previously added ones.
+    pub fn with_mount(mut self, mount: Mount) -> Config {
+        self.mounts.push(mount);
+        self
+    }
+
+    fn validate(self) -> Result<Config, &'static str> {
+        if self.address.is_empty() {
             return Err("Config value 'address' must not be empty!");
         }
 
-        if port < 1 {
+        if self.port < 1 {
             return Err("Config value 'port' must be grater than 0!");
         }
 
-        if threads < 1 {
+        if self.threads < 1 {
             return Err("Config value 'threads' must be grater than 0!");
         }
 
-        if web_dir.is_empty() {
+        if self.web_dir.is_empty() {
             return Err("Config value 'web_dir' must not be empty!");
         }
 
         // TODO Validate that it is a proper level.
-        if log_level.is_empty() {
+        if self.log_level.is_empty() {
             return Err("Config value 'log_level' must not be empty!");
         }
 
         // TODO Validate that dir exists.
-        if log_dir.is_empty() {
+        if self.log_dir.is_empty() {
             return Err("Config value 'log_dir' must not be empty!");
         }
 
-        Ok(Config { address, port, threads, web_dir: web_dir, log_level, log_dir })
-    }
+        if self.max_connections == Some(0) {
+            return Err("Config value 'max_connections' must be grater than 0!");
+        }
 
-    /// Get the IP address to listen.
-    pub fn address(&self) -> &String {
-        &self.address
-    }
+        if self.max_connections_per_ip == Some(0) {
+            return Err("Config value 'max_connections_per_ip' must be grater than 0!");
+        }
 
-    /// Get the TCP port to listen.
-    pub fn port(&self) -> &u16 {
-        &self.port
-    }
+        if self.rate_limit_per_second == Some(0) {
+            return Err("Config value 'rate_limit_per_second' must be grater than 0!");
+        }
 
-    /// Get the number of worker threads.
-    pub fn threads(&self) -> &usize {
-        &self.threads
-    }
+        if self.rate_limit_burst == Some(0) {
+            return Err("Config value 'rate_limit_burst' must be grater than 0!");
+        }
 
-    /// Get the web root directory.
-    pub fn web_dir(&self) -> &String {
-        &self.web_dir
-    }
+        if self.max_request_bytes < 1 {
+            return Err("Config value 'max_request_bytes' must be grater than 0!");
+        }
 
-    /// Get the log level.
-    pub fn log_level(&self) -> &String {
-        &self.log_level
-    }
+        if self.max_body_bytes < 1 {
+            return Err("Config value 'max_body_bytes' must be grater than 0!");
+        }
 
-    /// Get the location of the log files.
-    pub fn log_dir(&self) -> &String {
-        &self.log_dir
-    }
-}
+        if self.keep_alive_timeout < 1 {
+            return Err("Config value 'keep_alive_timeout' must be grater than 0!");
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use hamcrest::prelude::*;
+        if self.read_timeout < 1 {
+            return Err("Config value 'read_timeout' must be grater than 0!");
+        }
 
-    #[test]
-    fn read_config_from_from_file() {
-        let config = Config::from_file(&PathBuf::from("test/fixtures/config.toml"))
-            .expect("Can't read config fixture file!");
+        if self.handler_timeout.is_some_and(|handler_timeout| handler_timeout < 1) {
+            return Err("Config value 'handler_timeout' must be grater than 0!");
+        }
 
-        assert_eq!(config.address(), "127.0.0.1");
-        let expected_port: u16 = 8080;
+        if self.https_port < 1 {
+            return Err("Config value 'https_port' must be grater than 0!");
+        }
+
+        if self.trusted_proxies.iter().any(|trusted_proxy| trusted_proxy.parse::<IpAddr>().is_err()) {
+            return Err("Config value 'trusted_proxies' must only contain IP addresses!");
+        }
+
+        if self.keep_alive_max_requests < 1 {
+            return Err("Config value 'keep_alive_max_requests' must be grater than 0!");
+        }
+
+        for mount in &self.mounts {
+            mount.clone().validate()?;
+        }
+
+        for proxy in &self.proxies {
+            proxy.clone().validate()?;
+        }
+
+        for route_limit in &self.route_limits {
+            route_limit.clone().validate()?;
+        }
+
+        if self.index_files.iter().any(|index_file| index_file.is_empty() || index_file.contains(['/', '\\'])) {
+            return Err("Config value 'index_files' must only contain file names!");
+        }
+
+        if self.access_log.as_ref().is_some_and(|access_log| access_log.is_empty()) {
+            return Err("Config value 'access_log' must not be empty!");
+        }
+
+        if !(0.0..=1.0).contains(&self.log_sample_rate) {
+            return Err("Config value 'log_sample_rate' must be between 0.0 and 1.0!");
+        }
+
+        if self.worker_stack_size.is_some_and(|worker_stack_size| worker_stack_size < 1) {
+            return Err("Config value 'worker_stack_size' must be grater than 0!");
+        }
+
+        if self.listen_backlog.is_some_and(|listen_backlog| listen_backlog < 1) {
+            return Err("Config value 'listen_backlog' must be grater than 0!");
+        }
+
+        if self.unix_socket.as_ref().is_some_and(|unix_socket| unix_socket.is_empty()) {
+            return Err("Config value 'unix_socket' must not be empty!");
+        }
+
+        if cfg!(not(unix)) && self.unix_socket.is_some() {
+            return Err("Config value 'unix_socket' is only supported on Unix!");
+        }
+
+        if self.metrics_path.as_ref().is_some_and(|metrics_path| !metrics_path.starts_with('/')) {
+            return Err("Config value 'metrics_path' must start with a slash!");
+        }
+
+        if self.favicon.as_ref().is_some_and(|favicon| favicon.is_empty()) {
+            return Err("Config value 'favicon' must not be empty!");
+        }
+
+        if self.spa_fallback.as_ref().is_some_and(|spa_fallback| spa_fallback.is_empty()) {
+            return Err("Config value 'spa_fallback' must not be empty!");
+        }
+
+        Ok(self)
+    }
+
+    /// Get the IP address to listen.
+    pub fn address(&self) -> &String {
+        &self.address
+    }
+
+    /// Get the TCP port to listen.
+    pub fn port(&self) -> &u16 {
+        &self.port
+    }
+
+    /// Get the number of worker threads.
+    pub fn threads(&self) -> &usize {
+        &self.threads
+    }
+
+    /// Get the web root directory.
+    pub fn web_dir(&self) -> &String {
+        &self.web_dir
+    }
+
+    /// Get the log level.
+    pub fn log_level(&self) -> &String {
+        &self.log_level
+    }
+
+    /// Get the location of the log files.
+    pub fn log_dir(&self) -> &String {
+        &self.log_dir
+    }
+
+    /// Get the maximum number of concurrent connections, if limited.
+    pub fn max_connections(&self) -> Option<usize> {
+        self.max_connections
+    }
+
+    /// Get the maximum number of concurrent connections per client IP, if limited.
+    pub fn max_connections_per_ip(&self) -> Option<usize> {
+        self.max_connections_per_ip
+    }
+
+    /// Get the maximum size of a request in bytes.
+    pub fn max_request_bytes(&self) -> &usize {
+        &self.max_request_bytes
+    }
+
+    /// Get the maximum size of a decoded request body in bytes.
+    pub fn max_body_bytes(&self) -> &usize {
+        &self.max_body_bytes
+    }
+
+    /// Get whether to serve a built-in welcome page for `/` if the web root directory is empty.
+    pub fn welcome_page(&self) -> &bool {
+        &self.welcome_page
+    }
+
+    /// Get whether to echo `TRACE` requests instead of rejecting them.
+    pub fn allow_trace(&self) -> &bool {
+        &self.allow_trace
+    }
+
+    /// Get whether to warn about files whose content does not match the content type of their extension.
+    pub fn validate_content_type(&self) -> &bool {
+        &self.validate_content_type
+    }
+
+    /// Get the number of seconds to wait for the next request on a persistent connection.
+    pub fn keep_alive_timeout(&self) -> &u64 {
+        &self.keep_alive_timeout
+    }
+
+    /// Get the number of seconds to wait for the rest of a request once it started to arrive.
+    pub fn read_timeout(&self) -> &u64 {
+        &self.read_timeout
+    }
+
+    /// Get the number of seconds a request may take to be handled, `None` for no limit.
+    pub fn handler_timeout(&self) -> Option<u64> {
+        self.handler_timeout
+    }
+
+    /// Get the maximum number of requests served on a persistent connection.
+    pub fn keep_alive_max_requests(&self) -> &usize {
+        &self.keep_alive_max_requests
+    }
+
+    /// Get the route limits in the order they are consulted.
+    pub fn route_limits(&self) -> &Vec<RouteLimit> {
+        &self.route_limits
+    }
+
+    /// Get the proxies in the order they are consulted.
+    pub fn proxies(&self) -> &Vec<Proxy> {
+        &self.proxies
+    }
+
+    /// Get the first proxy forwarding the URL, if any.
+    pub fn proxy_for(&self, url: &str) -> Option<&Proxy> {
+        self.proxies.iter().find(|proxy| proxy.upstream_target(url).is_some())
+    }
+
+    /// Get the mounts in the order they are consulted.
+    pub fn mounts(&self) -> &Vec<Mount> {
+        &self.mounts
+    }
+
+    /// Get the value of the `Server` response header, `None` if the header is suppressed.
+    pub fn server_token(&self) -> Option<&str> {
+        match self.server_token {
+            Some(ref server_token) if server_token.is_empty() => None,
+            Some(ref server_token) => Some(server_token),
+            None => Some(APPLICATION_DESCRIPTION),
+        }
+    }
+
+    /// Get the stack size of the worker threads in bytes, `None` for the platform default.
+    pub fn worker_stack_size(&self) -> Option<usize> {
+        self.worker_stack_size
+    }
+
+    /// Get the maximum number of connections waiting to be accepted, `None` for the platform default.
+    pub fn listen_backlog(&self) -> Option<u32> {
+        self.listen_backlog
+    }
+
+    /// Get the path of the Unix domain socket to listen on, if any.
+    pub fn unix_socket(&self) -> Option<&String> {
+        self.unix_socket.as_ref()
+    }
+
+    /// Get whether to send small responses immediately instead of buffering them.
+    pub fn tcp_nodelay(&self) -> &bool {
+        &self.tcp_nodelay
+    }
+
+    /// Get whether to report the time spent on a request in a `Server-Timing` response header.
+    pub fn server_timing(&self) -> &bool {
+        &self.server_timing
+    }
+
+    /// Get whether to redirect all requests to the same URL on HTTPS.
+    pub fn force_https(&self) -> &bool {
+        &self.force_https
+    }
+
+    /// Get the port of the HTTPS redirects.
+    pub fn https_port(&self) -> &u16 {
+        &self.https_port
+    }
+
+    /// Get the IP addresses of proxies whose `X-Forwarded-Proto` header is trusted.
+    pub fn trusted_proxies(&self) -> &Vec<String> {
+        &self.trusted_proxies
+    }
+
+    /// Get whether to reject requests with malformed headers.
+    pub fn strict(&self) -> &bool {
+        &self.strict
+    }
+
+    /// Get whether to accept methods regardless of their case.
+    pub fn lenient_methods(&self) -> &bool {
+        &self.lenient_methods
+    }
+
+    /// Get whether to keep the header section of requests as received.
+    pub fn retain_raw_headers(&self) -> &bool {
+        &self.retain_raw_headers
+    }
+
+    /// Get the handling of request heads which are not valid UTF-8, if any.
+    pub fn invalid_utf8(&self) -> Option<InvalidUtf8> {
+        self.invalid_utf8
+    }
+
+    /// Get the URL path to serve the metrics at, if any.
+    pub fn metrics_path(&self) -> Option<&String> {
+        self.metrics_path.as_ref()
+    }
+
+    /// Get the file served for `/favicon.ico`, if any.
+    pub fn favicon(&self) -> Option<&String> {
+        self.favicon.as_ref()
+    }
+
+    /// Get the file served for HTML requests of paths without extension which are not found, if any.
+    pub fn spa_fallback(&self) -> Option<&String> {
+        self.spa_fallback.as_ref()
+    }
+
+    /// Get the direction to redirect URLs in to normalize their trailing slash, if any.
+    pub fn trailing_slash(&self) -> Option<TrailingSlash> {
+        self.trailing_slash
+    }
+
+    /// Get the extensions of files served as attachment.
+    pub fn attachment_extensions(&self) -> &Vec<String> {
+        &self.attachment_extensions
+    }
+
+    /// Get the names of request header fields whose values are redacted in logs.
+    pub fn redacted_headers(&self) -> &Vec<String> {
+        &self.redacted_headers
+    }
+
+    /// Get the names of the files served for a directory, in the order they are looked up.
+    pub fn index_files(&self) -> &Vec<String> {
+        &self.index_files
+    }
+
+    /// Get whether to serve files through symlinks leading outside of the directory they are served from.
+    pub fn follow_symlinks(&self) -> &bool {
+        &self.follow_symlinks
+    }
+
+    /// Whether the file is served as attachment because of its extension.
+    ///
+    /// Extensions match case-insensitively and may be given with or without leading dot.
+    pub fn is_attachment(&self, file_name: &Path) -> bool {
+        let extension = match file_name.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) => extension,
+            None => return false,
+        };
+
+        self.attachment_extensions.iter()
+            .any(|attachment| attachment.trim_start_matches('.').eq_ignore_ascii_case(extension))
+    }
+
+    /// Get the file to write the access log to, if any.
+    pub fn access_log(&self) -> Option<&String> {
+        self.access_log.as_ref()
+    }
+
+    /// Get the fraction of successful requests written to the access log.
+    pub fn log_sample_rate(&self) -> &f64 {
+        &self.log_sample_rate
+    }
+
+    /// Get the requests per second and the burst a single client IP may send, if rate limited.
+    pub fn rate_limit(&self) -> Option<(u32, u32)> {
+        self.rate_limit_per_second
+            .map(|per_second| (per_second, self.rate_limit_burst.unwrap_or(per_second)))
+    }
+}
+
+impl Default for Config {
+    /// Creates a configuration serving `web_dir` on `127.0.0.1:8080` with the defaults of all
+    /// optional settings, e.g. to configure an embedded server entirely in code.
+    fn default() -> Config {
+        Config::new(
+            DEFAULT_ADDRESS.to_string(),
+            DEFAULT_PORT,
+            DEFAULT_THREADS,
+            DEFAULT_WEB_DIR.to_string(),
+            DEFAULT_LOG_LEVEL.to_string(),
+            DEFAULT_LOG_DIR.to_string()).expect("Default config must be valid!")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+
+    #[test]
+    fn read_config_from_from_file() {
+        let config = Config::from_file(&PathBuf::from("test/fixtures/config.toml"))
+            .expect("Can't read config fixture file!");
+
+        assert_eq!(config.address(), "127.0.0.1");
+        let expected_port: u16 = 8080;
         assert_eq!(config.port(), &expected_port);
         let expected_threads: usize = 4;
         assert_eq!(config.threads(), &expected_threads);
         assert_eq!(config.web_dir(), "web_dir");
         assert_eq!(config.log_level(), "debug");
         assert_eq!(config.log_dir(), "logs");
+        assert_eq!(config.max_connections(), None);
+        assert_eq!(config.max_connections_per_ip(), None);
+        assert_eq!(config.rate_limit(), None);
+        assert_eq!(config.route_limits(), &Vec::new());
+        assert_eq!(config.access_log(), None);
+        assert_eq!(config.log_sample_rate(), &DEFAULT_LOG_SAMPLE_RATE);
+        assert_eq!(config.max_request_bytes(), &DEFAULT_MAX_REQUEST_BYTES);
+        assert_eq!(config.max_body_bytes(), &DEFAULT_MAX_BODY_BYTES);
+        assert_eq!(config.welcome_page(), &false);
+        assert_eq!(config.allow_trace(), &false);
+        assert_eq!(config.mounts(), &Vec::new());
+        assert_eq!(config.proxies(), &Vec::new());
+        assert_eq!(config.validate_content_type(), &false);
+        assert_eq!(config.keep_alive_timeout(), &DEFAULT_KEEP_ALIVE_TIMEOUT);
+        assert_eq!(config.read_timeout(), &DEFAULT_READ_TIMEOUT);
+        assert_eq!(config.handler_timeout(), None);
+        assert_eq!(config.keep_alive_max_requests(), &DEFAULT_KEEP_ALIVE_MAX_REQUESTS);
+        assert_eq!(config.server_token(), Some(APPLICATION_DESCRIPTION));
+        assert_eq!(config.metrics_path(), None);
+        assert_eq!(config.worker_stack_size(), None);
+        assert_eq!(config.listen_backlog(), None);
+        assert_eq!(config.unix_socket(), None);
+        assert_eq!(config.tcp_nodelay(), &DEFAULT_TCP_NODELAY);
+        assert_eq!(config.server_timing(), &false);
+        assert_eq!(config.force_https(), &false);
+        assert_eq!(config.https_port(), &DEFAULT_HTTPS_PORT);
+        assert_eq!(config.trusted_proxies(), &Vec::<String>::new());
+        assert_eq!(config.strict(), &false);
+        assert_eq!(config.lenient_methods(), &false);
+        assert_eq!(config.retain_raw_headers(), &false);
+        assert_eq!(config.invalid_utf8(), None);
+        assert_eq!(config.favicon(), None);
+        assert_eq!(config.spa_fallback(), None);
+        assert_eq!(config.trailing_slash(), None);
+        assert_eq!(config.attachment_extensions(), &Vec::<String>::new());
+        assert_eq!(config.redacted_headers(), &vec!(
+            String::from("Authorization"), String::from("Cookie"), String::from("Set-Cookie")));
+        assert_eq!(config.index_files(), &vec!(String::from("index.html"), String::from("index.htm")));
+        assert_eq!(config.follow_symlinks(), &false);
+    }
+
+    #[test]
+    fn default_config_may_be_changed_programmatically() {
+        let config = Config::default();
+
+        assert_eq!(config.address(), "127.0.0.1");
+        assert_eq!(config.port(), &8080);
+        assert_eq!(config.threads(), &4);
+        assert_eq!(config.web_dir(), "web_dir");
+        assert_eq!(config.log_level(), "debug");
+        assert_eq!(config.log_dir(), "log_files");
+
+        let config = config
+            .with_address(String::from("0.0.0.0")).unwrap()
+            .with_port(9090).unwrap()
+            .with_threads(8).unwrap()
+            .with_web_dir(String::from("public")).unwrap()
+            .with_log_level(String::from("info")).unwrap()
+            .with_log_dir(String::from("logs")).unwrap();
+
+        assert_eq!(config.address(), "0.0.0.0");
+        assert_eq!(config.port(), &9090);
+        assert_eq!(config.threads(), &8);
+        assert_eq!(config.web_dir(), "public");
+        assert_eq!(config.log_level(), "info");
+        assert_eq!(config.log_dir(), "logs");
+        assert_that!(Config::default().with_threads(0), is(equal_to(Err("Config value 'threads' must be grater than 0!"))));
+        assert_that!(Config::default().with_port(0), is(equal_to(Err("Config value 'port' must be grater than 0!"))));
+    }
+
+    #[test]
+    fn is_attachment_matches_extensions_case_insensitively() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+            .with_attachment_extensions(vec!(String::from("zip"), String::from(".exe")));
+
+        assert_that!(config.is_attachment(&PathBuf::from("web_dir/release.zip")), is(true));
+        assert_that!(config.is_attachment(&PathBuf::from("web_dir/SETUP.EXE")), is(true));
+        assert_that!(config.is_attachment(&PathBuf::from("web_dir/index.html")), is(false));
+        assert_that!(config.is_attachment(&PathBuf::from("web_dir/zip")), is(false));
+    }
+
+    #[test]
+    fn server_token_may_be_overridden_or_suppressed() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap();
+
+        assert_eq!(config.clone().with_server_token(String::from("MyServer")).server_token(), Some("MyServer"));
+        assert_eq!(config.with_server_token(String::from("")).server_token(), None);
+    }
+
+    #[test]
+    fn from_file_fails_with_io_error_for_missing_file() {
+        let error = Config::from_file(&PathBuf::from("test/fixtures/does_not_exist.toml")).unwrap_err();
+
+        match error {
+            ConfigError::Io(ref err) => assert_that!(err.kind(), is(equal_to(io::ErrorKind::NotFound))),
+            _ => panic!("Expected IO error but got: {:?}", error),
+        }
+        assert_that!(error::Error::source(&error).is_some(), is(true));
+    }
+
+    #[test]
+    fn from_file_fails_with_parse_error_for_malformed_toml() {
+        let error = Config::from_file(&PathBuf::from("test/fixtures/config_with_syntax_error.toml")).unwrap_err();
+
+        match error {
+            ConfigError::Parse(_) => {},
+            _ => panic!("Expected parse error but got: {:?}", error),
+        }
+        assert_that!(error.to_string().starts_with("Can't parse config file: "), is(true));
+    }
+
+    #[test]
+    fn from_file_fails_with_validation_error_for_invalid_value() {
+        let error = Config::from_file(&PathBuf::from("test/fixtures/config_with_invalid_value.toml")).unwrap_err();
+
+        match error {
+            ConfigError::Validation(message) =>
+                assert_that!(message, is(equal_to("Config value 'threads' must be grater than 0!"))),
+            _ => panic!("Expected validation error but got: {:?}", error),
+        }
+        assert_that!(error.to_string(), is(equal_to(String::from("Config value 'threads' must be grater than 0!"))));
+        assert_that!(error::Error::source(&error).is_none(), is(true));
+    }
+
+    #[test]
+    fn with_keep_alive_validates_not_less_than_one() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap();
+
+        assert_that!(
+            config.clone().with_keep_alive(0, 100),
+            is(equal_to(Err("Config value 'keep_alive_timeout' must be grater than 0!"))));
+        assert_that!(
+            config.with_keep_alive(5, 0),
+            is(equal_to(Err("Config value 'keep_alive_max_requests' must be grater than 0!"))));
+    }
+
+    #[test]
+    fn with_read_timeout_validates_not_less_than_one() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap();
+
+        assert_that!(
+            config.clone().with_read_timeout(0),
+            is(equal_to(Err("Config value 'read_timeout' must be grater than 0!"))));
+        assert_that!(config.with_read_timeout(10).unwrap().read_timeout(), is(equal_to(&10)));
+    }
+
+    #[test]
+    fn read_config_with_log_level_from_file() {
+        let config = Config::from_file(&PathBuf::from("test/fixtures/config_with_log_level.toml"))
+            .expect("Can't read config fixture file!");
+
+        assert_eq!(config.log_level(), "info");
+        assert_eq!(config.log_dir(), DEFAULT_LOG_DIR);
+    }
+
+    #[test]
+    fn read_config_with_mounts_from_file() {
+        let config = Config::from_file(&PathBuf::from("test/fixtures/config_with_mounts.toml"))
+            .expect("Can't read config fixture file!");
+
+        assert_eq!(config.mounts(), &vec!(
+            Mount::new(String::from("/static"), String::from("static_dir")).unwrap(),
+            Mount::new(String::from("/downloads"), String::from("downloads_dir")).unwrap()));
+    }
+
+    #[test]
+    fn read_config_with_trailing_slash_from_file() {
+        let config = Config::from_file(&PathBuf::from("test/fixtures/config_with_trailing_slash.toml"))
+            .expect("Can't read config fixture file!");
+
+        assert_eq!(config.trailing_slash(), Some(TrailingSlash::Remove));
+    }
+
+    #[test]
+    fn route_limit_validates_values() {
+        assert_that!(
+            RouteLimit::new(String::from("search"), 1, None),
+            is(equal_to(Err("Route limit value 'prefix' must start with a slash!"))));
+        assert_that!(
+            RouteLimit::new(String::from("/search"), 0, None),
+            is(equal_to(Err("Route limit value 'per_second' must be grater than 0!"))));
+        assert_that!(
+            RouteLimit::new(String::from("/search"), 1, Some(0)),
+            is(equal_to(Err("Route limit value 'burst' must be grater than 0!"))));
+        assert_that!(RouteLimit::new(String::from("/search"), 2, None).unwrap().burst(), is(equal_to(2)));
+    }
+
+    #[test]
+    fn route_limit_matches_whole_path_segments_ignoring_query() {
+        let sut = RouteLimit::new(String::from("/search"), 1, None).unwrap();
+
+        assert_that!(sut.matches("/search"), is(true));
+        assert_that!(sut.matches("/search?q=rust"), is(true));
+        assert_that!(sut.matches("/search/advanced"), is(true));
+        assert_that!(sut.matches("/searching"), is(false));
+        assert_that!(sut.matches("/index.html"), is(false));
+    }
+
+    #[test]
+    fn proxy_validates_values() {
+        assert_that!(
+            Proxy::new(String::from("api"), String::from("http://localhost:3000")),
+            is(equal_to(Err("Proxy value 'prefix' must start with a slash!"))));
+        assert_that!(
+            Proxy::new(String::from("/api"), String::from("https://localhost:3000")),
+            is(equal_to(Err("Proxy value 'upstream' must be an http:// URL!"))));
+        assert_that!(
+            Proxy::new(String::from("/api"), String::from("http:///v1")),
+            is(equal_to(Err("Proxy value 'upstream' must be an http:// URL!"))));
+    }
+
+    #[test]
+    fn proxy_maps_urls_to_upstream() {
+        let sut = Proxy::new(String::from("/api"), String::from("http://localhost:3000/v1/")).unwrap();
+
+        assert_that!(sut.upstream_address().as_str(), is(equal_to("localhost:3000")));
+        assert_that!(sut.upstream_target("/api/items?page=2"), is(equal_to(Some(String::from("/v1/items?page=2")))));
+        assert_that!(sut.upstream_target("/api"), is(equal_to(Some(String::from("/v1")))));
+        assert_that!(sut.upstream_target("/apis"), is(equal_to(None)));
+
+        let sut = Proxy::new(String::from("/"), String::from("http://example.com")).unwrap();
+
+        assert_that!(sut.upstream_address().as_str(), is(equal_to("example.com:80")));
+        assert_that!(sut.upstream_target("/?q=1"), is(equal_to(Some(String::from("/?q=1")))));
+        assert_that!(sut.upstream_target("/index.html"), is(equal_to(Some(String::from("/index.html")))));
+    }
+
+    #[test]
+    fn mount_validates_prefix_starts_with_slash() {
+        assert_that!(
+            Mount::new(String::from("static"), String::from("static_dir")),
+            is(equal_to(Err("Mount value 'prefix' must start with a slash!"))));
+    }
+
+    #[test]
+    fn mount_validates_root_not_empty() {
+        assert_that!(
+            Mount::new(String::from("/static"), String::from("")),
+            is(equal_to(Err("Mount value 'root' must not be empty!"))));
+    }
+
+    #[test]
+    fn mount_strip_prefix() {
+        let mount = Mount::new(String::from("/static"), String::from("static_dir")).unwrap();
+
+        assert_eq!(mount.strip_prefix("/static"), Some(""));
+        assert_eq!(mount.strip_prefix("/static/"), Some("/"));
+        assert_eq!(mount.strip_prefix("/static/css/main.css"), Some("/css/main.css"));
+        assert_eq!(mount.strip_prefix("/statical"), None);
+        assert_eq!(mount.strip_prefix("/index.html"), None);
+
+        let mount = Mount::new(String::from("/static/"), String::from("static_dir")).unwrap();
+
+        assert_eq!(mount.strip_prefix("/static/main.css"), Some("/main.css"));
+    }
+
+    #[test]
+    fn read_config_with_connection_limits_from_file() {
+        let config = Config::from_file(&PathBuf::from("test/fixtures/config_with_connection_limits.toml"))
+            .expect("Can't read config fixture file!");
+
+        assert_eq!(config.max_connections(), Some(100));
+        assert_eq!(config.max_connections_per_ip(), Some(10));
+    }
+
+    #[test]
+    fn read_config_with_rate_limit_from_file() {
+        let config = Config::from_file(&PathBuf::from("test/fixtures/config_with_rate_limit.toml"))
+            .expect("Can't read config fixture file!");
+
+        assert_eq!(config.rate_limit(), Some((5, 20)));
+        assert_eq!(config.route_limits(), &vec!(
+            RouteLimit::new(String::from("/search"), 1, Some(3)).unwrap()));
+    }
+
+    #[test]
+    fn with_rate_limit_validates_not_less_than_one() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap();
+
+        assert_that!(
+            config.clone().with_rate_limit(0, 1),
+            is(equal_to(Err("Config value 'rate_limit_per_second' must be grater than 0!"))));
+        assert_that!(
+            config.with_rate_limit(1, 0),
+            is(equal_to(Err("Config value 'rate_limit_burst' must be grater than 0!"))));
+    }
+
+    #[test]
+    fn with_max_request_bytes_validates_not_less_than_one() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+            .with_max_request_bytes(0);
+
+        assert_that!(config, is(equal_to(Err("Config value 'max_request_bytes' must be grater than 0!"))));
+    }
+
+    #[test]
+    fn with_worker_stack_size_validates_not_less_than_one() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap();
+
+        assert_that!(
+            config.clone().with_worker_stack_size(0),
+            is(equal_to(Err("Config value 'worker_stack_size' must be grater than 0!"))));
+        assert_that!(
+            config.with_worker_stack_size(8 * 1024 * 1024).unwrap().worker_stack_size(),
+            is(equal_to(Some(8 * 1024 * 1024))));
+    }
+
+    #[test]
+    fn with_handler_timeout_validates_not_less_than_one() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap();
+
+        assert_that!(
+            config.clone().with_handler_timeout(0),
+            is(equal_to(Err("Config value 'handler_timeout' must be grater than 0!"))));
+        assert_that!(config.with_handler_timeout(10).unwrap().handler_timeout(), is(equal_to(Some(10))));
+    }
+
+    #[test]
+    fn with_listen_backlog_validates_not_less_than_one() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+            .with_listen_backlog(0);
+
+        assert_that!(config, is(equal_to(Err("Config value 'listen_backlog' must be grater than 0!"))));
+    }
+
+    #[test]
+    fn with_unix_socket_validates_not_empty() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+            .with_unix_socket(String::new());
+
+        assert_that!(config, is(equal_to(Err("Config value 'unix_socket' must not be empty!"))));
+    }
+
+    #[test]
+    fn with_index_files_validates_file_names() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap();
+
+        assert_that!(
+            config.clone().with_index_files(vec!(String::from("index.html"), String::from("docs/index.html"))),
+            is(equal_to(Err("Config value 'index_files' must only contain file names!"))));
+        assert_that!(
+            config.clone().with_index_files(vec!(String::new())),
+            is(equal_to(Err("Config value 'index_files' must only contain file names!"))));
+        assert_that!(config.with_index_files(Vec::new()).is_ok(), is(true));
+    }
+
+    #[test]
+    fn with_trusted_proxies_validates_ip_addresses() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap();
+
+        assert_that!(
+            config.clone().with_trusted_proxies(vec!(String::from("127.0.0.1"), String::from("localhost"))),
+            is(equal_to(Err("Config value 'trusted_proxies' must only contain IP addresses!"))));
+        assert_that!(config.clone().with_https_port(0), is(equal_to(Err("Config value 'https_port' must be grater than 0!"))));
+        assert_that!(config.with_trusted_proxies(vec!(String::from("10.0.0.1"), String::from("::1"))).is_ok(), is(true));
+    }
+
+    #[test]
+    fn with_metrics_path_validates_starts_with_slash() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+            .with_metrics_path(String::from("metrics"));
+
+        assert_that!(config, is(equal_to(Err("Config value 'metrics_path' must start with a slash!"))));
+    }
+
+    #[test]
+    fn with_favicon_validates_not_empty() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+            .with_favicon(String::new());
+
+        assert_that!(config, is(equal_to(Err("Config value 'favicon' must not be empty!"))));
+    }
+
+    #[test]
+    fn with_max_body_bytes_validates_not_less_than_one() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+            .with_max_body_bytes(0);
+
+        assert_that!(config, is(equal_to(Err("Config value 'max_body_bytes' must be grater than 0!"))));
+    }
+
+    #[test]
+    fn with_access_log_validates_not_empty() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+            .with_access_log(String::from(""));
+
+        assert_that!(config, is(equal_to(Err("Config value 'access_log' must not be empty!"))));
+    }
+
+    #[test]
+    fn with_log_sample_rate_validates_range() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap();
+
+        assert_that!(config.clone().with_log_sample_rate(0.0).is_ok(), is(true));
+        assert_that!(config.clone().with_log_sample_rate(1.0).is_ok(), is(true));
+        assert_that!(
+            config.clone().with_log_sample_rate(1.5),
+            is(equal_to(Err("Config value 'log_sample_rate' must be between 0.0 and 1.0!"))));
+        assert_that!(
+            config.with_log_sample_rate(-0.1),
+            is(equal_to(Err("Config value 'log_sample_rate' must be between 0.0 and 1.0!"))));
+    }
+
+    #[test]
+    fn with_max_connections_validates_not_less_than_one() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+            .with_max_connections(0);
+
+        assert_that!(config, is(equal_to(Err("Config value 'max_connections' must be grater than 0!"))));
+    }
+
+    #[test]
+    fn with_max_connections_per_ip_validates_not_less_than_one() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+            .with_max_connections_per_ip(0);
+
+        assert_that!(config, is(equal_to(Err("Config value 'max_connections_per_ip' must be grater than 0!"))));
     }
 
     #[test]
@@ -1,4 +1,7 @@
 extern crate webserver;
+#[cfg(test)]
+#[macro_use]
+extern crate hamcrest;
 extern crate flexi_logger;
 #[macro_use]
 extern crate log;
@@ -6,16 +9,36 @@ extern crate log;
 use flexi_logger::{Logger, opt_format};
 
 extern crate clap;
+#[cfg(unix)]
+extern crate libc;
 
+use std::fs;
 use std::process;
+use std::thread;
+use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
 use clap::{Arg, App};
 use std::path::{PathBuf};
 use flexi_logger::FlexiLoggerError;
 use webserver::Config;
 use webserver::server::Server;
+use webserver::reload::{self, ConfigHandle, FileWatcher};
+
+/// How often the reload thread checks whether a SIGHUP was received or the config file changed.
+const RELOAD_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the termination thread checks whether SIGTERM or SIGINT was received.
+const TERMINATION_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Set by the signal handler, cleared by the reload thread.
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Set by the signal handler on SIGTERM or SIGINT.
+static TERMINATION_REQUESTED: AtomicBool = AtomicBool::new(false);
 
 fn main() {
-    let config = read_config().unwrap_or_else(|err| {
+    let (config_file, watch) = arguments();
+    let config = Config::from_file(&config_file).unwrap_or_else(|err| {
         println!("Problem reading config file: {}", err);
         process::exit(1);
     });
@@ -26,14 +49,25 @@ fn main() {
     });
 
     info!("Starting web server ...");
+    if let Some(path) = config.unix_socket() {
+        remove_socket_on_termination(PathBuf::from(path));
+    }
     let server = Server::new(config);
+    // Watches the config file until the server has finished.
+    let _watcher = if watch {
+        Some(reload_on_change(config_file.clone(), server.config_handle()))
+    } else {
+        None
+    };
+    reload_on_sighup(config_file, server.config_handle());
     server.bind().unwrap_or_else(|err| {
         println!("{}", err);
         process::exit(3);
     });
 }
 
-fn read_config() -> Result<Config, &'static str> {
+/// Returns the config file and whether to watch it for changes.
+fn arguments() -> (PathBuf, bool) {
     let matches = App::new(webserver::APPLICATION_DESCRIPTION)
         .version(webserver::APPLICATION_VERSION)
         .author("Sven Strittmatter <ich@weltraumschaf.de>")
@@ -44,25 +78,150 @@ fn read_config() -> Result<Config, &'static str> {
             .takes_value(true)
             .help("Location of configuration file in TOML format.")
             .required(true))
+        .arg(Arg::with_name("watch")
+            .long("watch")
+            .help("Reload the configuration whenever the config file changes."))
         .get_matches();
 
     let config_file = matches.value_of("config").expect("No config file given!");
-    let config_file = PathBuf::from(config_file);
-    Config::from_file(&config_file)
+    (PathBuf::from(config_file), matches.is_present("watch"))
 }
 
+/// Re-reads the config file whenever it is modified on disk, until the returned watcher is dropped.
+fn reload_on_change(config_file: PathBuf, handle: ConfigHandle) -> FileWatcher {
+    info!("Watching {:?} for changes.", config_file);
+
+    reload::watch_file(config_file.clone(), RELOAD_POLL_INTERVAL, move || {
+        info!("Config file {:?} changed, reloading config.", config_file);
+        reload_config(&config_file, &handle);
+    })
+}
+
+/// Re-reads the config file whenever the process receives SIGHUP.
+///
+/// An invalid config file is logged and the running config is kept.
+fn reload_on_sighup(config_file: PathBuf, handle: ConfigHandle) {
+    if !install_sighup_handler() {
+        warn!("Can't install SIGHUP handler. Config reload is disabled.");
+        return;
+    }
+
+    thread::spawn(move || loop {
+        thread::sleep(RELOAD_POLL_INTERVAL);
+
+        if RELOAD_REQUESTED.swap(false, Ordering::SeqCst) {
+            info!("Received SIGHUP, reloading config from {:?}.", config_file);
+            reload_config(&config_file, &handle);
+        }
+    });
+}
+
+/// Applies the config file to the running server. An invalid config file is logged and ignored.
+fn reload_config(config_file: &PathBuf, handle: &ConfigHandle) {
+    match Config::from_file(config_file) {
+        Ok(config) => handle.reload(config),
+        Err(err) => error!("Keeping current config: {}", err),
+    }
+}
+
+/// Removes the socket file before the process exits on SIGTERM or SIGINT.
+///
+/// The signals end the process without dropping the listener, which would leave the file behind.
+fn remove_socket_on_termination(path: PathBuf) {
+    if !install_termination_handler() {
+        warn!("Can't install SIGTERM handler. The socket file {:?} is kept on termination.", path);
+        return;
+    }
+
+    thread::spawn(move || loop {
+        thread::sleep(TERMINATION_POLL_INTERVAL);
+
+        if TERMINATION_REQUESTED.load(Ordering::SeqCst) {
+            info!("Received termination signal, removing socket file {:?}.", path);
+
+            if let Err(err) = fs::remove_file(&path) {
+                warn!("Can't remove socket file {:?}: {}", path, err);
+            }
+
+            process::exit(0);
+        }
+    });
+}
+
+#[cfg(unix)]
+fn install_termination_handler() -> bool {
+    extern "C" fn on_termination(_: libc::c_int) {
+        TERMINATION_REQUESTED.store(true, Ordering::SeqCst);
+    }
+
+    let handler = on_termination as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGTERM, handler) != libc::SIG_ERR && libc::signal(libc::SIGINT, handler) != libc::SIG_ERR
+    }
+}
+
+#[cfg(not(unix))]
+fn install_termination_handler() -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn install_sighup_handler() -> bool {
+    extern "C" fn on_sighup(_: libc::c_int) {
+        RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+    }
+
+    let handler = on_sighup as extern "C" fn(libc::c_int);
+    unsafe { libc::signal(libc::SIGHUP, handler as libc::sighandler_t) != libc::SIG_ERR }
+}
+
+#[cfg(not(unix))]
+fn install_sighup_handler() -> bool {
+    false
+}
+
+/// Logs to a file in the log directory and falls back to stderr if that is not possible, e.g.
+/// because the directory is not writable. Running without log file is better than not running.
 fn setup_logging(config: &Config) -> Result<(), FlexiLoggerError> {
-    let level = config.log_level().clone();
-    let log_config = format!(
-        "warn, {}={}, {}={}",
-        webserver::APPLICATION_NAME, level,
-        webserver::APPLICATION_NAME, level);
+    // Only the messages of this application are logged at the configured level.
+    let log_config = format!("warn, {}={}", webserver::APPLICATION_NAME, config.log_level());
     println!("Use log config: {}", log_config);
-    Logger::with_str(log_config.as_str())
+    let file_logging = Logger::with_str(log_config.as_str())
         .log_to_file()
         .print_message()
         .directory(config.log_dir().clone())
         .duplicate_error()
         .format(opt_format)
-        .start()
+        .start();
+
+    // The logger is only installed if the log file could be opened, so it can be tried again.
+    match file_logging {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            Logger::with_str(log_config.as_str())
+                .format(opt_format)
+                .start()?;
+            warn!("Can't log to directory {}: {}. Logging to stderr instead.", config.log_dir(), err);
+            Ok(())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+
+    #[test]
+    fn setup_logging_falls_back_to_stderr_if_log_dir_is_not_writable() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("test/web_dir"),
+            String::from("debug"),
+            String::from("/proc/webserver/logs")).unwrap();
+
+        assert_that!(setup_logging(&config).is_ok(), is(true));
+    }
 }
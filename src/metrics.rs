@@ -0,0 +1,156 @@
+///! This module provides counters for monitoring the server.
+///!
+///! The counters are rendered in the [Prometheus text format](https://prometheus.io/docs/instrumenting/exposition_formats/).
+
+use std::fmt::Write;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+use http::Status;
+
+/// Content type of the rendered metrics.
+pub const CONTENT_TYPE: &'static str = "text/plain; version=0.0.4";
+
+/// Status classes responses are counted by.
+static STATUS_CLASSES: [&'static str; 5] = ["1xx", "2xx", "3xx", "4xx", "5xx"];
+
+/// Counters updated while serving requests.
+///
+/// The metrics are cheap to clone. All clones share the same counters.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    requests: AtomicUsize,
+    responses_by_class: [AtomicUsize; 5],
+    bytes_served: AtomicU64,
+    active_connections: AtomicUsize,
+    response_time_micros: AtomicU64,
+}
+
+impl Metrics {
+    /// Creates new metrics with all counters at zero.
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Registers an open connection.
+    ///
+    /// The connection is counted as active until the returned guard is dropped.
+    pub fn track_connection(&self) -> ActiveConnection {
+        self.counters.active_connections.fetch_add(1, Ordering::SeqCst);
+        ActiveConnection { counters: Arc::clone(&self.counters) }
+    }
+
+    /// Counts a response with the given status and body length, which took `elapsed` to create.
+    pub fn record(&self, status: &Status, body_bytes: usize, elapsed: Duration) {
+        let counters = &self.counters;
+        counters.requests.fetch_add(1, Ordering::SeqCst);
+        counters.bytes_served.fetch_add(body_bytes as u64, Ordering::SeqCst);
+        counters.response_time_micros.fetch_add(elapsed.as_micros() as u64, Ordering::SeqCst);
+
+        if let Some(index) = status_class_index(status) {
+            counters.responses_by_class[index].fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Renders all counters in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let counters = &self.counters;
+        let requests = counters.requests.load(Ordering::SeqCst);
+        let response_time_micros = counters.response_time_micros.load(Ordering::SeqCst);
+        let average_response_time = if requests == 0 {
+            0.0
+        } else {
+            response_time_micros as f64 / requests as f64 / 1_000_000.0
+        };
+        let mut output = String::new();
+
+        metric(&mut output, "requests_total", "counter", "Total number of requests.");
+        writeln!(output, "{}_requests_total {}", super::APPLICATION_NAME, requests).unwrap();
+        metric(&mut output, "responses_total", "counter", "Number of responses by status class.");
+
+        for (class, count) in STATUS_CLASSES.iter().zip(counters.responses_by_class.iter()) {
+            writeln!(
+                output,
+                "{}_responses_total{{class=\"{}\"}} {}",
+                super::APPLICATION_NAME, class, count.load(Ordering::SeqCst)).unwrap();
+        }
+
+        metric(&mut output, "bytes_served_total", "counter", "Number of response body bytes sent.");
+        writeln!(
+            output,
+            "{}_bytes_served_total {}",
+            super::APPLICATION_NAME, counters.bytes_served.load(Ordering::SeqCst)).unwrap();
+        metric(&mut output, "active_connections", "gauge", "Number of currently open connections.");
+        writeln!(
+            output,
+            "{}_active_connections {}",
+            super::APPLICATION_NAME, counters.active_connections.load(Ordering::SeqCst)).unwrap();
+        metric(&mut output, "response_time_seconds_average", "gauge", "Average time to create a response.");
+        writeln!(
+            output,
+            "{}_response_time_seconds_average {:.6}",
+            super::APPLICATION_NAME, average_response_time).unwrap();
+
+        output
+    }
+}
+
+/// Represents an open connection counted by [`Metrics`](struct.Metrics.html).
+///
+/// The connection is no longer counted when the guard is dropped.
+#[derive(Debug)]
+pub struct ActiveConnection {
+    counters: Arc<Counters>,
+}
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        self.counters.active_connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn metric(output: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(output, "# HELP {}_{} {}", super::APPLICATION_NAME, name, help).unwrap();
+    writeln!(output, "# TYPE {}_{} {}", super::APPLICATION_NAME, name, kind).unwrap();
+}
+
+/// Index of the status class in `STATUS_CLASSES`, taken from the first digit of the status code.
+fn status_class_index(status: &Status) -> Option<usize> {
+    (status.code() as usize / 100)
+        .checked_sub(1)
+        .filter(|&index| index < STATUS_CLASSES.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+
+    #[test]
+    fn record_counts_responses_by_status_class() {
+        let sut = Metrics::new();
+        let clone = sut.clone();
+
+        sut.record(&Status::Ok, 100, Duration::from_millis(2));
+        sut.record(&Status::NotFound, 10, Duration::from_millis(4));
+        let connection = sut.track_connection();
+
+        let rendered = clone.render();
+        assert_that!(rendered.contains("\nwebserver_requests_total 2\n"), is(true));
+        assert_that!(rendered.contains("\nwebserver_responses_total{class=\"2xx\"} 1\n"), is(true));
+        assert_that!(rendered.contains("\nwebserver_responses_total{class=\"4xx\"} 1\n"), is(true));
+        assert_that!(rendered.contains("\nwebserver_responses_total{class=\"5xx\"} 0\n"), is(true));
+        assert_that!(rendered.contains("\nwebserver_bytes_served_total 110\n"), is(true));
+        assert_that!(rendered.contains("\nwebserver_active_connections 1\n"), is(true));
+        assert_that!(rendered.contains("\nwebserver_response_time_seconds_average 0.003000\n"), is(true));
+
+        drop(connection);
+
+        assert_that!(sut.render().contains("\nwebserver_active_connections 0\n"), is(true));
+    }
+}
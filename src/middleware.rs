@@ -0,0 +1,98 @@
+///! This module provides middlewares wrapping the handling of requests.
+///!
+///! A middleware gets the context of a request and the rest of the chain. It may respond itself,
+///! e.g. to reject unauthorized requests, or run the rest of the chain and change its response.
+///! Middlewares run in the order they are registered, the first one is the outermost.
+
+use context::{Context, Handler};
+use http::Response;
+
+/// Wraps the handling of a request, calling [`Next::run`](struct.Next.html#method.run) to continue.
+pub type Middleware = fn(&mut Context, Next) -> Response;
+
+/// The rest of a middleware chain: the middlewares not run yet and the handler at its end.
+#[derive(Clone, Copy)]
+pub struct Next<'m> {
+    middlewares: &'m [Middleware],
+    handler: Handler,
+}
+
+impl<'m> Next<'m> {
+    /// Creates a chain running the middlewares in order around the handler.
+    pub fn new(middlewares: &'m [Middleware], handler: Handler) -> Next<'m> {
+        Next { middlewares, handler }
+    }
+
+    /// Runs the next middleware, or the handler if all middlewares ran.
+    pub fn run(self, context: &mut Context) -> Response {
+        match self.middlewares.split_first() {
+            Some((middleware, middlewares)) => middleware(context, Next { middlewares, ..self }),
+            None => context.handle(self.handler),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+    use Config;
+    use assets::AssetSource;
+    use http::{self, ResponseHeader, Status};
+
+    fn hello(_context: &mut Context) -> Response {
+        Response::new(http::VERSION.to_string(), Status::Ok, b"Hello".to_vec())
+    }
+
+    fn outer(context: &mut Context, next: Next) -> Response {
+        let mut response = next.run(context);
+        response.add_header(ResponseHeader::Custom(String::from("X-Outer"), String::from("1")));
+        response
+    }
+
+    fn inner(context: &mut Context, next: Next) -> Response {
+        let mut response = next.run(context);
+        response.add_header(ResponseHeader::Custom(String::from("X-Inner"), String::from("2")));
+        response
+    }
+
+    fn deny(_context: &mut Context, _next: Next) -> Response {
+        Response::new(http::VERSION.to_string(), Status::Forbidden, Vec::new())
+    }
+
+    fn run(middlewares: &[Middleware]) -> String {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("test/web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap();
+        let raw_request = "GET / HTTP/1.1\r\nHost: localhost:8080\r\n";
+        let request = http::parse_request(raw_request).unwrap();
+        let assets = AssetSource::FileSystem;
+        let mut context = Context::new(config, &assets, request, raw_request, "127.0.0.1:50000".parse().unwrap());
+
+        let rendered = Next::new(middlewares, hello).run(&mut context).render();
+        String::from_utf8_lossy(&rendered).into_owned()
+    }
+
+    #[test]
+    fn middlewares_wrap_handler_in_registration_order() {
+        assert_that!(
+            run(&[outer, inner]),
+            is(equal_to(String::from("HTTP/1.1 200 OK\r\nX-Inner: 2\r\nX-Outer: 1\r\nContent-Length: 5\r\n\r\nHello"))));
+    }
+
+    #[test]
+    fn middleware_may_respond_without_running_the_rest_of_the_chain() {
+        assert_that!(
+            run(&[outer, deny, inner]),
+            is(equal_to(String::from("HTTP/1.1 403 FORBIDDEN\r\nX-Outer: 1\r\nContent-Length: 0\r\n\r\n"))));
+    }
+
+    #[test]
+    fn empty_chain_runs_handler() {
+        assert_that!(run(&[]), is(equal_to(String::from("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nHello"))));
+    }
+}
@@ -0,0 +1,237 @@
+///! This module forwards requests to upstream HTTP servers and relays their responses.
+///!
+///! The upstream request uses HTTP/1.0 and closes the connection, so the response body simply
+///! ends with the connection and is never chunked. The response is read completely before it is
+///! relayed, so it may not be larger than [`MAX_UPSTREAM_RESPONSE_BYTES`](constant.MAX_UPSTREAM_RESPONSE_BYTES.html).
+
+use std::io;
+use std::io::prelude::*;
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+use Proxy;
+use http::{self, Method, Request, Response, ResponseHeader};
+
+/// Time to wait for the upstream server to accept, read or answer the request.
+pub const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(30);
+/// Maximum size of an upstream response in bytes, larger responses are answered with `502`.
+pub const MAX_UPSTREAM_RESPONSE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Headers set by the proxy itself, in addition to the hop-by-hop headers it drops.
+static PROXY_HEADERS: [&'static str; 5] = ["host", "content-length", "x-forwarded-for", "x-forwarded-proto", "server"];
+
+/// Forwards the request from `peer` to the upstream server of the proxy and returns its response.
+///
+/// Fails if the URL is not below the prefix of the proxy, or the upstream server can't be
+/// reached, sends no valid response or a response larger than the maximum size.
+pub fn forward(proxy: &Proxy, request: &Request, peer: &SocketAddr) -> io::Result<Response> {
+    let target = proxy.upstream_target(&request.path_and_query())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "URL is not below the proxy prefix"))?;
+    let address = proxy.upstream_address();
+    debug!("Forwarding {} {} to http://{}{}", request.method(), request.url(), address, target);
+
+    let mut upstream = TcpStream::connect(&address)?;
+    upstream.set_read_timeout(Some(UPSTREAM_TIMEOUT))?;
+    upstream.set_write_timeout(Some(UPSTREAM_TIMEOUT))?;
+    upstream.write_all(&upstream_request(proxy, &target, request, peer))?;
+
+    let raw = read_upstream_response(upstream, MAX_UPSTREAM_RESPONSE_BYTES)?;
+
+    relay_response(&raw, Method::from_token(request.method()) == Method::Head)
+}
+
+/// Reads the upstream response until the upstream server closes the connection.
+///
+/// Fails without reading further if the response is larger than `max_bytes`.
+fn read_upstream_response<R: Read>(upstream: R, max_bytes: usize) -> io::Result<Vec<u8>> {
+    let mut raw = Vec::new();
+    upstream.take(max_bytes as u64 + 1).read_to_end(&mut raw)?;
+
+    if raw.len() > max_bytes {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Upstream response exceeds maximum size"));
+    }
+
+    Ok(raw)
+}
+
+/// Whether the header is dropped when forwarding, because it is hop-by-hop or set by the proxy.
+fn is_skipped(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    http::HOP_BY_HOP_HEADERS.contains(&name.as_str()) || PROXY_HEADERS.contains(&name.as_str())
+}
+
+/// Renders the request sent to the upstream server.
+fn upstream_request(proxy: &Proxy, target: &str, request: &Request, peer: &SocketAddr) -> Vec<u8> {
+    let mut forwarded = request.clone();
+
+    // Connection options name further headers which only apply to the connection to the proxy.
+    for option in request.connection().split(',') {
+        forwarded.remove_header(option.trim());
+    }
+
+    for name in http::HOP_BY_HOP_HEADERS.iter().chain(PROXY_HEADERS.iter()) {
+        forwarded.remove_header(name);
+    }
+
+    let forwarded_for = match request.header("X-Forwarded-For") {
+        Some(previous) => format!("{}, {}", previous, peer.ip()),
+        None => peer.ip().to_string(),
+    };
+    forwarded.set_header("X-Forwarded-For", &forwarded_for);
+    forwarded.set_header("X-Forwarded-Proto", "http");
+
+    let mut head = format!("{} {} HTTP/1.0\r\nHost: {}\r\n", request.method(), target, proxy.upstream_authority());
+
+    for (name, value) in forwarded.headers() {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+
+    if !request.body().is_empty() {
+        head.push_str(&format!("Content-Length: {}\r\n", request.body().len()));
+    }
+
+    head.push_str("Connection: close\r\n\r\n");
+    let mut rendered = head.into_bytes();
+    rendered.extend_from_slice(request.body());
+    rendered
+}
+
+/// Splits a response header into its lower case name and its value.
+fn name_and_value(header: &ResponseHeader) -> (String, String) {
+    let rendered = header.to_string();
+    let mut parts = rendered.splitn(2, ':');
+    let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+    let value = parts.next().unwrap_or("").trim().to_string();
+    (name, value)
+}
+
+/// Creates the response relayed to the client from the raw upstream response.
+///
+/// The body length is counted again, because the upstream body ends with its connection.
+/// Only the response to a `HEAD` request keeps the upstream length, as it has no body.
+/// Hop-by-hop headers are dropped, including the ones the upstream `Connection` header names.
+fn relay_response(raw: &[u8], head_request: bool) -> io::Result<Response> {
+    let head_end = raw.windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Upstream response has no end of headers"))?;
+    let head: String = raw[..head_end].iter().map(|&byte| byte as char).collect();
+    let upstream = http::parse_response(&head)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+    let body = raw[head_end + 4..].to_vec();
+    let content_length = body.len();
+    let mut response = Response::new(http::VERSION.to_string(), upstream.status().clone(), body);
+    // Connection options name further headers which only apply to the connection to the proxy.
+    let mut connection_options = Vec::new();
+
+    for (name, value) in upstream.headers().iter().map(name_and_value) {
+        if name == "connection" {
+            connection_options.extend(value.split(',').map(|option| option.trim().to_ascii_lowercase()));
+        }
+    }
+
+    for header in upstream.headers() {
+        let (name, _) = name_and_value(header);
+
+        if connection_options.contains(&name) {
+            continue;
+        }
+
+        if !is_skipped(&name) || (head_request && name == "content-length") {
+            response.add_header(header.clone());
+        }
+    }
+
+    if head_request {
+        response.mark_length_decided();
+    } else {
+        response.add_header(ResponseHeader::ContentLength(content_length));
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+
+    fn proxy() -> Proxy {
+        Proxy::new(String::from("/api"), String::from("http://localhost:3000/v1")).unwrap()
+    }
+
+    #[test]
+    fn upstream_request_skips_connection_headers_and_adds_forwarded_headers() {
+        let request = http::parse_request(
+            "POST /api/items HTTP/1.1\r\nHost: example.com\r\nConnection: keep-alive, X-Hop\r\nX-Hop: 1\r\n\
+             Accept: */*\r\nX-Forwarded-For: 10.0.0.1\r\nContent-Length: 5\r\n").unwrap()
+            .with_body(b"hello".to_vec());
+
+        let rendered = upstream_request(&proxy(), "/v1/items", &request, &"192.168.0.7:50123".parse().unwrap());
+
+        assert_that!(
+            String::from_utf8(rendered).unwrap(),
+            is(equal_to(String::from(
+                "POST /v1/items HTTP/1.0\r\nHost: localhost:3000\r\nAccept: */*\r\n\
+                 X-Forwarded-For: 10.0.0.1, 192.168.0.7\r\nX-Forwarded-Proto: http\r\nContent-Length: 5\r\n\
+                 Connection: close\r\n\r\nhello"))));
+    }
+
+    #[test]
+    fn upstream_request_removes_all_hop_by_hop_headers() {
+        let hop_by_hop_headers: String = http::HOP_BY_HOP_HEADERS.iter()
+            .map(|name| format!("{}: 1\r\n", name))
+            .collect();
+        let request = http::parse_request(
+            &format!("GET /api/items HTTP/1.1\r\nHost: example.com\r\n{}Accept: */*\r\n", hop_by_hop_headers)).unwrap();
+
+        let rendered = upstream_request(&proxy(), "/v1/items", &request, &"192.168.0.7:50123".parse().unwrap());
+
+        assert_that!(
+            String::from_utf8(rendered).unwrap(),
+            is(equal_to(String::from(
+                "GET /v1/items HTTP/1.0\r\nHost: localhost:3000\r\nAccept: */*\r\n\
+                 X-Forwarded-For: 192.168.0.7\r\nX-Forwarded-Proto: http\r\nConnection: close\r\n\r\n"))));
+    }
+
+    #[test]
+    fn relay_response_counts_body_and_drops_connection_headers() {
+        let raw = b"HTTP/1.1 201 Created\r\nServer: upstream\r\nConnection: close\r\nContent-Type: text/plain\r\n\r\nCreated!";
+
+        let rendered = relay_response(raw, false).unwrap().render();
+
+        assert_that!(
+            String::from_utf8(rendered).unwrap(),
+            is(equal_to(String::from(
+                "HTTP/1.1 201 CREATED\r\nContent-Type: text/plain\r\nContent-Length: 8\r\n\r\nCreated!"))));
+        assert_that!(relay_response(b"HTTP/1.1 200 OK\r\n", false).is_err(), is(true));
+    }
+
+    #[test]
+    fn relay_response_drops_headers_named_by_connection_header() {
+        let raw = b"HTTP/1.1 200 OK\r\nConnection: X-Hop, x-other\r\nX-Hop: 1\r\nX-Other: 2\r\nX-Kept: 3\r\n\r\nok";
+
+        let rendered = relay_response(raw, false).unwrap().render();
+
+        assert_that!(
+            String::from_utf8(rendered).unwrap(),
+            is(equal_to(String::from("HTTP/1.1 200 OK\r\nX-Kept: 3\r\nContent-Length: 2\r\n\r\nok"))));
+    }
+
+    #[test]
+    fn relay_response_keeps_head_response_without_upstream_length() {
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\n";
+
+        let rendered = relay_response(raw, true).unwrap().render();
+
+        assert_that!(
+            String::from_utf8(rendered).unwrap(),
+            is(equal_to(String::from("HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\n"))));
+    }
+
+    #[test]
+    fn read_upstream_response_fails_above_maximum_size() {
+        let raw = b"HTTP/1.1 200 OK\r\n\r\n0123456789";
+
+        assert_that!(read_upstream_response(&raw[..], raw.len()).unwrap().len(), is(equal_to(raw.len())));
+        assert_that!(read_upstream_response(&raw[..], raw.len() - 1).is_err(), is(true));
+    }
+}
@@ -0,0 +1,235 @@
+///! This module provides a token bucket rate limiter keyed by client IP.
+///!
+///! Besides the limit for all requests, URL path prefixes may have their own limits. Each of them
+///! has its own buckets, so requests to one route never wait for the lock of another.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use {Config, RouteLimit};
+
+/// Number of tracked clients above which idle buckets are pruned.
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Limits the request rate of each client IP with a token bucket.
+///
+/// Each client may send up to `burst` requests at once. The bucket refills with `requests_per_second`
+/// tokens per second. The limiter is cheap to clone. All clones share the same buckets.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    /// Tokens added to each bucket per second.
+    requests_per_second: u32,
+    /// Capacity of each bucket.
+    burst: u32,
+    /// Buckets shared across all threads.
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a new rate limiter.
+    ///
+    /// # Panics
+    ///
+    /// The `new` function will panic if the requests per second or the burst is zero.
+    pub fn new(requests_per_second: u32, burst: u32) -> RateLimiter {
+        assert!(requests_per_second > 0);
+        assert!(burst > 0);
+
+        RateLimiter {
+            requests_per_second,
+            burst,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Takes a token for a request from the given client IP.
+    ///
+    /// Returns the time the client should wait before retrying if it is over the limit.
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        self.check_at(ip, Instant::now())
+    }
+
+    fn check_at(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() > PRUNE_THRESHOLD {
+            let rate = f64::from(self.requests_per_second);
+            let burst = f64::from(self.burst);
+            buckets.retain(|_, bucket| bucket.refilled(now, rate, burst) < burst);
+        }
+
+        let burst = f64::from(self.burst);
+        let bucket = buckets.entry(ip).or_insert(Bucket { tokens: burst, last_refill: now });
+        bucket.refill(now, f64::from(self.requests_per_second), burst);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let missing = 1.0 - bucket.tokens;
+            let wait = (missing / f64::from(self.requests_per_second)).ceil() as u64;
+            debug!("Rate limit exceeded by {}.", ip);
+            Err(Duration::from_secs(wait.max(1)))
+        }
+    }
+
+    /// Gives back the token taken for a request from the given client IP which was not served.
+    fn refund(&self, ip: IpAddr) {
+        let burst = f64::from(self.burst);
+
+        if let Some(bucket) = self.buckets.lock().unwrap().get_mut(&ip) {
+            bucket.tokens = (bucket.tokens + 1.0).min(burst);
+        }
+    }
+}
+
+/// All rate limits configured for the server.
+///
+/// The limits are cheap to clone. All clones share the same buckets.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimits {
+    /// Limit for all requests, if any.
+    global: Option<RateLimiter>,
+    /// Limits for URL path prefixes in the order they are consulted.
+    routes: Vec<(RouteLimit, RateLimiter)>,
+}
+
+impl RateLimits {
+    /// Creates the rate limits configured in the config.
+    pub fn from_config(config: &Config) -> RateLimits {
+        RateLimits {
+            global: config.rate_limit()
+                .map(|(per_second, burst)| RateLimiter::new(per_second, burst)),
+            routes: config.route_limits().iter()
+                .map(|route| (route.clone(), RateLimiter::new(route.per_second(), route.burst())))
+                .collect(),
+        }
+    }
+
+    /// Takes a token for a request from the given client IP to the given URL path, if known.
+    ///
+    /// The path should be [normalized](../http/fn.normalize_path.html), so that other spellings
+    /// of a path can't bypass its limit. The first route limit matching the path is checked
+    /// first, then the limit for all requests. A request rejected by one limit takes no token
+    /// from the other. Returns the time the client should wait before retrying if it is over a limit.
+    pub fn check(&self, ip: IpAddr, path: Option<&str>) -> Result<(), Duration> {
+        let route = path.and_then(|path| self.routes.iter().find(|&(route, _)| route.matches(path)));
+
+        if let Some((route, limiter)) = route {
+            limiter.check(ip).inspect_err(|_| {
+                debug!("Rate limit for {} exceeded by {}.", route.prefix(), ip);
+            })?;
+        }
+
+        match self.global {
+            Some(ref global) => global.check(ip).inspect_err(|_| {
+                if let Some((_, limiter)) = route {
+                    limiter.refund(ip);
+                }
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Bucket {
+    fn refilled(&self, now: Instant, rate: f64, burst: f64) -> f64 {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        (self.tokens + elapsed * rate).min(burst)
+    }
+
+    fn refill(&mut self, now: Instant, rate: f64, burst: f64) {
+        self.tokens = self.refilled(now, rate, burst);
+        self.last_refill = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn rapid_requests_from_one_ip_hit_the_limit() {
+        let sut = RateLimiter::new(1, 3);
+        let now = Instant::now();
+
+        assert_that!(sut.check_at(ip("10.0.0.1"), now), is(equal_to(Ok(()))));
+        assert_that!(sut.check_at(ip("10.0.0.1"), now), is(equal_to(Ok(()))));
+        assert_that!(sut.check_at(ip("10.0.0.1"), now), is(equal_to(Ok(()))));
+        assert_that!(sut.check_at(ip("10.0.0.1"), now), is(equal_to(Err(Duration::from_secs(1)))));
+
+        assert_that!(sut.check_at(ip("10.0.0.2"), now), is(equal_to(Ok(()))));
+    }
+
+    #[test]
+    fn exceeding_a_route_limit_leaves_other_routes_unaffected() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+            .with_route_limit(RouteLimit::new(String::from("/search"), 1, Some(2)).unwrap());
+        let sut = RateLimits::from_config(&config);
+
+        assert_that!(sut.check(ip("10.0.0.1"), Some("/search?q=a")), is(equal_to(Ok(()))));
+        assert_that!(sut.check(ip("10.0.0.1"), Some("/search?q=b")), is(equal_to(Ok(()))));
+        assert_that!(sut.check(ip("10.0.0.1"), Some("/search?q=c")).is_err(), is(true));
+
+        assert_that!(sut.check(ip("10.0.0.2"), Some("/search")), is(equal_to(Ok(()))));
+        for _ in 0..10 {
+            assert_that!(sut.check(ip("10.0.0.1"), Some("/index.html")), is(equal_to(Ok(()))));
+        }
+        assert_that!(sut.check(ip("10.0.0.1"), None), is(equal_to(Ok(()))));
+    }
+
+    #[test]
+    fn rejected_requests_take_no_token_from_other_limit() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+            .with_rate_limit(1, 2).unwrap()
+            .with_route_limit(RouteLimit::new(String::from("/search"), 1, Some(1)).unwrap());
+        let sut = RateLimits::from_config(&config);
+
+        assert_that!(sut.check(ip("10.0.0.1"), Some("/search")), is(equal_to(Ok(()))));
+        assert_that!(sut.check(ip("10.0.0.1"), Some("/search")).is_err(), is(true));
+        assert_that!(sut.check(ip("10.0.0.1"), Some("/index.html")), is(equal_to(Ok(()))));
+
+        let sut = RateLimits::from_config(&config.with_route_limit(RouteLimit::new(String::from("/api"), 1, Some(2)).unwrap()));
+
+        assert_that!(sut.check(ip("10.0.0.1"), Some("/index.html")), is(equal_to(Ok(()))));
+        assert_that!(sut.check(ip("10.0.0.1"), Some("/index.html")), is(equal_to(Ok(()))));
+        assert_that!(sut.check(ip("10.0.0.1"), Some("/api")).is_err(), is(true));
+        assert_that!(sut.routes[1].1.buckets.lock().unwrap()[&ip("10.0.0.1")].tokens, is(equal_to(2.0)));
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let sut = RateLimiter::new(2, 1);
+        let now = Instant::now();
+
+        assert_that!(sut.check_at(ip("10.0.0.1"), now), is(equal_to(Ok(()))));
+        assert_that!(sut.check_at(ip("10.0.0.1"), now).is_err(), is(true));
+        assert_that!(
+            sut.check_at(ip("10.0.0.1"), now + Duration::from_millis(500)),
+            is(equal_to(Ok(()))));
+    }
+}
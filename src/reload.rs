@@ -0,0 +1,311 @@
+///! This module provides a configuration which can be reloaded while the server is running.
+///!
+///! All settings used to respond to requests are replaced on reload, e.g. the web root directory,
+///! the proxies or the timeouts. The settings of the listening socket, the thread pool, the logger,
+///! the access log and the connection and rate limits stay as they were when the server started.
+///! Changes of these are logged.
+///!
+///! A reload is triggered by the application, e.g. on SIGHUP or when [`watch_file`](fn.watch_file.html)
+///! notices a change of the config file.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+use Config;
+
+/// Handle to the configuration shared by all connections.
+///
+/// The handle is cheap to clone. All clones share the same configuration.
+#[derive(Debug, Clone)]
+pub struct ConfigHandle {
+    config: Arc<RwLock<Config>>,
+}
+
+impl ConfigHandle {
+    /// Creates a new handle holding the given configuration.
+    pub fn new(config: Config) -> ConfigHandle {
+        ConfigHandle { config: Arc::new(RwLock::new(config)) }
+    }
+
+    /// Get a snapshot of the current configuration.
+    ///
+    /// Connections take a snapshot for each request, so a reload never changes the settings
+    /// in the middle of a response.
+    pub fn current(&self) -> Config {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Replaces the settings which may change at runtime by the ones of the given configuration.
+    ///
+    /// The given configuration is expected to be validated already.
+    pub fn reload(&self, reloaded: Config) {
+        let mut config = self.config.write().unwrap();
+
+        for name in restart_required(&config, &reloaded) {
+            warn!("Config value '{}' changed, ignoring it until the server is restarted.", name);
+        }
+
+        info!("Reloading config: web_dir = {}, {} mounts.", reloaded.web_dir, reloaded.mounts.len());
+        *config = keep_startup_settings(&config, reloaded);
+    }
+}
+
+/// Watches a file from a background thread, see [`watch_file`](fn.watch_file.html).
+///
+/// Watching stops when the watcher is stopped or dropped.
+#[derive(Debug)]
+pub struct FileWatcher {
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FileWatcher {
+    /// Stops watching and waits until the background thread has finished.
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+
+            if thread.join().is_err() {
+                warn!("Watching a file panicked!");
+            }
+        }
+    }
+}
+
+/// Calls `on_change` from a background thread whenever the file is modified.
+///
+/// The modification time and size of the file are polled. Successive writes are debounced: the
+/// callback is called once the file did not change for a whole poll interval. Changes are
+/// noticed from the call on, until the returned watcher is stopped or dropped.
+pub fn watch_file<F>(path: PathBuf, poll_interval: Duration, on_change: F) -> FileWatcher where F: Fn() + Send + 'static {
+    let stopped = Arc::new(AtomicBool::new(false));
+    let stop_requested = stopped.clone();
+    let mut seen = file_state(&path);
+
+    let thread = thread::spawn(move || {
+        let mut pending = false;
+
+        loop {
+            thread::park_timeout(poll_interval);
+
+            if stop_requested.load(Ordering::SeqCst) {
+                break;
+            }
+
+            let current = file_state(&path);
+
+            if current != seen {
+                seen = current;
+                pending = true;
+            } else if pending {
+                pending = false;
+                on_change();
+            }
+        }
+    });
+
+    FileWatcher { stopped, thread: Some(thread) }
+}
+
+/// Modification time and size of the file, or nothing if it can't be read.
+fn file_state(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    metadata.modified().ok().map(|modified| (modified, metadata.len()))
+}
+
+/// Takes the reloaded configuration, but keeps the settings which are only applied when the server starts.
+///
+/// Must keep the same settings [`restart_required`](fn.restart_required.html) names.
+fn keep_startup_settings(current: &Config, reloaded: Config) -> Config {
+    Config {
+        address: current.address.clone(),
+        port: current.port,
+        unix_socket: current.unix_socket.clone(),
+        listen_backlog: current.listen_backlog,
+        tcp_nodelay: current.tcp_nodelay,
+        threads: current.threads,
+        worker_stack_size: current.worker_stack_size,
+        log_level: current.log_level.clone(),
+        log_dir: current.log_dir.clone(),
+        access_log: current.access_log.clone(),
+        max_connections: current.max_connections,
+        max_connections_per_ip: current.max_connections_per_ip,
+        rate_limit_per_second: current.rate_limit_per_second,
+        rate_limit_burst: current.rate_limit_burst,
+        route_limits: current.route_limits.clone(),
+        ..reloaded
+    }
+}
+
+/// Names of the changed settings which are only applied when the server starts.
+///
+/// These configure the listening socket, the thread pool, the logger, the access log or the
+/// connection and rate limits.
+fn restart_required(current: &Config, reloaded: &Config) -> Vec<&'static str> {
+    let mut names = Vec::new();
+
+    if current.address != reloaded.address {
+        names.push("address");
+    }
+    if current.port != reloaded.port {
+        names.push("port");
+    }
+    if current.unix_socket != reloaded.unix_socket {
+        names.push("unix_socket");
+    }
+    if current.listen_backlog != reloaded.listen_backlog {
+        names.push("listen_backlog");
+    }
+    if current.threads != reloaded.threads {
+        names.push("threads");
+    }
+    if current.worker_stack_size != reloaded.worker_stack_size {
+        names.push("worker_stack_size");
+    }
+    if current.log_level != reloaded.log_level {
+        names.push("log_level");
+    }
+    if current.log_dir != reloaded.log_dir {
+        names.push("log_dir");
+    }
+    if current.tcp_nodelay != reloaded.tcp_nodelay {
+        names.push("tcp_nodelay");
+    }
+    if current.access_log != reloaded.access_log {
+        names.push("access_log");
+    }
+    if current.max_connections != reloaded.max_connections {
+        names.push("max_connections");
+    }
+    if current.max_connections_per_ip != reloaded.max_connections_per_ip {
+        names.push("max_connections_per_ip");
+    }
+    if current.rate_limit_per_second != reloaded.rate_limit_per_second {
+        names.push("rate_limit_per_second");
+    }
+    if current.rate_limit_burst != reloaded.rate_limit_burst {
+        names.push("rate_limit_burst");
+    }
+    if current.route_limits != reloaded.route_limits {
+        names.push("route_limits");
+    }
+
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+    use std::env;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Instant;
+    use {Mount, Proxy, RouteLimit};
+
+    fn config(port: u16, web_dir: &str) -> Config {
+        Config::new(
+            String::from("127.0.0.1"),
+            port,
+            4,
+            String::from(web_dir),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+    }
+
+    #[test]
+    fn reload_replaces_runtime_settings_only() {
+        let sut = ConfigHandle::new(config(8080, "web_dir"));
+        let clone = sut.clone();
+        let reloaded = config(9090, "other_dir")
+            .with_mount(Mount::new(String::from("/static"), String::from("static_dir")).unwrap())
+            .with_proxy(Proxy::new(String::from("/api"), String::from("http://127.0.0.1:3000")).unwrap())
+            .with_force_https(true)
+            .with_max_body_bytes(16).unwrap()
+            .with_read_timeout(7).unwrap()
+            .with_redacted_headers(vec!(String::from("X-Api-Key")))
+            .with_spa_fallback(String::from("index.html")).unwrap()
+            .with_rate_limit(1, 2).unwrap()
+            .with_route_limit(RouteLimit::new(String::from("/search"), 1, None).unwrap())
+            .with_max_connections(10).unwrap();
+
+        sut.reload(reloaded);
+
+        let current = clone.current();
+        assert_that!(current.web_dir().as_str(), is(equal_to("other_dir")));
+        assert_that!(current.mounts().len(), is(equal_to(1)));
+        assert_that!(current.proxies().len(), is(equal_to(1)));
+        assert_that!(current.force_https(), is(equal_to(&true)));
+        assert_that!(current.max_body_bytes(), is(equal_to(&16)));
+        assert_that!(current.read_timeout(), is(equal_to(&7)));
+        assert_that!(current.redacted_headers().join(","), is(equal_to(String::from("X-Api-Key"))));
+        assert_that!(current.spa_fallback().is_some(), is(true));
+        assert_that!(current.port(), is(equal_to(&8080)));
+        assert_that!(current.rate_limit(), is(equal_to(None)));
+        assert_that!(current.route_limits().is_empty(), is(true));
+        assert_that!(current.max_connections(), is(equal_to(None)));
+    }
+
+    #[test]
+    fn restart_required_names_changed_startup_settings() {
+        let current = config(8080, "web_dir");
+
+        assert_that!(restart_required(&current, &config(8080, "other_dir")).is_empty(), is(true));
+        assert_that!(restart_required(&current, &config(9090, "other_dir")), is(equal_to(vec!["port"])));
+        assert_that!(
+            restart_required(&current, &config(8080, "web_dir").with_rate_limit(1, 2).unwrap().with_tcp_nodelay(false)),
+            is(equal_to(vec!["tcp_nodelay", "rate_limit_per_second", "rate_limit_burst"])));
+    }
+
+    /// Polls the condition until it holds, or fails after a few seconds.
+    fn wait_until<F: Fn() -> bool>(condition: F) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        while !condition() {
+            if Instant::now() > deadline {
+                return false;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        true
+    }
+
+    #[test]
+    fn watch_file_calls_back_once_for_successive_writes() {
+        let path = env::temp_dir().join(format!("webserver-watch-{}.toml", std::process::id()));
+        fs::write(&path, "port = 8080\n").unwrap();
+        let changes = Arc::new(AtomicUsize::new(0));
+        let counter = changes.clone();
+
+        let sut = watch_file(path.clone(), Duration::from_millis(50), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        fs::write(&path, "port = 8081\n").unwrap();
+        fs::write(&path, "port = 8082\nthreads = 4\n").unwrap();
+
+        assert_that!(wait_until(|| changes.load(Ordering::SeqCst) >= 1), is(true));
+
+        fs::write(&path, "port = 8083\n").unwrap();
+
+        assert_that!(wait_until(|| changes.load(Ordering::SeqCst) >= 2), is(true));
+
+        sut.stop();
+        fs::write(&path, "port = 8084\nthreads = 2\n").unwrap();
+        thread::sleep(Duration::from_millis(150));
+
+        assert_that!(changes.load(Ordering::SeqCst), is(equal_to(2)));
+        fs::remove_file(&path).unwrap();
+    }
+}
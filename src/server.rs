@@ -25,305 +25,3752 @@
 ///! });
 ///! ```
 
+use std::error;
+use std::fmt;
+use std::io;
 use std::io::prelude::*;
+use std::mem;
+use std::str;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::net::TcpListener;
-use std::net::TcpStream;
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use std::fs;
+use std::fs::OpenOptions;
+use std::net::SocketAddr;
 use time;
-use Config;
+use log::Level;
+#[cfg(unix)]
+use libc;
+use {Config, InvalidUtf8, TrailingSlash};
 use file;
+use gzip::{self, GzipError};
+use chunked::{self, ChunkedError};
 use threads::ThreadPool;
+use connections::ConnectionLimiter;
+use rate_limit::RateLimits;
+use access_log::{self, AccessLog, AccessLogger};
+use reload::ConfigHandle;
+use assets::{self, Asset, AssetSource};
+use metrics::{self, Metrics};
+use context::{Context, FallibleHandler, Handler};
+use stream::{self, ReplayStream, Stream};
+use websocket;
+use proxy;
+use middleware::{Middleware, Next};
+#[cfg(unix)]
+use stream::UnixSocket;
 use http;
-use http::{Request, Response, ResponseHeader, Status};
+use http::{ByteRange, Method, ParseError, Request, RequestTarget, Response, ResponseHeader, Status};
+
+/// Served for `/` if enabled and there are no static assets, e.g. the web root directory is empty.
+static WELCOME_PAGE: &'static str = "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+    <title>Welcome!</title>
+    <meta http-equiv=\"content-type\" content=\"text/html; charset=utf-8\"/>
+</head>
+<body>
+<h1>It works!</h1>
+<p>The webserver is running, but there is no content in the web root directory yet.</p>
+</body>
+</html>
+";
+
+/// URL path browsers request the site icon from.
+const FAVICON_URL: &'static str = "/favicon.ico";
+/// Seconds clients may cache the configured favicon: one week.
+const FAVICON_MAX_AGE: u64 = 7 * 24 * 60 * 60;
+/// Format of [HTTP dates](https://tools.ietf.org/html/rfc7231#section-7.1.1.1) in UTC.
+const HTTP_DATE_FORMAT: &'static str = "%a, %d %b %Y %H:%M:%S GMT";
+/// Milliseconds to wait before accepting again if the process ran out of resources, e.g. file descriptors.
+const ACCEPT_BACKOFF_MILLIS: u64 = 100;
+
+/// Error returned when the server can't serve.
+#[derive(Debug)]
+pub enum ServerError {
+    /// The TCP listener can't be bound to the configured address.
+    Bind(io::Error),
+    /// The access log file can't be opened.
+    AccessLog(io::Error),
+    /// The worker threads can't be spawned.
+    ThreadPool(&'static str),
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ServerError::Bind(ref err) => write!(f, "Can't bind TCP listener on address: {}", err),
+            ServerError::AccessLog(ref err) => write!(f, "Can't open access log file: {}", err),
+            ServerError::ThreadPool(message) => write!(f, "Can't start thread pool: {}", message),
+        }
+    }
+}
+
+impl error::Error for ServerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ServerError::Bind(ref err) | ServerError::AccessLog(ref err) => Some(err),
+            ServerError::ThreadPool(_) => None,
+        }
+    }
+}
 
 /// Represents the HTTP server.
 pub struct Server {
     /// Configuration of the server,
     config: Config,
+    /// Configuration used to respond to requests, may be reloaded while running.
+    handle: ConfigHandle,
+    /// Source of the static assets.
+    assets: AssetSource,
+    /// Counters for monitoring, updated while serving requests.
+    metrics: Metrics,
+    /// Takes over connections switched to the WebSocket protocol, if any.
+    websocket_handler: Option<websocket::Handler>,
+    /// Responds to requests matching no static asset instead of the built-in 404, if any.
+    not_found_handler: Option<FallibleHandler>,
+    /// Wraps the handling of every request, in order.
+    middlewares: Vec<Middleware>,
+}
+
+/// Everything a connection is served with besides its configuration.
+#[derive(Clone)]
+struct Services {
+    assets: AssetSource,
+    rate_limits: RateLimits,
+    access_logger: Option<AccessLogger>,
+    metrics: Metrics,
+    websocket_handler: Option<websocket::Handler>,
+    not_found_handler: Option<FallibleHandler>,
+    middlewares: Arc<[Middleware]>,
+    handler_threads: HandlerThreads,
 }
 
 impl Server {
     /// Creates a new server.
     pub fn new(config: Config) -> Server {
-        Server { config }
+        let handle = ConfigHandle::new(config.clone());
+        Server {
+            config,
+            handle,
+            assets: AssetSource::FileSystem,
+            metrics: Metrics::new(),
+            websocket_handler: None,
+            not_found_handler: None,
+            middlewares: Vec::new(),
+        }
+    }
+
+    /// Creates a new server which serves static assets from memory instead of the file system.
+    ///
+    /// The assets are keyed by their URL path, e.g. `/css/main.css`. The web root directory and
+    /// the mounts are not used.
+    pub fn with_embedded_assets(config: Config, assets: HashMap<String, Vec<u8>>) -> Server {
+        Server { assets: AssetSource::embedded(assets), ..Server::new(config) }
+    }
+
+    /// Accepts WebSocket handshakes and hands the switched connections over to the handler.
+    ///
+    /// Without a handler, requests to switch the protocol are answered with `501 Not Implemented`.
+    pub fn with_websocket_handler(self, handler: websocket::Handler) -> Server {
+        Server { websocket_handler: Some(handler), ..self }
+    }
+
+    /// Responds with the handler to requests matching no static asset, e.g. with a custom error
+    /// page or a JSON body.
+    ///
+    /// Errors of the handler are answered with their status. Without a handler, such requests are
+    /// answered with the built-in `404 Not Found`.
+    pub fn with_not_found_handler(self, handler: FallibleHandler) -> Server {
+        Server { not_found_handler: Some(handler), ..self }
+    }
+
+    /// Adds a middleware wrapping the handling of every request, inside all previously added ones.
+    pub fn with_middleware(mut self, middleware: Middleware) -> Server {
+        self.middlewares.push(middleware);
+        self
+    }
+
+    /// Get a handle to reload the configuration while the server is running.
+    pub fn config_handle(&self) -> ConfigHandle {
+        self.handle.clone()
     }
 
     /// Bind the server to the configured IP and port and start listening. It returns an error describing the problem if it had failed for any reason.
-    pub fn bind(&self) -> Result<(), &'static str> {
+    pub fn bind(&self) -> Result<(), ServerError> {
+        self.serve(None)
+    }
+
+    /// Like [`bind`](#method.bind), but returns after `n` connections have been accepted and fully handled.
+    ///
+    /// This drives the real accept loop deterministically, e.g. in integration tests.
+    pub fn serve_n(&self, n: usize) -> Result<(), ServerError> {
+        self.serve(Some(n))
+    }
+
+    fn serve(&self, max_accepted: Option<usize>) -> Result<(), ServerError> {
+        #[cfg(unix)]
+        {
+            if let Some(path) = self.config.unix_socket() {
+                return self.serve_unix_socket(path, max_accepted);
+            }
+        }
+
         let addr = format!("{}:{}", self.config.address, self.config.port);
         info!("Bind to {}", addr);
 
-        let listener = if let Ok(listener) = TcpListener::bind(addr.clone()) {
-            listener
-        } else {
-            return Err("Can't bind TCP listener on address!");
+        let listener = TcpListener::bind(addr.clone()).map_err(ServerError::Bind)?;
+
+        if let Some(backlog) = self.config.listen_backlog() {
+            set_listen_backlog(&listener, backlog).map_err(ServerError::Bind)?;
+        }
+
+        format!("Listening on http://{}:{}/", self.config.address, self.config.port);
+        let nodelay = *self.config.tcp_nodelay();
+        let incoming = listener.incoming()
+            .map(|stream| stream.map(|stream| prepare_tcp_stream(stream, nodelay)));
+        self.accept(incoming, max_accepted)
+    }
+
+    #[cfg(unix)]
+    fn serve_unix_socket(&self, path: &str, max_accepted: Option<usize>) -> Result<(), ServerError> {
+        info!("Bind to unix socket {}", path);
+
+        // Removes the socket file when serving has finished.
+        let socket = UnixSocket::bind(path).map_err(ServerError::Bind)?;
+        let incoming = socket.listener().incoming()
+            .map(|stream| stream.map(|stream| (stream, stream::unix_peer())));
+        self.accept(incoming, max_accepted)
+    }
+
+    /// Handles the accepted connections, together with the address of their client.
+    ///
+    /// A connection which can't be accepted is logged and skipped, it does not stop the server.
+    fn accept<S, I>(&self, incoming: I, max_accepted: Option<usize>) -> Result<(), ServerError>
+        where S: Stream + 'static, I: Iterator<Item = io::Result<(S, SocketAddr)>> {
+        // Declared before the pool so that it is dropped after all workers have finished.
+        let access_log = match self.config.access_log() {
+            Some(file_name) => Some(open_access_log(file_name)?),
+            None => None,
         };
 
         info!("Serving with {} threads.", self.config.threads);
-        let pool = ThreadPool::new(self.config.threads);
-        format!("Listening on http://{}:{}/", self.config.address, self.config.port);
+        let pool = ThreadPool::with_stack_size(self.config.threads, self.config.worker_stack_size())
+            .map_err(ServerError::ThreadPool)?;
+
+        let limiter = ConnectionLimiter::new(
+            self.config.max_connections(),
+            self.config.max_connections_per_ip());
+        let rate_limits = RateLimits::from_config(&self.config);
+        let handler_threads = HandlerThreads::new(self.config.threads);
+        let middlewares: Arc<[Middleware]> = self.middlewares.clone().into();
+
+        for accepted in incoming.take(max_accepted.unwrap_or(usize::MAX)) {
+            let (stream, peer) = match accepted {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    error!("Can't accept connection: {}", err);
+
+                    if runs_out_of_resources(&err) {
+                        thread::sleep(Duration::from_millis(ACCEPT_BACKOFF_MILLIS));
+                    }
+
+                    continue;
+                },
+            };
+
+            let guard = match limiter.acquire(peer.ip()) {
+                Some(guard) => guard,
+                None => {
+                    Server::reject_connection(&self.handle.current(), stream);
+                    continue;
+                },
+            };
 
-        for stream in listener.incoming() {
-            let stream = stream.expect("Cn't open TCP stream!");
-            let config = self.config.clone();
+            let config = self.handle.clone();
+            let services = Services {
+                assets: self.assets.clone(),
+                rate_limits: rate_limits.clone(),
+                access_logger: access_log.as_ref().map(AccessLog::logger),
+                metrics: self.metrics.clone(),
+                websocket_handler: self.websocket_handler,
+                not_found_handler: self.not_found_handler,
+                middlewares: middlewares.clone(),
+                handler_threads: handler_threads.clone(),
+            };
 
-            pool.execute(|| {
-                Server::handle_connection_new(stream, config);
+            pool.execute(move || {
+                Server::handle_connection_new(stream, peer, config, services);
+                drop(guard);
             });
         }
 
         Ok(())
     }
 
-    fn handle_connection_new(mut stream: TcpStream, config: Config) {
-        let mut buffer = [0; 4096];
-        stream.read(&mut buffer)
-            .expect("Can't read from TCP stream!");
-        let request = byte_array_to_string(buffer);
-        debug!("Received data: {:?}", request);
+    /// Serves the requests of a connection until it is closed and returns its final state.
+    fn handle_connection_new<S: Stream>(stream: S, peer: SocketAddr, handle: ConfigHandle, services: Services) -> ConnectionState {
+        let Services { ref rate_limits, ref access_logger, ref metrics, websocket_handler, .. } = services;
+        let _connection = metrics.track_connection();
+        let mut stream = CountingStream::new(stream, peer);
+        let mut pending: Vec<u8> = Vec::new();
+
+        loop {
+            // Each request is read and answered with the config current when it is awaited.
+            let config = handle.current();
+            let keep_alive = KeepAlive::new(&config);
+            let read_timeout = Duration::from_secs(*config.read_timeout());
+            // A new connection must send its first request in time, a reused one may idle longer.
+            let idle_timeout = if stream.state().requests() == 0 { read_timeout } else { *keep_alive.timeout() };
+
+            match await_request(&mut stream, &mut pending, idle_timeout, read_timeout) {
+                Ok(true) => (),
+                Ok(false) => {
+                    debug!("Connection closed by peer or idle.");
+                    break;
+                },
+                Err(err) => {
+                    debug!("Can't wait for request on TCP stream: {}", err);
+                    break;
+                },
+            }
+
+            let head = match read_request(&mut stream, &mut pending, *config.max_request_bytes()) {
+                Ok(Some(buffer)) => buffer,
+                Ok(None) => {
+                    debug!("Connection closed by peer or idle.");
+                    break;
+                },
+                Err(err) => {
+                    debug!("Can't read request: {:?}", err);
+                    let mut response = match err {
+                        ReadError::RequestLineTooLong => uri_too_long_response(),
+                        ReadError::HeaderFieldsTooLarge => header_fields_too_large_response(),
+                        ReadError::TimedOut => request_timeout_response(),
+                        ReadError::Unreadable => bad_request_response(),
+                    };
+                    add_server_header(&config, &mut response);
+                    keep_alive.add_headers(&mut response, stream.state(), false);
+
+                    if let Err(err) = write_response(&mut stream, &response) {
+                        debug!("Can't write to TCP stream: {}", err);
+                    }
+
+                    break;
+                },
+            };
+            let (request, decoded) = match decode_head(&config, &head) {
+                Ok(request) => (request, Ok(())),
+                Err(err) => (bytes_to_string(&head), Err(err)),
+            };
+            // Redacting copies the whole request, so it is skipped unless the request is logged.
+            if log_enabled!(Level::Debug) {
+                debug!("Received data: {:?}", access_log::redact_headers(&request, config.redacted_headers()));
+            }
+
+            let started = Instant::now();
+            let parsed = decoded.and_then(|()| parse_request(&config, request.trim()));
+            let parsed = retain_raw_headers(&config, parsed, &head);
+            stream.state_mut().count_request();
+            let keep_open = keep_alive.keep_open(stream.state(), &parsed);
+            let path = parsed.as_ref().ok().and_then(|request| request.target().normalized_path());
+            let websocket_request = websocket_handler
+                .and(parsed.as_ref().ok())
+                .filter(|request| websocket::is_handshake(request))
+                .cloned();
+            let declares_body = parsed.as_ref().is_ok_and(declares_body);
+            let (mut response, body_read) = match rate_limits.check(peer.ip(), path.as_deref()) {
+                Ok(()) if serves_metrics(&config, &parsed) => (metrics_response(metrics, &parsed), false),
+                Ok(()) => match websocket_request {
+                    Some(ref request) => (websocket_handshake_response(request), false),
+                    None => receive_and_respond(&mut stream, &mut pending, config.clone(), &services, parsed, request.trim(), peer),
+                },
+                Err(retry_after) => (too_many_requests_response(retry_after), false),
+            };
+            // Bytes of a body which was not read completely would be taken as the next request.
+            let keep_open = keep_open && (body_read || !declares_body);
+            let switched = matches!(response.status(), Status::SwitchingProtocols);
+            add_server_header(&config, &mut response);
+
+            // The connection belongs to the other protocol now and gets no HTTP connection options.
+            if !switched {
+                keep_alive.add_headers(&mut response, stream.state(), keep_open);
+            }
+
+            if *config.server_timing() {
+                response.add_header(ResponseHeader::ServerTiming(started.elapsed()));
+            }
+
+            if let Err(err) = write_response(&mut stream, &response) {
+                debug!("Can't write to TCP stream: {}", err);
+                break;
+            }
+
+            metrics.record(response.status(), response.body_length(), started.elapsed());
+
+            if let Some(ref access_logger) = access_logger {
+                if access_log::is_sampled(response.status(), *config.log_sample_rate()) {
+                    access_logger.log(format_access_log_line(stream.state(), request.trim(), &response));
+                }
+            }
+
+            if switched {
+                if let (Some(handler), Some(request)) = (websocket_handler, websocket_request) {
+                    debug!("Handing connection over to the WebSocket handler.");
+
+                    if let Err(err) = stream.set_read_timeout(None) {
+                        debug!("Can't clear read timeout of TCP stream: {}", err);
+                    }
+
+                    // The client may have sent data of the other protocol along with the request.
+                    handler(&mut ReplayStream::new(mem::take(&mut pending), &mut stream), &request);
+                }
+
+                break;
+            }
+
+            if !keep_open {
+                // Bytes beyond the body of the last request are no request, e.g. a wrong Content-Length.
+                if !pending.is_empty() {
+                    debug!("Ignoring {} bytes received after the last request.", pending.len());
+                }
+
+                break;
+            }
+        }
+
+        let state = stream.into_state();
+        debug!(
+            "Closing connection to {} after {:?}: {} requests, {} bytes read, {} bytes written.",
+            state.peer(), state.age(), state.requests(), state.bytes_read(), state.bytes_written());
+        state
+    }
+
+    fn reject_connection<S: Stream>(config: &Config, mut stream: S) {
+        let mut response = service_unavailable_response();
+        add_server_header(config, &mut response);
+
+        if let Err(err) = stream.write_all(&response.render()) {
+            debug!("Can't write rejection to TCP stream: {}", err);
+        }
+    }
+}
+
+/// Bookkeeping of a connection, shared by the per-connection limits and the access log.
+#[derive(Debug, Clone)]
+struct ConnectionState {
+    /// Address of the client.
+    peer: SocketAddr,
+    /// When the connection was accepted.
+    started: Instant,
+    /// Number of requests received so far.
+    requests: usize,
+    /// Number of bytes received so far.
+    bytes_read: u64,
+    /// Number of bytes sent so far.
+    bytes_written: u64,
+}
+
+impl ConnectionState {
+    fn new(peer: SocketAddr) -> ConnectionState {
+        ConnectionState { peer, started: Instant::now(), requests: 0, bytes_read: 0, bytes_written: 0 }
+    }
+
+    fn peer(&self) -> &SocketAddr {
+        &self.peer
+    }
+
+    /// How long the connection is open.
+    fn age(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    fn requests(&self) -> usize {
+        self.requests
+    }
 
-        let request = http::parse_request(request.trim());
-        debug!("Got request: {:?}", request);
+    fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
 
-        let response = build_response(config, request);
+    fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
 
-        stream.write(&response.render())
-            .expect("Can't write to TCP stream!");
-        stream.flush()
-            .expect("Can't flush TCP stream!");
+    fn count_request(&mut self) {
+        self.requests += 1;
     }
 }
 
-fn byte_array_to_string(input: [u8; 4096]) -> String {
-    let mut output = String::new();
+/// Stream which counts the bytes read from and written to it in the state of its connection.
+#[derive(Debug)]
+struct CountingStream<S> {
+    stream: S,
+    state: ConnectionState,
+}
 
-    for i in 0..input.len() {
-        let ch = input[i];
+impl<S: Stream> CountingStream<S> {
+    fn new(stream: S, peer: SocketAddr) -> CountingStream<S> {
+        CountingStream { stream, state: ConnectionState::new(peer) }
+    }
 
-        if ch == 0 {
-            break;
-        }
+    fn state(&self) -> &ConnectionState {
+        &self.state
+    }
 
-        output.push(ch as char);
+    fn state_mut(&mut self) -> &mut ConnectionState {
+        &mut self.state
     }
 
-    output
+    fn into_state(self) -> ConnectionState {
+        self.state
+    }
 }
 
-fn build_response(config: Config, request: Request) -> Response {
-    match request.method().as_ref() {
-        "GET" => handle_get_request(config, request),
-        "HEAD" => handle_head_request(config, request),
-        "OPTIONS" => handle_options_request(),
-        _ => handle_unsupported_request(),
+impl<S: Stream> Read for CountingStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.stream.read(buf)?;
+        self.state.bytes_read += read as u64;
+        Ok(read)
     }
 }
 
-fn handle_get_request(config: Config, request: Request) -> Response {
-    let mut response = match find_resource(config, request) {
-        Some(resource) => {
-            debug!("Found resource {:?}", resource);
-            let mut content = file::read_bytes(&resource);
-            let content_length = content.len();
-            let mut response = Response::new(
-                http::VERSION.to_string(),
-                Status::Ok,
-                content);
-            response.add_header(ResponseHeader::ContentLength(content_length));
-            response.add_header(
-                ResponseHeader::ContentType(
-                    format!("{}; charset=utf-8", determine_content_type(&resource))));
-            response
-        },
-        None => not_found_response(),
-    };
+impl<S: Stream> Write for CountingStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.stream.write(buf)?;
+        self.state.bytes_written += written as u64;
+        Ok(written)
+    }
 
-    add_default_headers(&mut response);
-    response
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
 }
 
-fn handle_head_request(config: Config, request: Request) -> Response {
-    let mut response = match find_resource(config, request) {
-        Some(resource) => {
-            debug!("Found resource {:?}", resource);
-            let mut response = Response::new(
-                http::VERSION.to_string(),
-                Status::Ok,
-                Vec::new());
-            response.add_header(ResponseHeader::ContentLength(0));
-            response.add_header(
-                ResponseHeader::ContentType(
-                    format!("{}; charset=utf-8", determine_content_type(&resource))));
-            response
-        },
-        None => not_found_response(),
-    };
+impl<S: Stream> Stream for CountingStream<S> {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(timeout)
+    }
+}
 
-    add_default_headers(&mut response);
-    response
+/// Counts the threads running handlers with a timeout, so overrunning handlers can't pile up.
+///
+/// It is cheap to clone. All clones share the same count.
+#[derive(Debug, Clone)]
+struct HandlerThreads {
+    /// Maximum number of threads running at the same time.
+    max: usize,
+    running: Arc<AtomicUsize>,
 }
 
-fn handle_options_request() -> Response {
-    let mut response = Response::new(
-        http::VERSION.to_string(),
-        Status::Ok,
-        Vec::new());
-    add_default_headers(&mut response);
-    response.add_header(ResponseHeader::Allow(http::ALLOWED_METHODS.to_string()));
-    response
+impl HandlerThreads {
+    fn new(max: usize) -> HandlerThreads {
+        HandlerThreads { max, running: Arc::new(AtomicUsize::new(0)) }
+    }
+
+    /// Reserves a thread, `None` if the maximum number is running already.
+    ///
+    /// The thread is released when the guard is dropped, even if its handler panics.
+    fn acquire(&self) -> Option<HandlerThreadGuard> {
+        if self.running.fetch_add(1, Ordering::SeqCst) >= self.max {
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+
+        Some(HandlerThreadGuard { running: Arc::clone(&self.running) })
+    }
 }
 
-fn handle_unsupported_request() -> Response {
-    let mut response = Response::new(
-        String::from("1.1"),
-        Status::MethodNotAllowed,
-        "Method not supported by this HTTP server implementation!".as_bytes().to_vec());
-    response.add_header(ResponseHeader::Allow(http::ALLOWED_METHODS.to_string()));
-    response
+/// Represents a thread reserved at [`HandlerThreads`](struct.HandlerThreads.html).
+#[derive(Debug)]
+struct HandlerThreadGuard {
+    running: Arc<AtomicUsize>,
 }
 
-fn find_resource(config: Config, request: Request) -> Option<PathBuf> {
-    let wanted_resource = create_resource_path(config.web_dir(), request.url());
-    debug!("Wanted resource is {:?}", wanted_resource);
+impl Drop for HandlerThreadGuard {
+    fn drop(&mut self) {
+        self.running.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Limits of persistent connections.
+#[derive(Debug)]
+struct KeepAlive {
+    /// How long to wait for the next request.
+    timeout: Duration,
+    /// Maximum number of requests served on the connection.
+    max_requests: usize,
+}
 
-    if wanted_resource.is_dir() {
-        return handle_directory_resource(wanted_resource);
+impl KeepAlive {
+    fn new(config: &Config) -> KeepAlive {
+        KeepAlive {
+            timeout: Duration::from_secs(*config.keep_alive_timeout()),
+            max_requests: *config.keep_alive_max_requests(),
+        }
     }
 
-    if wanted_resource.exists() {
-        Some(wanted_resource)
-    } else {
-        debug!("Not found {:?}", wanted_resource);
-        None
+    fn timeout(&self) -> &Duration {
+        &self.timeout
+    }
+
+    /// Number of requests which may still be served on the connection.
+    fn remaining(&self, state: &ConnectionState) -> usize {
+        self.max_requests.saturating_sub(state.requests())
+    }
+
+    /// Decides whether the connection stays open after responding to the last received request.
+    ///
+    /// Malformed requests close the connection. A request with a body may only keep it open if
+    /// the body is read completely, which the caller checks once the request is answered.
+    fn keep_open(&self, state: &ConnectionState, request: &Result<Request, ParseError>) -> bool {
+        match *request {
+            Ok(ref request) => self.remaining(state) > 0 && requests_keep_alive(request),
+            Err(_) => false,
+        }
+    }
+
+    fn add_headers(&self, response: &mut Response, state: &ConnectionState, keep_open: bool) {
+        if keep_open {
+            response.add_header(ResponseHeader::Connection(String::from("keep-alive")));
+            response.add_header(ResponseHeader::KeepAlive(self.timeout.as_secs(), self.remaining(state)));
+        } else {
+            response.add_header(ResponseHeader::Connection(String::from("close")));
+        }
     }
 }
 
-fn handle_directory_resource(wanted_resource: PathBuf) -> Option<PathBuf> {
-    let mut wanted_resource_file = wanted_resource.join("index.html");
-    debug!("Wanted resource is a directory. Looking for {:?}", wanted_resource_file);
+/// Whether the client wants to keep the connection open.
+///
+/// HTTP/1.1 connections are persistent unless closed explicitly, older ones must ask for it.
+fn requests_keep_alive(request: &Request) -> bool {
+    let connection = request.connection().to_ascii_lowercase();
+    let mut options = connection.split(',').map(|option| option.trim());
+
+    if options.clone().any(|option| option == "close") {
+        return false;
+    }
+
+    request.version() == "1.1" || options.any(|option| option == "keep-alive")
+}
 
-    if !wanted_resource_file.exists() {
-        wanted_resource_file = wanted_resource.join("index.htm");
-        debug!("Wanted resource is a directory. Looking for {:?}", wanted_resource_file);
+/// Configures an accepted TCP stream and gets the address of its client.
+///
+/// Failing to configure the stream is not fatal, it is served with the defaults then. If the
+/// address of the client is not available, e.g. because it already disconnected, the stream is
+/// served with the [unknown peer](../stream/fn.unknown_peer.html) address.
+fn prepare_tcp_stream(stream: TcpStream, nodelay: bool) -> (TcpStream, SocketAddr) {
+    if let Err(err) = stream.set_nodelay(nodelay) {
+        debug!("Can't set TCP_NODELAY of TCP stream: {}", err);
     }
 
-    if !wanted_resource_file.exists() {
-        debug!("Nothing appropriate found!");
-        None
+    let peer = stream.peer_addr().unwrap_or_else(|err| {
+        warn!("Can't get address of client: {}", err);
+        stream::unknown_peer()
+    });
+    (stream, peer)
+}
+
+/// Listens again with the given backlog, which replaces the one of the already listening socket.
+///
+/// The standard library always listens with its own backlog, so the configured one is applied
+/// afterwards. Values above `i32::MAX` are capped, the operating system caps them further.
+#[cfg(unix)]
+fn set_listen_backlog(listener: &TcpListener, backlog: u32) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let backlog = backlog.min(i32::MAX as u32) as libc::c_int;
+
+    if unsafe { libc::listen(listener.as_raw_fd(), backlog) } == 0 {
+        Ok(())
     } else {
-        Some(wanted_resource_file)
+        Err(io::Error::last_os_error())
     }
 }
 
-fn not_found_response() -> Response {
-    let mut response = Response::new(
-        String::from("1.1"),
-        Status::NotFound,
-        "Not found!".as_bytes().to_vec());
-    response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
-    response
+#[cfg(not(unix))]
+fn set_listen_backlog(_listener: &TcpListener, backlog: u32) -> io::Result<()> {
+    warn!("Ignoring listen backlog {}, it is only supported on Unix!", backlog);
+    Ok(())
 }
 
-fn determine_content_type(file_name: &PathBuf) -> String {
-    match file_name.extension() {
-        Some(extension) => {
-            match extension.to_str().unwrap() {
-                "html" | "htm" => String::from("text/html"),
-                "css" => String::from("text/css"),
-                "js" => String::from("text/javascript"),
-                "ico" => String::from("image/x-icon"),
-                _ => String::from("text/plain"),
-            }
-        },
-        None => String::from("text/plain"),
+/// Whether accepting failed because the process or system ran out of file descriptors or memory.
+///
+/// Accepting again right away would fail the same way, so the caller should back off first.
+#[cfg(unix)]
+fn runs_out_of_resources(err: &io::Error) -> bool {
+    match err.raw_os_error() {
+        Some(code) => code == libc::EMFILE || code == libc::ENFILE || code == libc::ENOBUFS || code == libc::ENOMEM,
+        None => false,
     }
 }
 
-fn add_default_headers(response: &mut Response) {
-    response.add_header(ResponseHeader::Date(formatted_now()));
-    response.add_header(ResponseHeader::Server(String::from(super::APPLICATION_DESCRIPTION)));
-    response.add_header(ResponseHeader::AcceptRanges(String::from("none")));
+#[cfg(not(unix))]
+fn runs_out_of_resources(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::OutOfMemory
 }
 
-fn formatted_now() -> String {
-    // http://man7.org/linux/man-pages/man3/strftime.3.html
-    // Wed, 14 Feb 2018 12:17:24 GMT
-    time::strftime("%a, %d %b %Y %H:%M:%S %Z", &time::now())
-        .expect("Can't format date!")
+fn write_response<W: Write>(stream: &mut W, response: &Response) -> io::Result<()> {
+    stream.write_all(&response.render())?;
+    stream.flush()
 }
 
-fn create_resource_path(web_root: &String, resource_url: &String) -> PathBuf {
-    let relative_resource_url = relativize_uri(resource_url);
-    Path::new(web_root).join(relative_resource_url)
-}
+fn open_access_log(file_name: &String) -> Result<AccessLog, ServerError> {
+    info!("Writing access log to {}", file_name);
 
-fn relativize_uri(resource_url: &String) -> String {
-    if resource_url.starts_with("/") {
-        resource_url[1..].to_string()
-    } else {
-        resource_url.clone()
+    match OpenOptions::new().create(true).append(true).open(file_name) {
+        Ok(file) => Ok(AccessLog::start(file, access_log::DEFAULT_FLUSH_INTERVAL, access_log::DEFAULT_BUFFERED_LINES)),
+        Err(err) => {
+            error!("Can't open access log {}: {}", file_name, err);
+            Err(ServerError::AccessLog(err))
+        },
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use hamcrest::prelude::*;
+/// Formats a line in the [Common Log Format](https://en.wikipedia.org/wiki/Common_Log_Format).
+fn format_access_log_line(state: &ConnectionState, raw_request: &str, response: &Response) -> String {
+    let request_line = raw_request.lines().next().unwrap_or("");
+    format!(
+        "{} - - [{}] \"{}\" {} {}",
+        state.peer().ip(),
+        formatted_now(),
+        request_line,
+        response.status().code(),
+        response.body_length())
+}
 
-    #[test]
-    fn test_determine_content_type_from_file_name() {
-        assert_that!(
-            determine_content_type(&PathBuf::from("")),
-            is(equal_to(String::from("text/plain")))
-        );
-        assert_that!(
-            determine_content_type(&PathBuf::from("index.html")),
-            is(equal_to(String::from("text/html")))
-        );
-        assert_that!(
-            determine_content_type(&PathBuf::from("new.index.htm")),
-            is(equal_to(String::from("text/html")))
-        );
-        assert_that!(
-            determine_content_type(&PathBuf::from("/foo/bar/new.index.html")),
-            is(equal_to(String::from("text/html")))
-        );
-        assert_that!(
-            determine_content_type(&PathBuf::from("foo.abr.css")),
-            is(equal_to(String::from("text/css")))
-        );
-        assert_that!(
-            determine_content_type(&PathBuf::from("/foo/bar/new.index.js")),
-            is(equal_to(String::from("text/javascript")))
-        );
-        assert_that!(
-            determine_content_type(&PathBuf::from("/foo/bar/new.index.js")),
-            is(equal_to(String::from("text/javascript")))
-        );
-        assert_that!(
-            determine_content_type(&PathBuf::from("/foo/bar/favicon.ico")),
-            is(equal_to(String::from("image/x-icon")))
-        );
-    }
+/// Waits up to `idle_timeout` for the first bytes of the next request and keeps them in `pending`.
+///
+/// The rest of the request is read with `read_timeout` afterwards. Returns `false` if the peer
+/// closed the connection or stayed idle, which ends the connection without a response.
+fn await_request<S: Stream>(stream: &mut S, pending: &mut Vec<u8>, idle_timeout: Duration, read_timeout: Duration) -> io::Result<bool> {
+    if pending.is_empty() {
+        stream.set_read_timeout(Some(idle_timeout))?;
+        let mut buffer = [0; 1024];
 
-    #[test]
-    fn test_relativize_uri() {
-        assert_that!(relativize_uri( & String::from("foo/bar/bax.html")),
-            is(equal_to(String::from("foo/bar/bax.html"))));
-        assert_that!(relativize_uri( & String::from("/foo/bar/bax.html")),
-            is(equal_to(String::from("foo/bar/bax.html"))));
+        let read = loop {
+            match stream.read(&mut buffer) {
+                Ok(read) => break read,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(ref err) if is_timeout(err) => return Ok(false),
+                Err(err) => return Err(err),
+            }
+        };
+
+        if read == 0 {
+            return Ok(false);
+        }
+
+        pending.extend_from_slice(&buffer[..read]);
     }
 
-    #[test]
-    fn test_create_resource_path() {
-        assert_that!(
-            create_resource_path( & String::from("web_root/"), & String::from("/")),
-            is(equal_to(PathBuf::from("web_root/"))));
-        assert_that!(
-            create_resource_path( & String::from("web_root/"), &String::from("/index.html")),
-            is(equal_to(PathBuf::from("web_root/index.html"))));
-        assert_that!(
-            create_resource_path( & String::from("web_root/"), & String::from("/css/main.css")),
-            is(equal_to(PathBuf::from("web_root/css/main.css"))));
+    stream.set_read_timeout(Some(read_timeout))?;
+    Ok(true)
+}
+
+/// Error of [`read_request`](fn.read_request.html).
+#[derive(Debug, PartialEq)]
+enum ReadError {
+    /// The request line alone exceeds the maximum size, most likely because of a long URL.
+    RequestLineTooLong,
+    /// The request line and headers exceed the maximum size.
+    HeaderFieldsTooLarge,
+    /// The request stopped arriving before it was complete.
+    TimedOut,
+    /// The stream can't be read.
+    Unreadable,
+}
+
+/// Reads from the stream until the blank line terminating the request header is found.
+///
+/// A single read is not guaranteed to return the whole request, so this reads until the terminator
+/// is found, the peer closes the connection or more than `max_bytes` bytes are read. Bytes read
+/// beyond the terminator belong to the next request and are kept in `pending`.
+///
+/// Returns `None` if the connection was closed or timed out before any byte of a request arrived.
+fn read_request<R: Read>(stream: &mut R, pending: &mut Vec<u8>, max_bytes: usize) -> Result<Option<Vec<u8>>, ReadError> {
+    let mut request: Vec<u8> = mem::take(pending);
+    let mut buffer = [0; 1024];
+    let mut search_from = 0;
+
+    loop {
+        if let Some(end) = find_header_terminator(&request, search_from) {
+            *pending = request.split_off(end);
+            break;
+        }
+
+        if request.len() > max_bytes {
+            return Err(too_large_error(&request, max_bytes));
+        }
+
+        let read = match stream.read(&mut buffer) {
+            Ok(read) => read,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(ref err) if request.is_empty() && is_timeout(err) => return Ok(None),
+            Err(ref err) if is_timeout(err) => return Err(ReadError::TimedOut),
+            Err(err) => {
+                debug!("Can't read from TCP stream: {}", err);
+                return Err(ReadError::Unreadable);
+            },
+        };
+
+        if read == 0 {
+            break;
+        }
+
+        // Only search the new bytes and the three before them which may start a terminator.
+        search_from = request.len().saturating_sub(3);
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    if request.len() > max_bytes {
+        return Err(too_large_error(&request, max_bytes));
+    }
+
+    // Line breaks some clients send after a request are no request of their own.
+    if request.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+
+    Ok(Some(request))
+}
+
+/// Tells whether the request line alone of a request exceeding the maximum size is too long.
+///
+/// Line breaks before the request line are skipped, like the parser does.
+fn too_large_error(request: &[u8], max_bytes: usize) -> ReadError {
+    let start = request.iter().position(|byte| !byte.is_ascii_whitespace()).unwrap_or(request.len());
+
+    match request[start..].iter().position(|&byte| byte == b'\n') {
+        Some(line_length) if line_length <= max_bytes => ReadError::HeaderFieldsTooLarge,
+        _ => ReadError::RequestLineTooLong,
+    }
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut
+}
+
+/// Returns the position right after the first header terminator found from `search_from` on.
+fn find_header_terminator(input: &[u8], search_from: usize) -> Option<usize> {
+    input[search_from..].windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|position| search_from + position + 4)
+}
+
+/// Returns the header section of a request head: the lines after the request line with their
+/// line breaks, but without the empty line ending the head.
+fn header_section(head: &[u8]) -> &[u8] {
+    let start = head.iter().position(|byte| !byte.is_ascii_whitespace()).unwrap_or(head.len());
+    let head = &head[start..];
+    let head = head.strip_suffix(b"\r\n").unwrap_or(head);
+
+    match head.windows(2).position(|window| window == b"\r\n") {
+        Some(end) => &head[end + 2..],
+        None => &[],
+    }
+}
+
+/// Keeps the header section as received on the request if the configuration asks for it.
+///
+/// It is only copied if enabled, so other requests don't pay for it.
+fn retain_raw_headers(config: &Config, parsed: Result<Request, ParseError>, head: &[u8]) -> Result<Request, ParseError> {
+    match parsed {
+        Ok(request) if *config.retain_raw_headers() => Ok(request.with_raw_headers(header_section(head).to_vec())),
+        parsed => parsed,
+    }
+}
+
+/// Decodes the request head according to the configured handling of invalid UTF-8.
+///
+/// Without one every byte is taken as the character of the same code point, like ISO-8859-1.
+fn decode_head(config: &Config, head: &[u8]) -> Result<String, ParseError> {
+    match config.invalid_utf8() {
+        Some(InvalidUtf8::Reject) => http::validate_utf8(head).map(|()| String::from_utf8_lossy(head).into_owned()),
+        Some(InvalidUtf8::Replace) => Ok(String::from_utf8_lossy(head).into_owned()),
+        None => Ok(bytes_to_string(head)),
+    }
+}
+
+fn bytes_to_string(input: &[u8]) -> String {
+    input.iter().map(|&byte| byte as char).collect()
+}
+
+/// Receives the body of the request, if any, and responds to the request.
+///
+/// Also returns whether the body was read completely. Otherwise the connection must be closed,
+/// because the rest of the body would be taken as the next request.
+fn receive_and_respond<R: Read>(stream: &mut R, pending: &mut Vec<u8>, config: Config, services: &Services, request: Result<Request, ParseError>, raw_request: &str, peer: SocketAddr) -> (Response, bool) {
+    match request {
+        Ok(request) if can_meet_expectation(&config, &request) => {
+            match receive_body(stream, pending, request, *config.max_body_bytes()) {
+                Ok(request) => (respond(config, services, Ok(request), raw_request, peer), true),
+                Err(response) => (response, false),
+            }
+        },
+        // A client whose expectation fails never sends the body, the request is rejected anyway.
+        request => (respond(config, services, request, raw_request, peer), false),
+    }
+}
+
+/// Whether the request announces a body by `Transfer-Encoding` or a non-zero `Content-Length`.
+fn declares_body(request: &Request) -> bool {
+    request.header("Transfer-Encoding").is_some() || request.content_length().unwrap_or(0) > 0
+}
+
+/// Reads the body declared by `Content-Length` or `Transfer-Encoding: chunked` and decodes it
+/// according to `Content-Encoding`.
+///
+/// Returns the response to send instead if the body is too large, can't be read or is encoded
+/// in an unsupported way.
+fn receive_body<R: Read>(stream: &mut R, pending: &mut Vec<u8>, request: Request, max_body_bytes: usize) -> Result<Request, Response> {
+    let transfer_encoding = request.header("Transfer-Encoding").unwrap_or("").trim().to_ascii_lowercase();
+
+    // Either header may be the one a proxy in front used, so the end of the body is ambiguous,
+    // see RFC 7230 section 3.3.3.
+    if !transfer_encoding.is_empty() && request.header("Content-Length").is_some() {
+        debug!("Request has both Transfer-Encoding and Content-Length!");
+        return Err(bad_request_response());
+    }
+
+    // The transfer encoding takes precedence over the content length, see RFC 7230 section 3.3.3.
+    let chunked = match transfer_encoding.as_str() {
+        "" | "identity" => false,
+        "chunked" => true,
+        _ => {
+            debug!("Unsupported transfer encoding '{}'!", transfer_encoding);
+            return Err(not_implemented_response("Transfer encodings other than chunked are not supported by this HTTP server!"));
+        },
+    };
+    let length = match request.content_length() {
+        _ if chunked => 0,
+        Some(length) if length > 0 => length,
+        _ => return Ok(request),
+    };
+    let encoding = request.content_encoding().trim().to_ascii_lowercase();
+    let gzipped = match encoding.as_str() {
+        "" | "identity" => false,
+        "gzip" | "x-gzip" => true,
+        _ => {
+            debug!("Unsupported content encoding '{}'!", encoding);
+            return Err(unsupported_media_type_response());
+        },
+    };
+
+    if length > max_body_bytes {
+        debug!("Request body of {} bytes exceeds maximum size!", length);
+        return Err(payload_too_large_response());
+    }
+
+    let body = if chunked {
+        match chunked::read_body(stream, pending, max_body_bytes) {
+            Ok(body) => body,
+            Err(ChunkedError::TooLarge) => {
+                debug!("Chunked request body exceeds maximum size!");
+                return Err(payload_too_large_response());
+            },
+            Err(ChunkedError::Io(kind)) if is_timeout(&io::Error::from(kind)) => {
+                debug!("Chunked request body did not arrive in time!");
+                return Err(request_timeout_response());
+            },
+            Err(err) => {
+                debug!("Can't read request body: {}", err);
+                return Err(bad_request_response());
+            },
+        }
+    } else {
+        match read_body(stream, pending, length) {
+            Ok(body) => body,
+            Err(ref err) if is_timeout(err) => {
+                debug!("Request body did not arrive in time!");
+                return Err(request_timeout_response());
+            },
+            Err(err) => {
+                debug!("Can't read request body: {}", err);
+                return Err(bad_request_response());
+            },
+        }
+    };
+
+    if !gzipped {
+        return Ok(request.with_body(body));
+    }
+
+    // The limit applies to the decompressed body, so a small body can't expand into huge amounts of memory.
+    match gzip::decompress(&body, max_body_bytes) {
+        Ok(body) => Ok(request.with_body(body)),
+        Err(GzipError::TooLarge) => {
+            debug!("Decompressed request body exceeds maximum size!");
+            Err(payload_too_large_response())
+        },
+        Err(err) => {
+            debug!("Can't decompress request body: {}", err);
+            Err(bad_request_response())
+        },
+    }
+}
+
+/// Reads exactly `length` body bytes, taking the bytes already read after the headers first.
+fn read_body<R: Read>(stream: &mut R, pending: &mut Vec<u8>, length: usize) -> io::Result<Vec<u8>> {
+    let mut body = mem::take(pending);
+
+    if body.len() >= length {
+        *pending = body.split_off(length);
+        return Ok(body);
+    }
+
+    let received = body.len();
+    body.resize(length, 0);
+    stream.read_exact(&mut body[received..])?;
+    Ok(body)
+}
+
+fn respond(config: Config, services: &Services, request: Result<Request, ParseError>, raw_request: &str, peer: SocketAddr) -> Response {
+    match request {
+        Ok(request) => {
+            debug!("Got request: {} {} HTTP/{}", request.method(), request.url(), request.version());
+
+            if !is_supported_version(request.version()) {
+                debug!("Rejecting unsupported HTTP version {}!", request.version());
+                return http_version_not_supported_response();
+            }
+
+            // HTTP/1.0 clients may omit the host, HTTP/1.1 clients must send it.
+            if request.version() == "1.1" && request.host().trim().is_empty() {
+                debug!("Rejecting HTTP/1.1 request without host!");
+                return bad_request_response();
+            }
+
+            if !can_meet_expectation(&config, &request) {
+                return expectation_failed_response();
+            }
+
+            match config.handler_timeout() {
+                Some(timeout) => run_handlers_with_timeout(config, services, request, raw_request, peer, Duration::from_secs(timeout)),
+                None => run_handlers(config, services, request, raw_request, peer),
+            }
+        },
+        Err(err) => {
+            debug!("Rejecting malformed request: {}", err);
+            bad_request_response()
+        },
+    }
+}
+
+/// Runs the middlewares and the handler selected for the request.
+fn run_handlers(config: Config, services: &Services, request: Request, raw_request: &str, peer: SocketAddr) -> Response {
+    let context = Context::new(config, &services.assets, request, raw_request, peer);
+    let mut context = match services.not_found_handler {
+        Some(handler) => context.with_not_found_handler(handler),
+        None => context,
+    };
+
+    Next::new(&services.middlewares, build_response).run(&mut context)
+}
+
+/// Runs the handlers on a thread of their own and answers with `503` if they overrun the timeout.
+///
+/// Threads can't be cancelled, so an overrunning handler keeps running in the background until it
+/// returns. Only the worker serving the connection is freed. At most as many handler threads as
+/// workers may run, further requests are answered with `503` right away. A panicking handler is
+/// answered with `500`.
+fn run_handlers_with_timeout(config: Config, services: &Services, request: Request, raw_request: &str, peer: SocketAddr, timeout: Duration) -> Response {
+    let guard = match services.handler_threads.acquire() {
+        Some(guard) => guard,
+        None => {
+            warn!("Too many handlers running, rejecting request for {}!", request.url());
+            return service_unavailable_response();
+        },
+    };
+    let (sender, receiver) = mpsc::channel();
+    let services = services.clone();
+    let raw_request = raw_request.to_string();
+    let url = request.url().clone();
+    let spawned = thread::Builder::new().name(String::from("handler")).spawn(move || {
+        let _guard = guard;
+        let response = run_handlers(config, &services, request, &raw_request, peer);
+        // Nobody waits for the response anymore if the timeout passed.
+        let _ = sender.send(response);
+    });
+
+    if let Err(err) = spawned {
+        warn!("Can't spawn handler thread: {}", err);
+        let mut response = Response::from_io_error(&err);
+        add_default_headers(&mut response);
+        return response;
+    }
+
+    match receiver.recv_timeout(timeout) {
+        Ok(response) => response,
+        Err(RecvTimeoutError::Timeout) => {
+            warn!("Handling request for {} took longer than {:?}!", url, timeout);
+            handler_timeout_response()
+        },
+        Err(RecvTimeoutError::Disconnected) => {
+            error!("Handler for {} panicked!", url);
+            internal_server_error_response()
+        },
+    }
+}
+
+/// Whether requests of the HTTP version are served, which are only those of HTTP/1.x.
+fn is_supported_version(version: &str) -> bool {
+    version == "1.0" || version == "1.1"
+}
+
+/// Parses the request, rejecting malformed headers if the strict mode is configured.
+fn parse_request(config: &Config, raw_request: &str) -> Result<Request, ParseError> {
+    if *config.strict() {
+        http::parse_request_strict(raw_request)
+    } else {
+        http::parse_request(raw_request)
+    }
+}
+
+/// Decides whether the server can meet the `Expect` header of the request.
+///
+/// A client sending `Expect: 100-continue` waits for the server before it sends the body. If the
+/// declared body exceeds the maximum request size the server would reject it anyway, so it fails
+/// the expectation before any body is read.
+fn can_meet_expectation(config: &Config, request: &Request) -> bool {
+    let expect = request.expect();
+
+    if expect.is_empty() {
+        return true;
+    }
+
+    if !expect.eq_ignore_ascii_case("100-continue") {
+        debug!("Unsupported expectation '{}'!", expect);
+        return false;
+    }
+
+    request.content_length().is_none_or(|content_length| content_length <= *config.max_body_bytes())
+}
+
+/// Checks whether a trusted proxy forwarded the request it received over HTTPS.
+///
+/// The `X-Forwarded-Proto` header is only taken into account if the peer is one of the trusted
+/// proxies, otherwise any client could skip the redirect to HTTPS.
+fn forwarded_over_https(config: &Config, peer: &SocketAddr, request: &Request) -> bool {
+    let trusted = config.trusted_proxies().iter().any(|trusted_proxy| trusted_proxy.parse() == Ok(peer.ip()));
+
+    trusted && request.header("X-Forwarded-Proto").is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"))
+}
+
+/// Determines the method of the request, ignoring its case if lenient methods are configured.
+fn method_of(config: &Config, request: &Request) -> Method {
+    if *config.lenient_methods() {
+        Method::from_token(&request.method().to_ascii_uppercase())
+    } else {
+        Method::from_token(request.method())
+    }
+}
+
+fn build_response(context: &mut Context) -> Response {
+    let config = context.config();
+    let assets = context.assets();
+    let request = context.request();
+    let method = method_of(config, request);
+
+    // The asterisk-form is only defined for OPTIONS and the authority-form only for CONNECT.
+    if !request.target().is_allowed_for(&method) {
+        debug!("The {} is not allowed for {}!", request.target().form(), method);
+        return bad_request_response();
+    }
+
+    if contains_control_characters(request.url()) {
+        debug!("Refusing request target with control characters: {:?}", request.url());
+        return bad_request_response();
+    }
+
+    if request.target().path().is_some_and(encodes_path_separator) {
+        debug!("Refusing request target with encoded path separators: {:?}", request.url());
+        return bad_request_response();
+    }
+
+    // Switching to another protocol, e.g. WebSocket, is not supported yet.
+    if asks_for_upgrade(request) {
+        debug!("Refusing to upgrade the connection to {:?}!", request.upgrade());
+        return not_implemented_response("Protocol upgrades are not supported by this HTTP server!");
+    }
+
+    // The server only listens for plain HTTP, so every request needs to be redirected unless a
+    // trusted proxy already received it over HTTPS.
+    if *config.force_https() && *request.target() != RequestTarget::Asterisk && !forwarded_over_https(config, context.peer(), request) {
+        let host = if request.host().trim().is_empty() { config.address() } else { request.host() };
+        let mut response = Response::redirect_https(host, *config.https_port(), &request.path_and_query());
+        add_default_headers(&mut response);
+        return response;
+    }
+
+    if config.proxy_for(request.path()).is_some() {
+        return context.handle(handle_proxy_request);
+    }
+
+    if serves_configured_favicon(config, &method, request) {
+        return favicon_response(config, method == Method::Head);
+    }
+
+    if let Some(location) = normalized_location(config, assets, &method, request) {
+        debug!("Redirecting {} to {}", request.url(), location);
+        return moved_permanently_response(location);
+    }
+
+    let handler = select_handler(config, &method);
+    context.handle(handler)
+}
+
+fn select_handler(config: &Config, method: &Method) -> Handler {
+    match *method {
+        Method::Get => handle_get_request,
+        Method::Head => handle_head_request,
+        Method::Options => handle_options_request,
+        // Echoing requests allows cross-site tracing, so it must be enabled explicitly.
+        Method::Trace if *config.allow_trace() => handle_trace_request,
+        Method::Trace => handle_disabled_trace_request,
+        _ => handle_unsupported_request,
+    }
+}
+
+/// Whether the client asks to switch the protocol with `Connection: Upgrade` and an `Upgrade` header.
+fn asks_for_upgrade(request: &Request) -> bool {
+    !request.upgrade().trim().is_empty()
+        && request.connection().split(',').any(|option| option.trim().eq_ignore_ascii_case("upgrade"))
+}
+
+/// Whether the URL contains control characters, either literally or percent-encoded.
+///
+/// They must never reach file system calls or log files.
+fn contains_control_characters(url: &str) -> bool {
+    let bytes = url.as_bytes();
+
+    bytes.iter().enumerate().any(|(index, &byte)| {
+        if byte.is_ascii_control() {
+            return true;
+        }
+
+        if byte != b'%' || index + 2 >= bytes.len() {
+            return false;
+        }
+
+        str::from_utf8(&bytes[index + 1..index + 3]).ok()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .is_some_and(|decoded| decoded.is_ascii_control())
+    })
+}
+
+/// Whether a segment of the URL path contains a percent-encoded `/` or `\`.
+///
+/// Decoded, it would split the segment and change the meaning of the path.
+fn encodes_path_separator(path: &str) -> bool {
+    path.as_bytes().windows(3).any(|window| {
+        window[0] == b'%'
+            && window[1..].iter().all(u8::is_ascii_hexdigit)
+            && matches!(
+                str::from_utf8(&window[1..]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                Some(b'/') | Some(b'\\'))
+    })
+}
+
+fn handle_get_request(context: &mut Context) -> Response {
+    let config = context.config();
+    let assets = context.assets();
+    let request = context.request();
+    let mut response = match assets.find(config, request.path()) {
+        Some(asset) => {
+            let validators = Validators::of(&asset);
+
+            if validators.not_modified(request) {
+                debug!("Resource {:?} not modified", asset.path());
+                not_modified_response(&validators)
+            } else {
+                asset_response(config, request, &asset, &validators)
+            }
+        },
+        None if serves_welcome_page(config, assets, request) => welcome_page_response(),
+        None if serves_spa_fallback(config, request) => spa_fallback_response(config),
+        None if request.path() == FAVICON_URL => no_content_response(),
+        None => handle_not_found(context),
+    };
+
+    add_default_headers(&mut response);
+    response
+}
+
+/// Answers `HEAD` with the response `GET` would get without its body, so the headers including
+/// `Content-Length` are exactly the same.
+fn handle_head_request(context: &mut Context) -> Response {
+    handle_get_request(context).into_head()
+}
+
+/// Responds with the registered not found handler or the built-in 404.
+fn handle_not_found(context: &mut Context) -> Response {
+    match context.not_found_handler() {
+        Some(handler) => context.try_handle(handler),
+        None => not_found_response(),
+    }
+}
+
+/// Answers `OPTIONS` for a resource and `OPTIONS *` for the server as a whole alike: the same
+/// methods are supported for every resource, so no resource is looked up.
+fn handle_options_request(_context: &mut Context) -> Response {
+    let mut response = Response::new(
+        http::VERSION.to_string(),
+        Status::Ok,
+        Vec::new());
+    response.add_header(ResponseHeader::ContentLength(0));
+    add_default_headers(&mut response);
+    response.add_header(ResponseHeader::Allow(http::ALLOWED_METHODS.to_string()));
+    response
+}
+
+fn handle_trace_request(context: &mut Context) -> Response {
+    let body = format!("{}\r\n\r\n", context.raw_request().trim()).into_bytes();
+    let content_length = body.len();
+    let mut response = Response::new(
+        http::VERSION.to_string(),
+        Status::Ok,
+        body);
+    response.add_header(ResponseHeader::ContentLength(content_length));
+    response.add_header(ResponseHeader::ContentType(String::from("message/http")));
+    response.no_cache();
+    add_default_headers(&mut response);
+    response
+}
+
+fn handle_proxy_request(context: &mut Context) -> Response {
+    let proxy = context.config().proxy_for(context.request().path())
+        .expect("Proxy handler selected without matching proxy!");
+
+    match proxy::forward(proxy, context.request(), context.peer()) {
+        Ok(response) => response,
+        Err(err) => {
+            warn!("Can't forward request to {}: {}", proxy.upstream(), err);
+            bad_gateway_response()
+        },
+    }
+}
+
+fn handle_disabled_trace_request(_context: &mut Context) -> Response {
+    not_implemented_response("TRACE is disabled on this HTTP server!")
+}
+
+fn handle_unsupported_request(_context: &mut Context) -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::MethodNotAllowed,
+        "Method not supported by this HTTP server implementation!".as_bytes().to_vec());
+    response.add_header(ResponseHeader::Allow(http::ALLOWED_METHODS.to_string()));
+    response
+}
+
+/// Serves the content of a found asset, or the requested range of it.
+fn asset_response(config: &Config, request: &Request, asset: &Asset, validators: &Validators) -> Response {
+    let resource = asset.path();
+    debug!("Found resource {:?}", resource);
+    let content = match asset.read() {
+        Ok(content) => content,
+        Err(err) => {
+            warn!("Can't read resource {:?}: {}", resource, err);
+            return Response::from_io_error(&err);
+        },
+    };
+    let content_type = determine_content_type(&resource);
+
+    if *config.validate_content_type() && !file::matches_content_type(&content, &content_type) {
+        warn!("Content of {:?} does not match its content type {}!", resource, content_type);
+    }
+
+    // Range requests are only defined for GET, see RFC 7233 section 3.1.
+    let range = if request.method_is("GET") && !request.range().is_empty()
+        && validators.matches_if_range(request.if_range()) {
+        http::parse_range(request.range(), content.len())
+    } else {
+        ByteRange::Full
+    };
+
+    let mut response = match range {
+        ByteRange::Full => {
+            let content_length = content.len();
+            let mut response = Response::new(
+                http::VERSION.to_string(),
+                Status::Ok,
+                content);
+            response.add_header(ResponseHeader::ContentLength(content_length));
+            response
+        },
+        ByteRange::Partial(first, last) => {
+            debug!("Serving bytes {} to {} of {:?}", first, last, resource);
+            let total_length = content.len();
+            let mut response = Response::new(
+                http::VERSION.to_string(),
+                Status::PartialContent,
+                content[first..last + 1].to_vec());
+            response.add_header(ResponseHeader::ContentLength(last + 1 - first));
+            response.add_header(
+                ResponseHeader::ContentRange(format!("bytes {}-{}/{}", first, last, total_length)));
+            response
+        },
+        ByteRange::Unsatisfiable => range_not_satisfiable_response(content.len()),
+    };
+    response.add_header(ResponseHeader::ContentType(format!("{}; charset=utf-8", content_type)));
+    validators.add_headers(&mut response);
+    add_content_disposition_header(config, &resource, &mut response);
+    response.add_header(ResponseHeader::AcceptRanges(String::from("bytes")));
+    response
+}
+
+/// Answers a conditional request whose cached copy is still fresh, only sending the validators.
+fn not_modified_response(validators: &Validators) -> Response {
+    let mut response = Response::empty(http::VERSION.to_string(), Status::NotModified);
+    validators.add_headers(&mut response);
+    response
+}
+
+/// Validators of a static asset used for conditional requests.
+#[derive(Debug)]
+struct Validators {
+    /// Strong entity tag derived from the file size and modification time, or the embedded content.
+    etag: Option<String>,
+    /// Modification time of the file as HTTP date, unknown for embedded assets.
+    last_modified: Option<String>,
+    /// Modification time of the file in seconds since the epoch.
+    modified: Option<i64>,
+}
+
+impl Validators {
+    fn of(asset: &Asset) -> Validators {
+        match *asset {
+            Asset::File(ref resource) => Validators::of_file(resource),
+            Asset::Embedded(_, content) => Validators {
+                etag: Some(assets::content_etag(content)),
+                last_modified: None,
+                modified: None,
+            },
+        }
+    }
+
+    fn of_file(resource: &PathBuf) -> Validators {
+        let metadata = match fs::metadata(resource) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                debug!("Can't read metadata of {:?}: {}", resource, err);
+                return Validators { etag: None, last_modified: None, modified: None };
+            },
+        };
+        let modified = metadata.modified().ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|modified| modified.as_secs() as i64);
+
+        Validators {
+            etag: modified.map(|modified| format!("\"{:x}-{:x}\"", metadata.len(), modified)),
+            last_modified: modified.map(format_http_date),
+            modified,
+        }
+    }
+
+    /// Whether the cached copy of the client is still fresh, so `304 Not Modified` may be sent.
+    ///
+    /// `If-None-Match` takes precedence, so `If-Modified-Since` is ignored if both are sent, see
+    /// RFC 7232 section 6. Entity tags are compared weakly and `*` matches any of them.
+    fn not_modified(&self, request: &Request) -> bool {
+        if let Some(if_none_match) = request.header("If-None-Match") {
+            return self.etag.as_ref().is_some_and(|etag| matches_any_etag(if_none_match, etag));
+        }
+
+        match (request.header("If-Modified-Since").and_then(parse_http_date), self.modified) {
+            (Some(since), Some(modified)) => modified <= since,
+            _ => false,
+        }
+    }
+
+    /// Whether the validator of an `If-Range` header still matches the file.
+    ///
+    /// An empty header always matches. Entity tags must match strongly, so weak tags never match.
+    /// Dates must match the modification time exactly.
+    fn matches_if_range(&self, if_range: &str) -> bool {
+        let if_range = if_range.trim();
+
+        if if_range.is_empty() {
+            return true;
+        }
+
+        if if_range.starts_with("W/") {
+            return false;
+        }
+
+        if if_range.starts_with('"') {
+            return self.etag.as_ref().is_some_and(|etag| etag == if_range);
+        }
+
+        self.last_modified.as_ref().is_some_and(|last_modified| last_modified == if_range)
+    }
+
+    fn add_headers(&self, response: &mut Response) {
+        if let Some(ref etag) = self.etag {
+            response.add_header(ResponseHeader::ETag(etag.clone()));
+        }
+
+        if let Some(ref last_modified) = self.last_modified {
+            response.add_header(ResponseHeader::LastModified(last_modified.clone()));
+        }
+    }
+}
+
+/// Whether to serve the built-in welcome page instead of a not found response.
+///
+/// This is only the case for `/` if enabled and there are no static assets at all, e.g. the
+/// web root directory is empty.
+fn serves_welcome_page(config: &Config, assets: &AssetSource, request: &Request) -> bool {
+    *config.welcome_page() && request.path() == "/" && assets.is_empty(config)
+}
+
+/// Whether to serve the configured fallback instead of a 404, so the client side router of a
+/// single page application can handle the path.
+///
+/// Only browsers navigating to a page accept HTML. Paths with an extension ask for a file, e.g.
+/// a script, and still get a 404.
+fn serves_spa_fallback(config: &Config, request: &Request) -> bool {
+    let file_name = request.path().rsplit('/').next().unwrap_or("");
+
+    config.spa_fallback().is_some()
+        && !file_name.contains('.')
+        && request.header("Accept").is_some_and(|accept| accept.contains("text/html"))
+}
+
+fn spa_fallback_response(config: &Config) -> Response {
+    let fallback = PathBuf::from(config.spa_fallback().unwrap());
+    let content = match fs::read(&fallback) {
+        Ok(content) => content,
+        Err(err) => {
+            warn!("Can't read SPA fallback {:?}: {}", fallback, err);
+            return Response::from_io_error(&err);
+        },
+    };
+    let content_length = content.len();
+    let mut response = Response::new(
+        http::VERSION.to_string(),
+        Status::Ok,
+        content);
+    response.add_header(ResponseHeader::ContentLength(content_length));
+    response.add_header(ResponseHeader::ContentType(String::from("text/html; charset=utf-8")));
+    response
+}
+
+fn welcome_page_response() -> Response {
+    let mut response = Response::new(
+        http::VERSION.to_string(),
+        Status::Ok,
+        WELCOME_PAGE.as_bytes().to_vec());
+    response.add_header(ResponseHeader::ContentLength(WELCOME_PAGE.len()));
+    response.add_header(ResponseHeader::ContentType(String::from("text/html; charset=utf-8")));
+    response
+}
+
+/// Whether the request asks for the favicon and a file is configured for it.
+///
+/// The configured file takes precedence over the static assets.
+fn serves_configured_favicon(config: &Config, method: &Method, request: &Request) -> bool {
+    match *method {
+        Method::Get | Method::Head => config.favicon().is_some() && request.path() == FAVICON_URL,
+        _ => false,
+    }
+}
+
+fn favicon_response(config: &Config, head: bool) -> Response {
+    let favicon = PathBuf::from(config.favicon().unwrap());
+    let content = match fs::read(&favicon) {
+        Ok(content) => content,
+        Err(err) => {
+            warn!("Can't read favicon {:?}: {}", favicon, err);
+            let mut response = Response::from_io_error(&err);
+            add_default_headers(&mut response);
+            return response;
+        },
+    };
+    let content_length = content.len();
+    let mut response = Response::new(
+        http::VERSION.to_string(),
+        Status::Ok,
+        content);
+    response.add_header(ResponseHeader::ContentLength(content_length));
+    response.add_header(ResponseHeader::ContentType(String::from("image/x-icon")));
+    response.add_header(ResponseHeader::CacheControl(format!("public, max-age={}", FAVICON_MAX_AGE)));
+    add_default_headers(&mut response);
+
+    if head {
+        response.clear_body();
+    }
+
+    response
+}
+
+/// Answers a favicon request without a favicon, which browsers don't log as an error like a 404.
+fn no_content_response() -> Response {
+    Response::no_content()
+}
+
+/// The URL to redirect to if the trailing slash of the requested URL must be normalized.
+///
+/// Only URLs of existing directories or files are redirected, everything else is left to the
+/// normal lookup.
+fn normalized_location(config: &Config, assets: &AssetSource, method: &Method, request: &Request) -> Option<String> {
+    match *method {
+        Method::Get | Method::Head => (),
+        _ => return None,
+    }
+
+    let path = request.path();
+    let query = request.target().query().map(|query| format!("?{}", query)).unwrap_or_default();
+
+    match config.trailing_slash() {
+        Some(TrailingSlash::Add) if !path.ends_with('/') && assets.is_directory(config, path) =>
+            Some(format!("{}/{}", path, query)),
+        Some(TrailingSlash::Remove) if path.len() > 1 && path.ends_with('/') && assets.is_file(config, path) =>
+            Some(format!("{}{}", path.trim_end_matches('/'), query)),
+        _ => None,
+    }
+}
+
+fn moved_permanently_response(location: String) -> Response {
+    let mut response = Response::new(
+        http::VERSION.to_string(),
+        Status::MovedPermanently,
+        Vec::new());
+    response.add_header(ResponseHeader::ContentLength(0));
+    response.add_header(ResponseHeader::Location(location));
+    add_default_headers(&mut response);
+    response
+}
+
+fn bad_request_response() -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::BadRequest,
+        "Bad request!".as_bytes().to_vec());
+    response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+    add_default_headers(&mut response);
+    response
+}
+
+/// Answers a request which was not received completely within the read timeout.
+fn request_timeout_response() -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::RequestTimeout,
+        "Request not received in time!".as_bytes().to_vec());
+    response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+    add_default_headers(&mut response);
+    response
+}
+
+fn uri_too_long_response() -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::UriTooLong,
+        "Request URI too long!".as_bytes().to_vec());
+    response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+    add_default_headers(&mut response);
+    response
+}
+
+/// Answers a request whose request line and headers exceed the maximum size, see RFC 6585 section 5.
+fn header_fields_too_large_response() -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::RequestHeaderFieldsTooLarge,
+        "Request header fields too large!".as_bytes().to_vec());
+    response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+    add_default_headers(&mut response);
+    response
+}
+
+/// Whether the request asks for the metrics, which are only served if a path is configured.
+fn serves_metrics(config: &Config, request: &Result<Request, ParseError>) -> bool {
+    let request = match *request {
+        Ok(ref request) => request,
+        Err(_) => return false,
+    };
+
+    match method_of(config, request) {
+        Method::Get | Method::Head => config.metrics_path().is_some_and(|path| path == request.path()),
+        _ => false,
+    }
+}
+
+fn metrics_response(metrics: &Metrics, request: &Result<Request, ParseError>) -> Response {
+    let body = metrics.render().into_bytes();
+    let content_length = body.len();
+    let mut response = Response::new(
+        http::VERSION.to_string(),
+        Status::Ok,
+        body);
+    response.add_header(ResponseHeader::ContentLength(content_length));
+    response.add_header(ResponseHeader::ContentType(String::from(metrics::CONTENT_TYPE)));
+    response.no_cache();
+    add_default_headers(&mut response);
+
+    // Only requests accepted as GET or HEAD get here, so the case needs no check.
+    if request.as_ref().is_ok_and(|request| request.method_is("HEAD")) {
+        response.clear_body();
+    }
+
+    response
+}
+
+fn payload_too_large_response() -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::PayloadTooLarge,
+        "Payload too large!".as_bytes().to_vec());
+    response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+    add_default_headers(&mut response);
+    response
+}
+
+fn unsupported_media_type_response() -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::UnsupportedMediaType,
+        "Unsupported content encoding!".as_bytes().to_vec());
+    response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+    add_default_headers(&mut response);
+    response
+}
+
+fn expectation_failed_response() -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::ExpectationFailed,
+        "Expectation failed!".as_bytes().to_vec());
+    response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+    add_default_headers(&mut response);
+    response
+}
+
+fn http_version_not_supported_response() -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::HttpVersionNotSupported,
+        "HTTP version not supported!".as_bytes().to_vec());
+    response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+    add_default_headers(&mut response);
+    response
+}
+
+fn websocket_handshake_response(request: &Request) -> Response {
+    match websocket::handshake_response(request) {
+        Ok(response) => response,
+        Err(message) => {
+            debug!("Rejecting WebSocket handshake: {}", message);
+            bad_request_response()
+        },
+    }
+}
+
+fn too_many_requests_response(retry_after: Duration) -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::TooManyRequests,
+        "Too many requests!".as_bytes().to_vec());
+    response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+    response.add_header(ResponseHeader::RetryAfter(retry_after.as_secs()));
+    add_default_headers(&mut response);
+    response
+}
+
+fn service_unavailable_response() -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::ServiceUnavailable,
+        "Too many connections!".as_bytes().to_vec());
+    response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+    add_default_headers(&mut response);
+    response
+}
+
+fn internal_server_error_response() -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::InternalServerError,
+        "Internal server error!".as_bytes().to_vec());
+    response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+    add_default_headers(&mut response);
+    response
+}
+
+fn handler_timeout_response() -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::ServiceUnavailable,
+        "Response took too long!".as_bytes().to_vec());
+    response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+    add_default_headers(&mut response);
+    response
+}
+
+fn range_not_satisfiable_response(length: usize) -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::RangeNotSatisfiable,
+        Vec::new());
+    response.add_header(ResponseHeader::ContentLength(0));
+    response.add_header(ResponseHeader::ContentRange(format!("bytes */{}", length)));
+    response
+}
+
+fn bad_gateway_response() -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::BadGateway,
+        "Bad gateway!".as_bytes().to_vec());
+    response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+    add_default_headers(&mut response);
+    response
+}
+
+fn not_implemented_response(message: &str) -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::NotImplemented,
+        message.as_bytes().to_vec());
+    response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+    add_default_headers(&mut response);
+    response
+}
+
+fn not_found_response() -> Response {
+    let mut response = Response::new(
+        String::from("1.1"),
+        Status::NotFound,
+        "Not found!".as_bytes().to_vec());
+    response.add_header(ResponseHeader::ContentType(String::from("text/plain; charset=utf-8")));
+    response
+}
+
+fn determine_content_type(file_name: &PathBuf) -> String {
+    match file_name.extension() {
+        Some(extension) => {
+            match extension.to_str().unwrap() {
+                "html" | "htm" => String::from("text/html"),
+                "css" => String::from("text/css"),
+                "js" => String::from("text/javascript"),
+                "ico" => String::from("image/x-icon"),
+                "png" => String::from("image/png"),
+                "jpg" | "jpeg" => String::from("image/jpeg"),
+                "gif" => String::from("image/gif"),
+                "pdf" => String::from("application/pdf"),
+                "gz" => String::from("application/gzip"),
+                _ => String::from("text/plain"),
+            }
+        },
+        None => String::from("text/plain"),
+    }
+}
+
+/// Marks the response as attachment if the file has one of the configured extensions.
+fn add_content_disposition_header(config: &Config, resource: &Path, response: &mut Response) {
+    if !config.is_attachment(resource) {
+        return;
+    }
+
+    if let Some(file_name) = resource.file_name() {
+        response.add_header(ResponseHeader::ContentDisposition(file_name.to_string_lossy().into_owned()));
+    }
+}
+
+/// Adds the headers every response has, except `Server` which depends on the configuration.
+///
+/// `Accept-Ranges` is not one of them: only static files support byte ranges and advertise it.
+fn add_default_headers(response: &mut Response) {
+    response.add_header(ResponseHeader::Date(formatted_now()));
+}
+
+/// Adds the `Server` header unless it is suppressed by the configuration.
+///
+/// It is added to every response written to a connection, regardless of how it was created.
+fn add_server_header(config: &Config, response: &mut Response) {
+    if let Some(server_token) = config.server_token() {
+        response.add_header(ResponseHeader::Server(String::from(server_token)));
+    }
+}
+
+fn formatted_now() -> String {
+    // http://man7.org/linux/man-pages/man3/strftime.3.html
+    // Wed, 14 Feb 2018 12:17:24 GMT
+    time::strftime("%a, %d %b %Y %H:%M:%S %Z", &time::now())
+        .expect("Can't format date!")
+}
+
+/// Formats seconds since the epoch as [HTTP date](https://tools.ietf.org/html/rfc7231#section-7.1.1.1).
+fn format_http_date(seconds: i64) -> String {
+    time::strftime(HTTP_DATE_FORMAT, &time::at_utc(time::Timespec::new(seconds, 0)))
+        .expect("Can't format date!")
+}
+
+/// Parses an HTTP date into seconds since the epoch.
+///
+/// Only the preferred IMF-fixdate format is accepted, invalid dates are ignored as RFC 7232
+/// section 3.3 requires.
+fn parse_http_date(date: &str) -> Option<i64> {
+    time::strptime(date.trim(), HTTP_DATE_FORMAT).ok().map(|date| date.to_timespec().sec)
+}
+
+/// Whether the comma separated list of an `If-None-Match` header contains the entity tag.
+///
+/// The weak comparison of RFC 7232 section 2.3.2 is used, ignoring the `W/` prefix.
+fn matches_any_etag(list: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+
+    list.split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+    use std::env;
+    use std::net::Shutdown;
+    use std::process;
+    use std::thread;
+    use {Mount, Proxy, RouteLimit};
+    use context::HandlerError;
+
+    #[test]
+    fn test_determine_content_type_from_file_name() {
+        assert_that!(
+            determine_content_type(&PathBuf::from("")),
+            is(equal_to(String::from("text/plain")))
+        );
+        assert_that!(
+            determine_content_type(&PathBuf::from("index.html")),
+            is(equal_to(String::from("text/html")))
+        );
+        assert_that!(
+            determine_content_type(&PathBuf::from("new.index.htm")),
+            is(equal_to(String::from("text/html")))
+        );
+        assert_that!(
+            determine_content_type(&PathBuf::from("/foo/bar/new.index.html")),
+            is(equal_to(String::from("text/html")))
+        );
+        assert_that!(
+            determine_content_type(&PathBuf::from("foo.abr.css")),
+            is(equal_to(String::from("text/css")))
+        );
+        assert_that!(
+            determine_content_type(&PathBuf::from("/foo/bar/new.index.js")),
+            is(equal_to(String::from("text/javascript")))
+        );
+        assert_that!(
+            determine_content_type(&PathBuf::from("/foo/bar/new.index.js")),
+            is(equal_to(String::from("text/javascript")))
+        );
+        assert_that!(
+            determine_content_type(&PathBuf::from("/foo/bar/favicon.ico")),
+            is(equal_to(String::from("image/x-icon")))
+        );
+        assert_that!(
+            determine_content_type(&PathBuf::from("logo.png")),
+            is(equal_to(String::from("image/png")))
+        );
+        assert_that!(
+            determine_content_type(&PathBuf::from("photo.jpeg")),
+            is(equal_to(String::from("image/jpeg")))
+        );
+        assert_that!(
+            determine_content_type(&PathBuf::from("manual.pdf")),
+            is(equal_to(String::from("application/pdf")))
+        );
+    }
+
+    fn respond_raw(config: Config, raw_request: &str) -> Response {
+        let parsed = parse_request(&config, raw_request);
+        respond(config, &test_services(Metrics::new()), parsed, raw_request, test_peer())
+    }
+
+    fn test_peer() -> SocketAddr {
+        "127.0.0.1:50000".parse().unwrap()
+    }
+
+    fn test_config() -> Config {
+        Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("test/web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+    }
+
+    fn json_not_found(context: &mut Context) -> Result<Response, HandlerError> {
+        let body = format!("{{\"error\":\"not found\",\"path\":\"{}\"}}", context.request().url()).into_bytes();
+        let mut response = Response::new(http::VERSION.to_string(), Status::NotFound, body);
+        response.add_header(ResponseHeader::ContentType(String::from("application/json")));
+        Ok(response)
+    }
+
+    fn unreadable_error_page(_context: &mut Context) -> Result<Response, HandlerError> {
+        let content = fs::read("test/web_dir/errors/404.html")?;
+        Ok(Response::new(http::VERSION.to_string(), Status::NotFound, content))
+    }
+
+    #[test]
+    fn test_respond_calls_not_found_handler_if_nothing_matches() {
+        let request_fixture = "GET /missing HTTP/1.1\r\nHost: localhost:8080\r\n";
+        let parsed = parse_request(&test_config(), request_fixture);
+        let services = Services { not_found_handler: Some(json_not_found), ..test_services(Metrics::new()) };
+
+        let response = respond(test_config(), &services, parsed, request_fixture, test_peer());
+        let rendered = String::from_utf8_lossy(&response.render()).into_owned();
+
+        assert_that!(rendered.starts_with("HTTP/1.1 404 NOT FOUND\r\nContent-Type: application/json\r\n"), is(true));
+        assert_that!(rendered.ends_with("\r\n\r\n{\"error\":\"not found\",\"path\":\"/missing\"}"), is(true));
+    }
+
+    fn echo_peer(context: &mut Context) -> Result<Response, HandlerError> {
+        Ok(Response::new(http::VERSION.to_string(), Status::Ok, context.peer().to_string().into_bytes()))
+    }
+
+    #[test]
+    fn test_respond_passes_address_of_client_to_handler() {
+        let request_fixture = "GET /missing HTTP/1.1\r\nHost: localhost:8080\r\n";
+        let parsed = parse_request(&test_config(), request_fixture);
+        let services = Services { not_found_handler: Some(echo_peer), ..test_services(Metrics::new()) };
+        let peer = "192.0.2.7:4242".parse().unwrap();
+
+        let response = respond(test_config(), &services, parsed, request_fixture, peer);
+
+        assert_that!(String::from_utf8_lossy(response.body()).into_owned(), is(equal_to(String::from("192.0.2.7:4242"))));
+    }
+
+    #[test]
+    fn test_respond_answers_error_of_not_found_handler() {
+        let request_fixture = "GET /missing HTTP/1.1\r\nHost: localhost:8080\r\n";
+        let parsed = parse_request(&test_config(), request_fixture);
+        let services = Services { not_found_handler: Some(unreadable_error_page), ..test_services(Metrics::new()) };
+
+        let response = respond(test_config(), &services, parsed, request_fixture, test_peer());
+        let rendered = String::from_utf8_lossy(&response.render()).into_owned();
+
+        assert_that!(rendered.starts_with("HTTP/1.1 404 NOT FOUND\r\nContent-Type: text/plain; charset=utf-8\r\n"), is(true));
+        assert_that!(rendered.ends_with("\r\n\r\nNot found!"), is(true));
+    }
+
+    fn sleeping_middleware(context: &mut Context, next: Next) -> Response {
+        thread::sleep(Duration::from_secs(3));
+        next.run(context)
+    }
+
+    #[test]
+    fn test_respond_answers_service_unavailable_if_handler_overruns_timeout() {
+        let config = test_config().with_handler_timeout(1).unwrap();
+        let request_fixture = "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n";
+        let parsed = parse_request(&config, request_fixture);
+        let services = Services { middlewares: Arc::from(vec!(sleeping_middleware as Middleware)), ..test_services(Metrics::new()) };
+
+        let started = Instant::now();
+        let response = respond(config, &services, parsed, request_fixture, test_peer());
+
+        assert_that!(format!("{}", response.status()).as_str(), is(equal_to("503 SERVICE UNAVAILABLE")));
+        assert_that!(response.body().to_vec(), is(equal_to(b"Response took too long!".to_vec())));
+        assert_that!(started.elapsed() < Duration::from_secs(3), is(true));
+    }
+
+    fn panicking_middleware(_context: &mut Context, _next: Next) -> Response {
+        panic!("Handler failed!");
+    }
+
+    #[test]
+    fn test_respond_answers_internal_server_error_if_handler_panics_with_timeout() {
+        let config = test_config().with_handler_timeout(5).unwrap();
+        let request_fixture = "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n";
+        let parsed = parse_request(&config, request_fixture);
+        let services = Services { middlewares: Arc::from(vec!(panicking_middleware as Middleware)), ..test_services(Metrics::new()) };
+
+        let response = respond(config, &services, parsed, request_fixture, test_peer());
+
+        assert_that!(format!("{}", response.status()).as_str(), is(equal_to("500 INTERNAL SERVER ERROR")));
+    }
+
+    #[test]
+    fn test_respond_rejects_request_while_overrunning_handlers_occupy_all_threads() {
+        let config = test_config().with_handler_timeout(1).unwrap();
+        let request_fixture = "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n";
+        let services = Services {
+            middlewares: Arc::from(vec!(sleeping_middleware as Middleware)),
+            handler_threads: HandlerThreads::new(1),
+            ..test_services(Metrics::new())
+        };
+
+        let response = respond(config.clone(), &services, parse_request(&config, request_fixture), request_fixture, test_peer());
+        assert_that!(response.body().to_vec(), is(equal_to(b"Response took too long!".to_vec())));
+
+        let started = Instant::now();
+        let response = respond(config.clone(), &services, parse_request(&config, request_fixture), request_fixture, test_peer());
+        assert_that!(format!("{}", response.status()).as_str(), is(equal_to("503 SERVICE UNAVAILABLE")));
+        assert_that!(response.body().to_vec() == b"Response took too long!".to_vec(), is(false));
+        assert_that!(started.elapsed() < Duration::from_millis(500), is(true));
+    }
+
+    #[test]
+    fn test_handler_threads_are_released_by_guard() {
+        let sut = HandlerThreads::new(1);
+
+        let guard = sut.acquire();
+        assert_that!(guard.is_some(), is(true));
+        assert_that!(sut.acquire().is_none(), is(true));
+
+        drop(guard);
+        assert_that!(sut.acquire().is_some(), is(true));
+    }
+
+    #[test]
+    fn test_respond_within_handler_timeout() {
+        let config = test_config().with_handler_timeout(5).unwrap();
+
+        let response = respond_raw(config, "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n");
+
+        assert_that!(format!("{}", response.status()).as_str(), is(equal_to("200 OK")));
+    }
+
+    #[test]
+    fn test_respond_serves_files_despite_not_found_handler() {
+        let request_fixture = "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n";
+        let parsed = parse_request(&test_config(), request_fixture);
+        let services = Services { not_found_handler: Some(json_not_found), ..test_services(Metrics::new()) };
+
+        let response = respond(test_config(), &services, parsed, request_fixture, test_peer());
+
+        assert_that!(format!("{}", response.status()).as_str(), is(equal_to("200 OK")));
+    }
+
+    #[test]
+    fn test_respond_serves_spa_fallback_for_unknown_html_routes() {
+        let config = test_config().with_spa_fallback(String::from("test/web_dir/index.html")).unwrap();
+        let request_fixture = "GET /users/42?tab=posts HTTP/1.1\r\nHost: localhost:8080\r\nAccept: text/html,application/xhtml+xml\r\n";
+
+        let response = respond_raw(config, request_fixture);
+
+        assert_that!(format!("{}", response.status()).as_str(), is(equal_to("200 OK")));
+        assert_that!(response.body().to_vec(), is(equal_to(fs::read("test/web_dir/index.html").unwrap())));
+    }
+
+    #[test]
+    fn test_respond_serves_no_spa_fallback_for_files_or_other_content_types() {
+        let config = test_config().with_spa_fallback(String::from("test/web_dir/index.html")).unwrap();
+
+        for request_fixture in &[
+            "GET /js/missing.js HTTP/1.1\r\nHost: localhost:8080\r\nAccept: text/html,*/*\r\n",
+            "GET /users/42 HTTP/1.1\r\nHost: localhost:8080\r\nAccept: application/json\r\n"] {
+            let response = respond_raw(config.clone(), request_fixture);
+
+            assert_that!(format!("{}", response.status()).as_str(), is(equal_to("404 NOT FOUND")));
+        }
+    }
+
+    #[test]
+    fn test_respond_rejects_obsolete_line_folding_with_bad_request() {
+        let request_fixture = "GET /index.html HTTP/1.1\r\nHost: localhost:8080\r\nX-Folded: foo\r\n bar\r\n";
+
+        let rendered = respond_raw(test_config(), request_fixture).render();
+
+        assert_that!(
+            String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 400 BAD REQUEST\r\n"),
+            is(true));
+    }
+
+    #[test]
+    fn test_respond_fails_expectation_for_too_large_body() {
+        let request_fixture = "PUT /index.html HTTP/1.1\r\nHost: localhost:8080\r\nContent-Length: 10000000\r\nExpect: 100-continue\r\n";
+
+        let rendered = respond_raw(test_config(), request_fixture).render();
+
+        assert_that!(
+            String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 417 EXPECTATION FAILED\r\n"),
+            is(true));
+    }
+
+    fn post_with_body(content_encoding: &str, body: &[u8]) -> Request {
+        let request_fixture = format!(
+            "POST /upload HTTP/1.1\r\nHost: localhost:8080\r\nContent-Length: {}\r\nContent-Encoding: {}\r\n",
+            body.len(),
+            content_encoding);
+
+        http::parse_request(&request_fixture).unwrap()
+    }
+
+    #[test]
+    fn test_receive_body_takes_pending_bytes_first() {
+        let request = post_with_body("", b"Hello, World!");
+        let mut pending = b"Hello".to_vec();
+        let mut stream = io::Cursor::new(b", World!GET / HTTP/1.1\r\n\r\n".to_vec());
+
+        let request = receive_body(&mut stream, &mut pending, request, 4096).unwrap();
+
+        assert_that!(request.body(), is(equal_to(&b"Hello, World!"[..])));
+        assert_that!(pending.is_empty(), is(true));
+    }
+
+    #[test]
+    fn test_receive_body_decompresses_gzip() {
+        let body = file::read_bytes(&PathBuf::from("test/fixtures/hello.txt.gz")).unwrap();
+        let request = post_with_body("gzip", &body);
+        let mut pending = body;
+
+        let request = receive_body(&mut io::Cursor::new(Vec::new()), &mut pending, request, 4096).unwrap();
+
+        assert_that!(request.body(), is(equal_to(&b"Hello, World!"[..])));
+    }
+
+    #[test]
+    fn test_receive_body_rejects_decompressed_body_over_limit() {
+        let body = file::read_bytes(&PathBuf::from("test/fixtures/zeros.gz")).unwrap();
+        let request = post_with_body("gzip", &body);
+        let mut pending = body;
+
+        let response = receive_body(&mut io::Cursor::new(Vec::new()), &mut pending, request, 4096).unwrap_err();
+
+        assert_that!(
+            String::from_utf8_lossy(&response.render()).starts_with("HTTP/1.1 413 PAYLOAD TOO LARGE\r\n"),
+            is(true));
+    }
+
+    fn post_chunked() -> Request {
+        http::parse_request("POST /upload HTTP/1.1\r\nHost: localhost:8080\r\nTransfer-Encoding: chunked\r\n").unwrap()
+    }
+
+    #[test]
+    fn test_receive_body_rejects_unsupported_transfer_encoding() {
+        let request = http::parse_request(
+            "POST /upload HTTP/1.1\r\nHost: localhost:8080\r\nTransfer-Encoding: gzip\r\n").unwrap();
+
+        let response = receive_body(&mut io::Cursor::new(b"hello".to_vec()), &mut Vec::new(), request, 4096).unwrap_err();
+
+        assert_that!(
+            String::from_utf8_lossy(&response.render()).starts_with("HTTP/1.1 501 NOT IMPLEMENTED\r\n"),
+            is(true));
+    }
+
+    #[test]
+    fn test_receive_body_accepts_identity_transfer_encoding_without_body() {
+        let request = http::parse_request(
+            "POST /upload HTTP/1.1\r\nHost: localhost:8080\r\nTransfer-Encoding: identity\r\n").unwrap();
+
+        let request = receive_body(&mut io::Cursor::new(Vec::new()), &mut Vec::new(), request, 4096).unwrap();
+
+        assert_that!(request.body().is_empty(), is(true));
+    }
+
+    #[test]
+    fn test_receive_body_rejects_transfer_encoding_with_content_length() {
+        for transfer_encoding in ["chunked", "identity"].iter() {
+            let request = http::parse_request(&format!(
+                "POST /upload HTTP/1.1\r\nHost: localhost:8080\r\nTransfer-Encoding: {}\r\nContent-Length: 5\r\n",
+                transfer_encoding)).unwrap();
+
+            let response = receive_body(&mut io::Cursor::new(b"hello".to_vec()), &mut Vec::new(), request, 4096).unwrap_err();
+
+            assert_that!(
+                String::from_utf8_lossy(&response.render()).starts_with("HTTP/1.1 400 BAD REQUEST\r\n"),
+                is(true));
+        }
+    }
+
+    #[test]
+    fn test_receive_body_decodes_chunked_body() {
+        let mut pending = b"5\r\nHello\r\n".to_vec();
+        let mut stream = io::Cursor::new(b"8\r\n, World!\r\n0\r\n\r\nGET / HTTP/1.1\r\n\r\n".to_vec());
+
+        let request = receive_body(&mut stream, &mut pending, post_chunked(), 4096).unwrap();
+
+        assert_that!(request.body(), is(equal_to(&b"Hello, World!"[..])));
+        assert_that!(pending, is(equal_to(b"GET / HTTP/1.1\r\n\r\n".to_vec())));
+    }
+
+    #[test]
+    fn test_receive_body_rejects_chunked_body_exceeding_limit_mid_stream() {
+        let chunk = format!("100\r\n{}\r\n", "a".repeat(256));
+        let mut stream = io::Cursor::new(chunk.repeat(100).into_bytes());
+
+        let response = receive_body(&mut stream, &mut Vec::new(), post_chunked(), 4096).unwrap_err();
+
+        assert_that!(
+            String::from_utf8_lossy(&response.render()).starts_with("HTTP/1.1 413 PAYLOAD TOO LARGE\r\n"),
+            is(true));
+        // Reading stopped right after the limit was exceeded, long before the end of the body.
+        assert_that!(stream.position() < (chunk.len() * 50) as u64, is(true));
+    }
+
+    #[test]
+    fn test_receive_body_rejects_unsupported_encoding() {
+        let request = post_with_body("br", b"compressed");
+
+        let response = receive_body(&mut io::Cursor::new(Vec::new()), &mut Vec::new(), request, 4096).unwrap_err();
+
+        assert_that!(
+            String::from_utf8_lossy(&response.render()).starts_with("HTTP/1.1 415 UNSUPPORTED MEDIA TYPE\r\n"),
+            is(true));
+    }
+
+    #[test]
+    fn test_respond_rejects_invalid_content_length_with_bad_request() {
+        let request_fixture = "GET /index.html HTTP/1.1\r\nHost: localhost:8080\r\nContent-Length: abc\r\n";
+
+        let rendered = respond_raw(test_config(), request_fixture).render();
+
+        assert_that!(
+            String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 400 BAD REQUEST\r\n"),
+            is(true));
+    }
+
+    #[test]
+    fn test_respond_rejects_malformed_version_with_bad_request() {
+        for request_fixture in &["GET /index.html HTTP\r\nHost: localhost:8080\r\n", "GET /index.html FOO/1.1\r\n"] {
+            let rendered = respond_raw(test_config(), request_fixture).render();
+
+            assert_that!(
+                String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 400 BAD REQUEST\r\n"),
+                is(true));
+        }
+    }
+
+    #[test]
+    fn test_respond_rejects_invalid_method_with_bad_request() {
+        let rendered = respond_raw(test_config(), "G\u{7}T /index.html HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+
+        assert_that!(
+            String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 400 BAD REQUEST\r\n"),
+            is(true));
+    }
+
+    #[test]
+    fn test_respond_accepts_lowercase_method_only_if_lenient() {
+        let request_fixture = "get /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n";
+
+        let strict = respond_raw(test_config(), request_fixture);
+        let lenient = respond_raw(test_config().with_lenient_methods(true), request_fixture);
+
+        assert_that!(format!("{}", strict.status()).as_str(), is(equal_to("405 METHOD NOT ALLOWED")));
+        assert_that!(format!("{}", lenient.status()).as_str(), is(equal_to("200 OK")));
+        assert_that!(lenient.body().is_empty(), is(false));
+    }
+
+    #[test]
+    fn test_respond_rejects_unsupported_version() {
+        let rendered = respond_raw(test_config(), "GET /index.html HTTP/2.0\r\nHost: localhost:8080\r\n").render();
+
+        assert_that!(
+            String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 505 HTTP VERSION NOT SUPPORTED\r\n"),
+            is(true));
+    }
+
+    #[test]
+    fn test_respond_accepts_expectation_for_small_body() {
+        let request_fixture = "GET /index.html HTTP/1.1\r\nHost: localhost:8080\r\nContent-Length: 10\r\nExpect: 100-continue\r\n";
+
+        let rendered = respond_raw(test_config(), request_fixture).render();
+
+        assert_that!(
+            String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 200 OK\r\n"),
+            is(true));
+    }
+
+    fn empty_web_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("webserver-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_respond_follows_symlinks_outside_of_web_dir_only_if_enabled() {
+        use std::os::unix::fs::symlink;
+
+        let dir = empty_web_dir("symlinks");
+        let web_dir = dir.join("web");
+        fs::create_dir_all(&web_dir).unwrap();
+        fs::write(web_dir.join("page.html"), "<h1>Page</h1>").unwrap();
+        fs::write(dir.join("secret.txt"), "secret").unwrap();
+        let _ = symlink(web_dir.join("page.html"), web_dir.join("link.html"));
+        let _ = symlink(dir.join("secret.txt"), web_dir.join("leak.txt"));
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            web_dir.to_string_lossy().into_owned(),
+            String::from("debug"),
+            String::from("logs")).unwrap();
+        let status_line = |config: &Config, url: &str| {
+            let rendered = respond_raw(config.clone(), &format!("GET {} HTTP/1.1\r\nHost: localhost:8080\r\n", url)).render();
+            String::from_utf8_lossy(&rendered).lines().next().unwrap_or("").to_string()
+        };
+
+        assert_that!(status_line(&config, "/link.html"), is(equal_to(String::from("HTTP/1.1 200 OK"))));
+        assert_that!(status_line(&config, "/leak.txt"), is(equal_to(String::from("HTTP/1.1 404 NOT FOUND"))));
+
+        let config = config.with_follow_symlinks(true);
+        assert_that!(status_line(&config, "/leak.txt"), is(equal_to(String::from("HTTP/1.1 200 OK"))));
+    }
+
+    #[test]
+    fn test_respond_with_welcome_page_for_empty_web_dir_if_enabled() {
+        let web_dir = empty_web_dir("welcome-enabled");
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            web_dir.to_string_lossy().into_owned(),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+            .with_welcome_page(true);
+
+        let rendered = respond_raw(config.clone(), "GET / HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        let rendered = String::from_utf8_lossy(&rendered);
+        assert_that!(rendered.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        assert_that!(rendered.contains("It works!"), is(true));
+
+        let rendered = respond_raw(config, "GET /other.html HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 404 NOT FOUND\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_without_welcome_page_if_disabled() {
+        let web_dir = empty_web_dir("welcome-disabled");
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            web_dir.to_string_lossy().into_owned(),
+            String::from("debug"),
+            String::from("logs")).unwrap();
+
+        let rendered = respond_raw(config, "GET / HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 404 NOT FOUND\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_without_welcome_page_if_web_dir_has_content() {
+        let config = test_config().with_welcome_page(true);
+
+        let rendered = respond_raw(config, "GET / HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        let rendered = String::from_utf8_lossy(&rendered);
+
+        assert_that!(rendered.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        assert_that!(rendered.contains("It works!"), is(false));
+    }
+
+    #[test]
+    fn test_respond_serves_configured_favicon() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            empty_web_dir("favicon-configured").to_string_lossy().into_owned(),
+            String::from("debug"),
+            String::from("logs")).unwrap()
+            .with_favicon(String::from("test/web_dir/favicon.ico")).unwrap();
+
+        let response = respond_raw(config, "GET /favicon.ico HTTP/1.1\r\nHost: localhost:8080\r\n");
+        let rendered = response.render();
+        let rendered = String::from_utf8_lossy(&rendered);
+
+        assert_that!(rendered.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        assert_that!(rendered.contains("\r\nContent-Type: image/x-icon\r\n"), is(true));
+        assert_that!(rendered.contains("\r\nCache-Control: public, max-age=604800\r\n"), is(true));
+        assert_that!(
+            response.body_length(),
+            is(equal_to(file::read_bytes(&PathBuf::from("test/web_dir/favicon.ico")).unwrap().len())));
+    }
+
+    #[test]
+    fn test_respond_with_no_content_if_there_is_no_favicon() {
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            empty_web_dir("favicon-missing").to_string_lossy().into_owned(),
+            String::from("debug"),
+            String::from("logs")).unwrap();
+
+        let rendered = respond_raw(config, "GET /favicon.ico HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        let rendered = String::from_utf8_lossy(&rendered);
+
+        assert_that!(rendered.starts_with("HTTP/1.1 204 NO CONTENT\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_redirects_plain_http_to_https_if_forced() {
+        let config = test_config().with_force_https(true);
+
+        let rendered = respond_raw(config.clone(), "GET /hello.html HTTP/1.1\r\nHost: example.com:8080\r\n").render();
+        let rendered = String::from_utf8_lossy(&rendered);
+
+        assert_that!(rendered.starts_with("HTTP/1.1 308 PERMANENT REDIRECT\r\n"), is(true));
+        assert_that!(rendered.contains("\r\nLocation: https://example.com/hello.html\r\n"), is(true));
+
+        let config = config.with_https_port(8443).unwrap();
+        let rendered = respond_raw(config, "POST /hello.html HTTP/1.1\r\nHost: example.com:8080\r\n").render();
+        let rendered = String::from_utf8_lossy(&rendered);
+
+        assert_that!(rendered.starts_with("HTTP/1.1 308 PERMANENT REDIRECT\r\n"), is(true));
+        assert_that!(rendered.contains("\r\nLocation: https://example.com:8443/hello.html\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_does_not_redirect_requests_forwarded_over_https_by_trusted_proxy() {
+        let config = test_config().with_force_https(true);
+        let forwarded = "GET /hello.html HTTP/1.1\r\nHost: example.com\r\nX-Forwarded-Proto: https\r\n";
+
+        let rendered = respond_raw(config.clone(), forwarded).render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 308 PERMANENT REDIRECT\r\n"), is(true));
+
+        let config = config.with_trusted_proxies(vec!(String::from("127.0.0.1"))).unwrap();
+        let rendered = respond_raw(config.clone(), forwarded).render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+
+        let rendered = respond_raw(config, "GET /hello.html HTTP/1.1\r\nHost: example.com\r\nX-Forwarded-Proto: http\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 308 PERMANENT REDIRECT\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_redirects_directory_to_trailing_slash() {
+        let config = test_config().with_trailing_slash(TrailingSlash::Add);
+
+        let rendered = respond_raw(config.clone(), "GET /css HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        let rendered = String::from_utf8_lossy(&rendered);
+        assert_that!(rendered.starts_with("HTTP/1.1 301 MOVED PERMANENTLY\r\n"), is(true));
+        assert_that!(rendered.contains("\r\nLocation: /css/\r\n"), is(true));
+
+        let rendered = respond_raw(config, "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_redirects_directory_to_trailing_slash_keeping_query() {
+        let config = test_config().with_trailing_slash(TrailingSlash::Add);
+
+        let rendered = respond_raw(config, "GET /css?v=2 HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+
+        assert_that!(String::from_utf8_lossy(&rendered).contains("\r\nLocation: /css/?v=2\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_asset_response_answers_unreadable_file_with_error_status() {
+        let request = http::parse_request("GET /gone.html HTTP/1.1\r\nHost: localhost:8080\r\n").unwrap();
+        let status = |path: &str| {
+            let asset = Asset::File(PathBuf::from(path));
+            format!("{}", asset_response(&test_config(), &request, &asset, &Validators::of(&asset)).status())
+        };
+
+        assert_that!(status("test/web_dir/gone.html"), is(equal_to(String::from("404 NOT FOUND"))));
+        assert_that!(status("test/web_dir/css"), is(equal_to(String::from("500 INTERNAL SERVER ERROR"))));
+    }
+
+    #[test]
+    fn test_respond_serves_files_regardless_of_query_and_target_form() {
+        for target in ["/hello.html?x=1", "http://localhost:8080/hello.html", "http://localhost:8080/hello.html?x=1"].iter() {
+            let rendered = respond_raw(test_config(), &format!("GET {} HTTP/1.1\r\nHost: localhost:8080\r\n", target)).render();
+            let rendered = String::from_utf8_lossy(&rendered);
+
+            assert_that!(rendered.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+            assert_that!(rendered.contains("\r\nContent-Length: 516\r\n"), is(true));
+        }
+    }
+
+    #[test]
+    fn test_respond_redirects_file_without_trailing_slash() {
+        let config = test_config().with_trailing_slash(TrailingSlash::Remove);
+
+        let rendered = respond_raw(config.clone(), "GET /hello.html/ HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        let rendered = String::from_utf8_lossy(&rendered);
+        assert_that!(rendered.starts_with("HTTP/1.1 301 MOVED PERMANENTLY\r\n"), is(true));
+        assert_that!(rendered.contains("\r\nLocation: /hello.html\r\n"), is(true));
+
+        let rendered = respond_raw(config, "GET /css HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 301"), is(false));
+    }
+
+    #[test]
+    fn test_respond_serves_configured_extensions_as_attachment() {
+        let config = test_config().with_attachment_extensions(vec!(String::from("ico")));
+
+        let rendered = respond_raw(config.clone(), "GET /favicon.ico HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        let rendered = String::from_utf8_lossy(&rendered);
+        assert_that!(rendered.contains("\r\nContent-Disposition: attachment; filename=\"favicon.ico\"\r\n"), is(true));
+
+        let rendered = respond_raw(config, "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).contains("Content-Disposition"), is(false));
+    }
+
+    fn get_with_range(if_range: &str) -> String {
+        let request_fixture = format!(
+            "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nRange: bytes=0-4\r\nIf-Range: {}\r\n",
+            if_range);
+        String::from_utf8_lossy(&respond_raw(test_config(), &request_fixture).render()).into_owned()
+    }
+
+    #[test]
+    fn test_respond_with_partial_content_if_range_etag_matches() {
+        let validators = Validators::of_file(&PathBuf::from("test/web_dir/hello.html"));
+
+        let rendered = get_with_range(&validators.etag.unwrap());
+
+        assert_that!(rendered.starts_with("HTTP/1.1 206 PARTIAL CONTENT\r\n"), is(true));
+        assert_that!(rendered.contains("\r\nContent-Range: bytes 0-4/516\r\n"), is(true));
+        assert_that!(rendered.ends_with("\r\n\r\n<!DOC"), is(true));
+    }
+
+    #[test]
+    fn test_respond_with_partial_content_if_range_date_matches() {
+        let validators = Validators::of_file(&PathBuf::from("test/web_dir/hello.html"));
+
+        let rendered = get_with_range(&validators.last_modified.unwrap());
+
+        assert_that!(rendered.starts_with("HTTP/1.1 206 PARTIAL CONTENT\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_with_full_content_if_range_does_not_match() {
+        let rendered = get_with_range("\"outdated\"");
+
+        assert_that!(rendered.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        assert_that!(rendered.contains("\r\nContent-Length: 516\r\n"), is(true));
+        assert_that!(rendered.contains("Content-Range"), is(false));
+
+        let rendered = get_with_range("Wed, 14 Feb 2018 11:27:44 GMT");
+
+        assert_that!(rendered.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+    }
+
+    fn get_conditionally(conditions: &str) -> String {
+        let request_fixture = format!("GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n{}", conditions);
+        String::from_utf8_lossy(&respond_raw(test_config(), &request_fixture).render()).into_owned()
+    }
+
+    #[test]
+    fn test_respond_not_modified_if_etag_matches_despite_stale_date() {
+        let validators = Validators::of_file(&PathBuf::from("test/web_dir/hello.html"));
+        let etag = validators.etag.unwrap();
+
+        let rendered = get_conditionally(&format!(
+            "If-None-Match: \"other\", {}\r\nIf-Modified-Since: Mon, 01 Jan 1990 00:00:00 GMT\r\n", etag));
+
+        assert_that!(rendered.starts_with("HTTP/1.1 304 NOT MODIFIED\r\n"), is(true));
+        assert_that!(rendered.contains(&format!("\r\nETag: {}\r\n", etag)), is(true));
+        assert_that!(rendered.contains("Content-Length"), is(false));
+        assert_that!(rendered.ends_with("\r\n\r\n"), is(true));
+
+        let rendered = get_conditionally(&format!("If-None-Match: W/{}\r\n", etag));
+        assert_that!(rendered.starts_with("HTTP/1.1 304 NOT MODIFIED\r\n"), is(true));
+
+        let rendered = get_conditionally("If-None-Match: *\r\n");
+        assert_that!(rendered.starts_with("HTTP/1.1 304 NOT MODIFIED\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_with_content_if_etag_does_not_match_despite_fresh_date() {
+        let rendered = get_conditionally(
+            "If-None-Match: \"outdated\"\r\nIf-Modified-Since: Fri, 01 Jan 2100 00:00:00 GMT\r\n");
+
+        assert_that!(rendered.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        assert_that!(rendered.contains("\r\nContent-Length: 516\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_not_modified_if_not_modified_since_date() {
+        let validators = Validators::of_file(&PathBuf::from("test/web_dir/hello.html"));
+
+        let rendered = get_conditionally(&format!("If-Modified-Since: {}\r\n", validators.last_modified.unwrap()));
+        assert_that!(rendered.starts_with("HTTP/1.1 304 NOT MODIFIED\r\n"), is(true));
+
+        let rendered = get_conditionally("If-Modified-Since: Mon, 01 Jan 1990 00:00:00 GMT\r\n");
+        assert_that!(rendered.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+
+        let rendered = get_conditionally("If-Modified-Since: yesterday\r\n");
+        assert_that!(rendered.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_parse_http_date_inverts_format_http_date() {
+        assert_that!(parse_http_date(&format_http_date(1_518_607_664)), is(equal_to(Some(1_518_607_664))));
+        assert_that!(parse_http_date("Wednesday, 14-Feb-18 11:27:44 GMT"), is(equal_to(None)));
+    }
+
+    #[test]
+    fn test_respond_advertises_byte_ranges_only_for_files() {
+        let rendered = respond_raw(test_config(), "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).contains("\r\nAccept-Ranges: bytes\r\n"), is(true));
+
+        let rendered = respond_raw(test_config(), "HEAD /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).contains("\r\nAccept-Ranges: bytes\r\n"), is(true));
+
+        let rendered = respond_raw(test_config(), "OPTIONS / HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).contains("Accept-Ranges: bytes"), is(false));
+
+        let rendered = respond_raw(test_config().with_allow_trace(true), "TRACE / HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).contains("Accept-Ranges: bytes"), is(false));
+
+        let rendered = respond_raw(test_config(), "GET /missing.html HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).contains("Accept-Ranges: bytes"), is(false));
+    }
+
+    #[test]
+    fn test_respond_to_head_with_headers_of_get() {
+        // The Date header differs if the clock ticks between both responses.
+        fn header_lines(response: &Response) -> Vec<String> {
+            let rendered = String::from_utf8_lossy(&response.render()).into_owned();
+            rendered.split("\r\n\r\n").next().unwrap().lines()
+                .skip(1)
+                .filter(|header| !header.starts_with("Date: "))
+                .map(String::from)
+                .collect()
+        }
+
+        for url in ["/hello.html", "/missing.html"].iter() {
+            let get = respond_raw(test_config(), &format!("GET {} HTTP/1.1\r\nHost: localhost:8080\r\n", url));
+            let head = respond_raw(test_config(), &format!("HEAD {} HTTP/1.1\r\nHost: localhost:8080\r\n", url));
+
+            assert_that!(format!("{}", head.status()), is(equal_to(format!("{}", get.status()))));
+            assert_that!(header_lines(&head), is(equal_to(header_lines(&get))));
+            assert_that!(get.body_length() > 0, is(true));
+            assert_that!(head.body_length(), is(equal_to(0)));
+        }
+
+        let head = respond_raw(test_config(), "HEAD /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nRange: bytes=0-1\r\n");
+        assert_that!(format!("{}", head.status()), is(equal_to(String::from("200 OK"))));
+    }
+
+    #[test]
+    fn test_respond_to_options_asterisk_with_server_capabilities() {
+        let rendered = respond_raw(test_config(), "OPTIONS * HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        let rendered = String::from_utf8_lossy(&rendered);
+
+        assert_that!(rendered.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        assert_that!(rendered.contains(&format!("\r\nAllow: {}\r\n", http::ALLOWED_METHODS)), is(true));
+        assert_that!(rendered.contains("\r\nContent-Length: 0\r\n"), is(true));
+        assert_that!(rendered.ends_with("\r\n\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_rejects_asterisk_form_for_other_methods() {
+        let rendered = respond_raw(test_config(), "GET * HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 400 BAD REQUEST\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_rejects_authority_form_for_methods_other_than_connect() {
+        let rendered = respond_raw(test_config(), "GET localhost:8080 HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 400 BAD REQUEST\r\n"), is(true));
+
+        let rendered = respond_raw(test_config(), "CONNECT /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 400 BAD REQUEST\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_rejects_malformed_headers_only_in_strict_mode() {
+        for raw_request in &[
+            "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nX Forwarded: 1\r\n",
+            "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nX-Note: a\x1bb\r\n"] {
+            let rendered = respond_raw(test_config(), raw_request).render();
+            assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+
+            let rendered = respond_raw(test_config().with_strict(true), raw_request).render();
+            assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 400 BAD REQUEST\r\n"), is(true));
+        }
+    }
+
+    #[test]
+    fn test_respond_requires_host_for_http_1_1() {
+        let rendered = respond_raw(test_config(), "GET /hello.html HTTP/1.1\r\nAccept: */*\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 400 BAD REQUEST\r\n"), is(true));
+
+        let rendered = respond_raw(test_config(), "GET /hello.html HTTP/1.0\r\nAccept: */*\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_rejects_control_characters_in_request_target() {
+        let rendered = respond_raw(test_config(), "GET /hello\x01.html HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 400 BAD REQUEST\r\n"), is(true));
+
+        let rendered = respond_raw(test_config(), "GET /hello.html%00.txt HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 400 BAD REQUEST\r\n"), is(true));
+
+        let rendered = respond_raw(test_config(), "GET /hello.html%7f HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 400 BAD REQUEST\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_rejects_encoded_nul_and_path_separators_in_path() {
+        for url in ["/hello.html%00", "/docs%2F..%2Fhello.html", "/docs%2fhello.html", "/docs%5Chello.html"].iter() {
+            let rendered = respond_raw(test_config(), &format!("GET {} HTTP/1.1\r\nHost: localhost:8080\r\n", url)).render();
+            assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 400 BAD REQUEST\r\n"), is(true));
+        }
+
+        let rendered = respond_raw(test_config(), "GET /hello.html?next=%2Fdocs HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 400 BAD REQUEST\r\n"), is(false));
+    }
+
+    #[test]
+    fn test_respond_refuses_encoded_path_traversal() {
+        let status_of = |url: &str| {
+            let rendered = respond_raw(test_config(), &format!("GET {} HTTP/1.1\r\nHost: localhost:8080\r\n", url)).render();
+            String::from_utf8_lossy(&rendered).lines().next().unwrap_or("").to_string()
+        };
+
+        assert_that!(status_of("/%2e%2e/%2e%2e/etc/passwd"), is(equal_to(String::from("HTTP/1.1 404 NOT FOUND"))));
+        assert_that!(status_of("/.%2E/web_dir/hello.html"), is(equal_to(String::from("HTTP/1.1 404 NOT FOUND"))));
+        assert_that!(status_of("/..%2f..%2fetc/passwd"), is(equal_to(String::from("HTTP/1.1 400 BAD REQUEST"))));
+        assert_that!(status_of("/hello%2Ehtml"), is(equal_to(String::from("HTTP/1.1 200 OK"))));
+    }
+
+    #[test]
+    fn test_encodes_path_separator() {
+        assert_that!(encodes_path_separator("/index.html"), is(false));
+        assert_that!(encodes_path_separator("/100%20sure.html"), is(false));
+        assert_that!(encodes_path_separator("/100%2"), is(false));
+        assert_that!(encodes_path_separator("/a%+f"), is(false));
+        assert_that!(encodes_path_separator("/a%2Fb"), is(true));
+        assert_that!(encodes_path_separator("/a%5cb"), is(true));
+    }
+
+    #[test]
+    fn test_contains_control_characters() {
+        assert_that!(contains_control_characters("/index.html"), is(false));
+        assert_that!(contains_control_characters("/100%20sure.html"), is(false));
+        assert_that!(contains_control_characters("/100%"), is(false));
+        assert_that!(contains_control_characters("/%zz"), is(false));
+        assert_that!(contains_control_characters("/%\u{e4}\u{e4}"), is(false));
+        assert_that!(contains_control_characters("/a\x7fb"), is(true));
+        assert_that!(contains_control_characters("/a%0Ab"), is(true));
+        assert_that!(contains_control_characters("/a%1f"), is(true));
+    }
+
+    #[test]
+    fn test_respond_with_range_not_satisfiable() {
+        let request_fixture = "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nRange: bytes=1000-\r\n";
+
+        let rendered = respond_raw(test_config(), request_fixture).render();
+        let rendered = String::from_utf8_lossy(&rendered);
+
+        assert_that!(rendered.starts_with("HTTP/1.1 416 RANGE NOT SATISFIABLE\r\n"), is(true));
+        assert_that!(rendered.contains("\r\nContent-Range: bytes */516\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_format_http_date() {
+        assert_that!(format_http_date(1518607664).as_str(), is(equal_to("Wed, 14 Feb 2018 11:27:44 GMT")));
+    }
+
+    #[test]
+    fn test_respond_rejects_trace_by_default() {
+        let request_fixture = "TRACE / HTTP/1.1\r\nHost: localhost:8080\r\n";
+
+        let rendered = respond_raw(test_config(), request_fixture).render();
+
+        assert_that!(
+            String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 501 NOT IMPLEMENTED\r\n"),
+            is(true));
+    }
+
+    #[test]
+    fn test_respond_echoes_trace_if_allowed() {
+        let request_fixture = "TRACE / HTTP/1.1\r\nHost: localhost:8080\r\n";
+
+        let rendered = respond_raw(test_config().with_allow_trace(true), request_fixture).render();
+        let rendered = String::from_utf8_lossy(&rendered);
+
+        assert_that!(rendered.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        assert_that!(rendered.contains("\r\nContent-Type: message/http\r\n"), is(true));
+        assert_that!(rendered.ends_with("\r\n\r\nTRACE / HTTP/1.1\r\nHost: localhost:8080\r\n\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_refuses_protocol_upgrade() {
+        let request_fixture = "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nConnection: keep-alive, Upgrade\r\nUpgrade: websocket\r\n";
+
+        let rendered = respond_raw(test_config(), request_fixture).render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 501 NOT IMPLEMENTED\r\n"), is(true));
+
+        // Without the connection option the header is just informational.
+        let request_fixture = "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nUpgrade: websocket\r\n";
+
+        let rendered = respond_raw(test_config(), request_fixture).render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_forwards_requests_to_upstream() {
+        let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream_url = format!("http://{}/v1", upstream.local_addr().unwrap());
+        let received = thread::spawn(move || {
+            let (mut connection, _) = upstream.accept().unwrap();
+            let mut head = Vec::new();
+            let mut byte = [0; 1];
+
+            while !head.ends_with(b"\r\n\r\n") {
+                connection.read_exact(&mut byte).unwrap();
+                head.push(byte[0]);
+            }
+
+            connection.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\nFrom upstream").unwrap();
+            String::from_utf8(head).unwrap()
+        });
+        let config = test_config()
+            .with_proxy(Proxy::new(String::from("/api"), upstream_url).unwrap());
+
+        let rendered = respond_raw(config, "GET /api/items?page=2 HTTP/1.1\r\nHost: localhost:8080\r\nAccept: */*\r\n").render();
+        let rendered = String::from_utf8_lossy(&rendered);
+        let received = received.join().unwrap();
+
+        assert_that!(rendered.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        assert_that!(rendered.contains("\r\nContent-Length: 13\r\n"), is(true));
+        assert_that!(rendered.ends_with("\r\n\r\nFrom upstream"), is(true));
+        assert_that!(received.starts_with("GET /v1/items?page=2 HTTP/1.0\r\n"), is(true));
+        assert_that!(received.contains("\r\nAccept: */*\r\n"), is(true));
+        assert_that!(received.contains("\r\nX-Forwarded-For: 127.0.0.1\r\nX-Forwarded-Proto: http\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_answers_bad_gateway_if_upstream_is_down() {
+        let unused = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream_url = format!("http://{}", unused.local_addr().unwrap());
+        drop(unused);
+        let config = test_config()
+            .with_proxy(Proxy::new(String::from("/api"), upstream_url).unwrap());
+
+        let rendered = respond_raw(config, "GET /api/items HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 502 BAD GATEWAY\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_serves_files_from_mounts() {
+        let config = test_config()
+            .with_mount(Mount::new(String::from("/static"), String::from("test/web_dir/css")).unwrap())
+            .with_mount(Mount::new(String::from("/downloads"), String::from("test/fixtures")).unwrap());
+
+        let rendered = respond_raw(config.clone(), "GET /static/main.css HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        let rendered = String::from_utf8_lossy(&rendered);
+        assert_that!(rendered.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        assert_that!(rendered.contains("\r\nContent-Type: text/css; charset=utf-8\r\n"), is(true));
+
+        let rendered = respond_raw(config.clone(), "GET /downloads/hello.txt HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        let rendered = String::from_utf8_lossy(&rendered);
+        assert_that!(rendered.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        assert_that!(rendered.ends_with("\r\n\r\nHello, World!"), is(true));
+
+        let rendered = respond_raw(config, "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_respond_does_not_serve_files_outside_of_mount() {
+        let config = test_config()
+            .with_mount(Mount::new(String::from("/static"), String::from("test/web_dir/css")).unwrap());
+
+        let rendered = respond_raw(config.clone(), "GET /static/../hello.html HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 404 NOT FOUND\r\n"), is(true));
+
+        let rendered = respond_raw(config, "GET /../fixtures/hello.txt HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 404 NOT FOUND\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_too_many_requests_response_has_retry_after() {
+        let rendered = too_many_requests_response(Duration::from_secs(3)).render();
+        let rendered = String::from_utf8_lossy(&rendered);
+
+        assert_that!(rendered.starts_with("HTTP/1.1 429 TOO MANY REQUESTS\r\n"), is(true));
+        assert_that!(rendered.contains("\r\nRetry-After: 3\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_format_access_log_line() {
+        let peer: SocketAddr = "10.0.0.1:4711".parse().unwrap();
+        let response = Response::new(
+            String::from("1.1"),
+            Status::NotFound,
+            "Not found!".as_bytes().to_vec());
+
+        let line = format_access_log_line(&ConnectionState::new(peer), "GET /foo HTTP/1.1\r\nHost: localhost", &response);
+
+        assert_that!(line.starts_with("10.0.0.1 - - ["), is(true));
+        assert_that!(line.ends_with("] \"GET /foo HTTP/1.1\" 404 10"), is(true));
+    }
+
+    /// Reads from the given input and keeps what is written.
+    struct MemoryStream {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for MemoryStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MemoryStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Stream for MemoryStream {
+        fn set_read_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_accept_continues_after_failed_connection() {
+        let server = Server::new(test_config());
+        let stream = MemoryStream {
+            input: io::Cursor::new(b"HEAD /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nConnection: close\r\n\r\n".to_vec()),
+            output: Vec::new(),
+        };
+        let incoming = vec!(
+            Err(io::Error::from_raw_os_error(libc::EMFILE)),
+            Err(io::Error::from(io::ErrorKind::ConnectionAborted)),
+            Ok((stream, test_peer())));
+
+        assert_that!(server.accept(incoming.into_iter(), None).is_ok(), is(true));
+        assert_that!(server.metrics.render().contains("\nwebserver_requests_total 1\n"), is(true));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_runs_out_of_resources() {
+        assert_that!(runs_out_of_resources(&io::Error::from_raw_os_error(libc::EMFILE)), is(true));
+        assert_that!(runs_out_of_resources(&io::Error::from_raw_os_error(libc::ENFILE)), is(true));
+        assert_that!(runs_out_of_resources(&io::Error::from_raw_os_error(libc::ECONNABORTED)), is(false));
+        assert_that!(runs_out_of_resources(&io::Error::from(io::ErrorKind::ConnectionAborted)), is(false));
+    }
+
+    #[test]
+    fn test_connection_state_counts_pipelined_requests() {
+        let input = b"HEAD /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n\r\nHEAD /index.html HTTP/1.1\r\nHost: localhost:8080\r\n\r\n".to_vec();
+        let input_length = input.len() as u64;
+        let stream = MemoryStream { input: io::Cursor::new(input), output: Vec::new() };
+
+        let state = Server::handle_connection_new(
+            stream, test_peer(), ConfigHandle::new(test_config()), test_services(Metrics::new()));
+
+        assert_that!(state.requests(), is(equal_to(2)));
+        assert_that!(state.bytes_read(), is(equal_to(input_length)));
+        assert_that!(state.bytes_written() > 0, is(true));
+        assert_that!(*state.peer(), is(equal_to(test_peer())));
+    }
+
+    /// Returns the given chunks one per read call.
+    struct ChunkedReader {
+        chunks: Vec<Vec<u8>>,
+    }
+
+    impl Read for ChunkedReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.chunks.is_empty() {
+                return Ok(0);
+            }
+
+            let chunk = self.chunks.remove(0);
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    #[test]
+    fn test_read_request_from_several_small_chunks() {
+        let request_fixture = "GET /foo HTTP/1.1\r\nHost: localhost:8080\r\nUser-Agent: curl/7.54.0\r\nAccept: */*\r\n\r\n";
+        let mut reader = ChunkedReader {
+            chunks: request_fixture.as_bytes().chunks(3).map(|chunk| chunk.to_vec()).collect(),
+        };
+        // Must not be read because the request ends before.
+        reader.chunks.push(b"garbage".to_vec());
+
+        let request = read_request(&mut reader, &mut Vec::new(), 4096).unwrap().unwrap();
+
+        assert_that!(bytes_to_string(&request), is(equal_to(String::from(request_fixture))));
+        let request = http::parse_request(bytes_to_string(&request).trim()).unwrap();
+        assert_that!(request.url().as_str(), is(equal_to("/foo")));
+    }
+
+    #[test]
+    fn test_read_request_until_end_of_stream() {
+        let mut reader = ChunkedReader {
+            chunks: vec!(b"GET /foo ".to_vec(), b"HTTP/1.1\r\n".to_vec()),
+        };
+
+        assert_that!(
+            read_request(&mut reader, &mut Vec::new(), 4096),
+            is(equal_to(Ok(Some(b"GET /foo HTTP/1.1\r\n".to_vec())))));
+    }
+
+    #[test]
+    fn test_read_request_respects_maximum_size() {
+        let mut reader = ChunkedReader {
+            chunks: vec!(b"GET /foo HTTP/1.1\r\n".to_vec(), b"Host: localhost:8080\r\n".to_vec()),
+        };
+
+        assert_that!(
+            read_request(&mut reader, &mut Vec::new(), 30),
+            is(equal_to(Err(ReadError::HeaderFieldsTooLarge))));
+    }
+
+    #[test]
+    fn test_read_request_tells_apart_too_long_request_line() {
+        let mut reader = ChunkedReader {
+            chunks: vec!(b"\r\nGET /".to_vec(), "a".repeat(40).into_bytes(), b" HTTP/1.1\r\n\r\n".to_vec()),
+        };
+
+        assert_that!(
+            read_request(&mut reader, &mut Vec::new(), 30),
+            is(equal_to(Err(ReadError::RequestLineTooLong))));
+    }
+
+    #[test]
+    fn test_read_request_returns_nothing_if_connection_closed() {
+        let mut reader = ChunkedReader { chunks: Vec::new() };
+
+        assert_that!(read_request(&mut reader, &mut Vec::new(), 4096), is(equal_to(Ok(None))));
+    }
+
+    #[test]
+    fn test_read_request_returns_nothing_if_connection_closed_after_line_breaks() {
+        let mut reader = ChunkedReader { chunks: vec!(b"\r\n".to_vec()) };
+
+        assert_that!(read_request(&mut reader, &mut Vec::new(), 4096), is(equal_to(Ok(None))));
+    }
+
+    fn send_and_close(services: Services, request: &str) -> String {
+        let mut client = connect_with_services(ConfigHandle::new(test_config()), services);
+
+        client.write_all(request.as_bytes()).unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        let mut output = String::new();
+        client.read_to_string(&mut output).unwrap();
+        output
+    }
+
+    fn assert_body_is_not_taken_as_request(request: &str, status: &str) {
+        let output = send_and_close(test_services(Metrics::new()), request);
+
+        assert_that!(output.starts_with(status), is(true));
+        assert_that!(output.contains("\r\nConnection: close\r\n"), is(true));
+        assert_that!(output.matches("HTTP/1.1 ").count(), is(equal_to(1)));
+    }
+
+    #[test]
+    fn test_unread_body_is_not_taken_as_pipelined_request() {
+        let smuggled = "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n\r\n";
+
+        assert_body_is_not_taken_as_request(
+            &format!("POST /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nTransfer-Encoding: gzip\r\n\r\n{}", smuggled),
+            "HTTP/1.1 501 NOT IMPLEMENTED\r\n");
+        assert_body_is_not_taken_as_request(
+            &format!("POST /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n{}", smuggled),
+            "HTTP/1.1 400 BAD REQUEST\r\n");
+        assert_body_is_not_taken_as_request(
+            &format!(
+                "POST /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n0\r\n\r\n{}",
+                smuggled),
+            "HTTP/1.1 400 BAD REQUEST\r\n");
+    }
+
+    #[test]
+    fn test_body_of_rate_limited_request_is_not_taken_as_pipelined_request() {
+        let config = test_config().with_rate_limit(1, 1).unwrap();
+        let services = Services { rate_limits: RateLimits::from_config(&config), ..test_services(Metrics::new()) };
+        let smuggled = "GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n\r\n";
+
+        let output = send_and_close(services, &format!(
+            "{}POST /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+            smuggled, smuggled.len(), smuggled));
+
+        assert_that!(output.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        assert_that!(output.matches("HTTP/1.1 ").count(), is(equal_to(2)));
+        assert_that!(output.contains("HTTP/1.1 429 TOO MANY REQUESTS\r\n"), is(true));
+        assert_that!(output.contains("\r\nConnection: close\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_route_limit_applies_to_other_spellings_of_its_path() {
+        let config = test_config().with_route_limit(RouteLimit::new(String::from("/hello.html"), 1, Some(1)).unwrap());
+        let request = |target: &str| format!("HEAD {} HTTP/1.1\r\nHost: localhost:8080\r\n\r\n", target);
+
+        for target in ["/%68ello.html", "//hello.html", "/css/../hello.html?x=1", "http://localhost:8080/hello.html"].iter() {
+            let services = Services { rate_limits: RateLimits::from_config(&config), ..test_services(Metrics::new()) };
+            let output = send_and_close(services, &format!("{}{}", request("/hello.html"), request(target)));
+
+            assert_that!(output.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+            assert_that!(output.contains("HTTP/1.1 429 TOO MANY REQUESTS\r\n"), is(true));
+        }
+    }
+
+    #[test]
+    fn test_connection_stays_open_after_content_length_bodies() {
+        let request = "POST /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nContent-Length: 5\r\n\r\nhello";
+
+        let output = send_and_close(test_services(Metrics::new()), &format!("{}{}", request, request));
+
+        assert_that!(output.matches("HTTP/1.1 ").count(), is(equal_to(2)));
+        assert_that!(output.matches("\r\nConnection: keep-alive\r\n").count(), is(equal_to(2)));
+    }
+
+    #[test]
+    fn test_bytes_after_body_of_closing_request_are_ignored() {
+        let mut client = connect(test_config());
+
+        client.write_all(b"POST /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nConnection: close\r\nContent-Length: 5\r\n\r\nhelloGET /hello.html HTTP/1.1\r\n\r\n").unwrap();
+        let mut output = String::new();
+        client.read_to_string(&mut output).unwrap();
+
+        assert_that!(output.starts_with("HTTP/1.1 405 METHOD NOT ALLOWED\r\n"), is(true));
+        assert_that!(output.matches("HTTP/1.1 ").count(), is(equal_to(1)));
+    }
+
+    #[test]
+    fn test_body_shorter_than_content_length_is_rejected() {
+        let mut client = connect(test_config());
+
+        client.write_all(b"POST /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nContent-Length: 50\r\n\r\nhello").unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        let mut output = String::new();
+        client.read_to_string(&mut output).unwrap();
+
+        assert_that!(output.starts_with("HTTP/1.1 400 BAD REQUEST\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_oversized_header_fields_are_rejected_with_431() {
+        let mut client = connect(test_config().with_max_request_bytes(256).unwrap());
+        let request = format!("GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nCookie: {}\r\n\r\n", "a".repeat(300));
+
+        client.write_all(request.as_bytes()).unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        let mut output = String::new();
+        client.read_to_string(&mut output).unwrap();
+
+        assert_that!(output.starts_with("HTTP/1.1 431 REQUEST HEADER FIELDS TOO LARGE\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_overlong_request_line_is_rejected_with_414() {
+        let mut client = connect(test_config().with_max_request_bytes(256).unwrap());
+        let request = format!("GET /{} HTTP/1.1\r\nHost: localhost:8080\r\n\r\n", "a".repeat(300));
+
+        client.write_all(request.as_bytes()).unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        let mut output = String::new();
+        client.read_to_string(&mut output).unwrap();
+
+        assert_that!(output.starts_with("HTTP/1.1 414 URI TOO LONG\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_keep_alive_connection_closes_quietly_on_end_of_stream() {
+        let mut client = connect(test_config());
+
+        client.write_all(b"HEAD /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n\r\n\r\n").unwrap();
+        let response = read_head(&mut client);
+        assert_that!(response.contains("\r\nConnection: keep-alive\r\n"), is(true));
+
+        client.shutdown(Shutdown::Write).unwrap();
+
+        let mut rest = Vec::new();
+        client.read_to_end(&mut rest).unwrap();
+        assert_that!(String::from_utf8_lossy(&rest).into_owned(), is(equal_to(String::new())));
+    }
+
+    #[test]
+    fn test_read_request_keeps_bytes_of_next_request() {
+        let mut reader = ChunkedReader {
+            chunks: vec!(b"GET /foo HTTP/1.1\r\n\r\nGET /bar HTTP/1.1\r\n".to_vec(), b"\r\n".to_vec()),
+        };
+        let mut pending = Vec::new();
+
+        assert_that!(
+            read_request(&mut reader, &mut pending, 4096),
+            is(equal_to(Ok(Some(b"GET /foo HTTP/1.1\r\n\r\n".to_vec())))));
+        assert_that!(pending.clone(), is(equal_to(b"GET /bar HTTP/1.1\r\n".to_vec())));
+        assert_that!(
+            read_request(&mut reader, &mut pending, 4096),
+            is(equal_to(Ok(Some(b"GET /bar HTTP/1.1\r\n\r\n".to_vec())))));
+        assert_that!(pending.is_empty(), is(true));
+    }
+
+    #[test]
+    fn test_raw_headers_are_retained_exactly_if_enabled() {
+        let headers: &[u8] = b"host:localhost:8080\r\nX-Signature:  sha256=ab\t \r\nX-Name: Ren\xe9\r\n";
+        let head = [&b"\r\nPOST /hook HTTP/1.1\r\n"[..], headers, b"\r\n"].concat();
+        let request = bytes_to_string(&head);
+        let parse = |config: &Config| retain_raw_headers(config, parse_request(config, request.trim()), &head).unwrap();
+
+        assert_that!(parse(&test_config().with_retain_raw_headers(true)).raw_headers(), is(equal_to(headers)));
+        assert_that!(parse(&test_config()).raw_headers().is_empty(), is(true));
+        assert_that!(header_section(b"GET / HTTP/1.0\r\n\r\n").is_empty(), is(true));
+    }
+
+    /// Serves a single connection in the background and returns the client side of it.
+    fn connect(config: Config) -> TcpStream {
+        connect_with_handle(ConfigHandle::new(config), Metrics::new())
+    }
+
+    /// Serves a single connection in the background with a reloadable config and the given metrics.
+    fn connect_with_handle(handle: ConfigHandle, metrics: Metrics) -> TcpStream {
+        connect_with_services(handle, test_services(metrics))
+    }
+
+    fn connect_with_services(handle: ConfigHandle, services: Services) -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server_side, peer) = listener.accept().unwrap();
+        thread::spawn(move || Server::handle_connection_new(server_side, peer, handle, services));
+        client
+    }
+
+    fn test_services(metrics: Metrics) -> Services {
+        Services {
+            assets: AssetSource::FileSystem,
+            rate_limits: RateLimits::default(),
+            access_logger: None,
+            metrics,
+            websocket_handler: None,
+            not_found_handler: None,
+            middlewares: Arc::from(Vec::new()),
+            handler_threads: HandlerThreads::new(4),
+        }
+    }
+
+    fn greet_websocket(stream: &mut dyn Stream, request: &Request) {
+        stream.write_all(format!("Hello {}", request.url()).as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_websocket_handshake_hands_connection_over_to_handler() {
+        let services = Services { websocket_handler: Some(greet_websocket), ..test_services(Metrics::new()) };
+        let mut client = connect_with_services(ConfigHandle::new(test_config()), services);
+        client.write_all(
+            b"GET /chat HTTP/1.1\r\nHost: localhost:8080\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+              Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n").unwrap();
+
+        let mut output = String::new();
+        client.read_to_string(&mut output).unwrap();
+
+        assert_that!(output.starts_with("HTTP/1.1 101 SWITCHING PROTOCOLS\r\n"), is(true));
+        assert_that!(output.contains("\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"), is(true));
+        assert_that!(output.contains("\r\nKeep-Alive:"), is(false));
+        assert_that!(output.ends_with("\r\n\r\nHello /chat"), is(true));
+    }
+
+    fn echo_websocket(stream: &mut dyn Stream, _request: &Request) {
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).unwrap();
+        stream.write_all(&received).unwrap();
+    }
+
+    #[test]
+    fn test_websocket_handler_receives_data_sent_along_with_handshake() {
+        let services = Services { websocket_handler: Some(echo_websocket), ..test_services(Metrics::new()) };
+
+        let output = send_and_close(services,
+            "GET /chat HTTP/1.1\r\nHost: localhost:8080\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\nfirst frame");
+
+        assert_that!(output.starts_with("HTTP/1.1 101 SWITCHING PROTOCOLS\r\n"), is(true));
+        assert_that!(output.ends_with("\r\n\r\nfirst frame"), is(true));
+    }
+
+    /// Reads the status line and headers of a response, e.g. one to a `HEAD` request.
+    fn read_head(client: &mut TcpStream) -> String {
+        let mut head = Vec::new();
+        let mut byte = [0; 1];
+
+        while !head.ends_with(b"\r\n\r\n") {
+            client.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+
+        String::from_utf8(head).unwrap()
+    }
+
+    /// Reads a response with a `Content-Length` header from the client side of a connection.
+    fn read_response(client: &mut TcpStream) -> String {
+        let head = read_head(client);
+        let content_length: usize = head.lines()
+            .find(|line| line.starts_with("Content-Length: "))
+            .map(|line| line["Content-Length: ".len()..].parse().unwrap())
+            .unwrap_or(0);
+        let mut body = vec![0; content_length];
+        client.read_exact(&mut body).unwrap();
+
+        head + &String::from_utf8(body).unwrap()
+    }
+
+    #[test]
+    fn test_error_responses_on_persistent_connection_declare_their_length() {
+        let mut client = connect(test_config());
+
+        client.write_all(b"GET /nope HTTP/1.1\r\nHost: localhost:8080\r\n\r\nGET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n\r\n").unwrap();
+
+        let response = read_response(&mut client);
+        assert_that!(response.starts_with("HTTP/1.1 404 NOT FOUND\r\n"), is(true));
+        assert_that!(response.contains("\r\nConnection: keep-alive\r\n"), is(true));
+        assert_that!(response.ends_with("\r\n\r\nNot found!"), is(true));
+        assert_that!(read_response(&mut client).starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_invalid_utf8_in_request_head_is_handled_as_configured() {
+        let request_fixture = b"TRACE / HTTP/1.1\r\nHost: localhost:8080\r\nX-Name: Ren\xe9 \xc3\xa4\r\nConnection: close\r\n\r\n";
+        let echo = |config: Config| {
+            let mut client = connect(config.with_allow_trace(true));
+            client.write_all(request_fixture).unwrap();
+            read_response(&mut client)
+        };
+
+        assert_that!(echo(test_config()).contains("\r\nX-Name: Ren\u{e9} \u{c3}\u{a4}\r\n"), is(true));
+        assert_that!(
+            echo(test_config().with_invalid_utf8(InvalidUtf8::Replace)).contains("\r\nX-Name: Ren\u{fffd} \u{e4}\r\n"),
+            is(true));
+        assert_that!(
+            echo(test_config().with_invalid_utf8(InvalidUtf8::Reject)).starts_with("HTTP/1.1 400 BAD REQUEST\r\n"),
+            is(true));
+
+        let mut client = connect(test_config().with_allow_trace(true).with_invalid_utf8(InvalidUtf8::Reject));
+        client.write_all("TRACE / HTTP/1.1\r\nHost: localhost:8080\r\nX-Name: Ren\u{e9}\r\nConnection: close\r\n\r\n".as_bytes()).unwrap();
+        assert_that!(read_response(&mut client).contains("\r\nX-Name: Ren\u{e9}\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_keep_alive_header_reflects_timeout_and_remaining_requests() {
+        let config = test_config().with_keep_alive(7, 3).unwrap();
+        let mut client = connect(config);
+        let request_fixture = b"HEAD /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n\r\n";
+
+        client.write_all(request_fixture).unwrap();
+        let response = read_head(&mut client);
+        assert_that!(response.contains("\r\nConnection: keep-alive\r\n"), is(true));
+        assert_that!(response.contains("\r\nKeep-Alive: timeout=7, max=2\r\n"), is(true));
+
+        client.write_all(request_fixture).unwrap();
+        let response = read_head(&mut client);
+        assert_that!(response.contains("\r\nKeep-Alive: timeout=7, max=1\r\n"), is(true));
+
+        client.write_all(request_fixture).unwrap();
+        let response = read_head(&mut client);
+        assert_that!(response.contains("\r\nConnection: close\r\n"), is(true));
+        assert_that!(response.contains("Keep-Alive:"), is(false));
+        assert_that!(client.read(&mut [0; 1]).unwrap(), is(equal_to(0)));
+    }
+
+    #[test]
+    fn test_idle_keep_alive_connection_is_closed_quietly_after_timeout() {
+        let config = test_config().with_keep_alive(1, 100).unwrap().with_read_timeout(30).unwrap();
+        let mut client = connect(config);
+
+        client.write_all(b"HEAD /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n\r\n").unwrap();
+        let response = read_head(&mut client);
+        assert_that!(response.contains("\r\nConnection: keep-alive\r\n"), is(true));
+
+        let idle_since = Instant::now();
+        let mut rest = Vec::new();
+        client.read_to_end(&mut rest).unwrap();
+
+        assert_that!(String::from_utf8_lossy(&rest).into_owned(), is(equal_to(String::new())));
+        assert_that!(idle_since.elapsed() < Duration::from_secs(10), is(true));
+    }
+
+    #[test]
+    fn test_incomplete_request_is_rejected_after_read_timeout() {
+        let config = test_config().with_keep_alive(30, 100).unwrap().with_read_timeout(1).unwrap();
+        let mut client = connect(config);
+
+        client.write_all(b"GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n").unwrap();
+
+        let started = Instant::now();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+
+        assert_that!(response.starts_with("HTTP/1.1 408 REQUEST TIMEOUT\r\n"), is(true));
+        assert_that!(response.contains("\r\nConnection: close\r\n"), is(true));
+        assert_that!(started.elapsed() < Duration::from_secs(10), is(true));
+    }
+
+    #[test]
+    fn test_incomplete_body_is_rejected_after_read_timeout() {
+        let config = test_config().with_keep_alive(30, 100).unwrap().with_read_timeout(1).unwrap();
+        let mut client = connect(config);
+
+        client.write_all(b"POST /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nContent-Length: 10\r\n\r\nhello").unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+
+        assert_that!(response.starts_with("HTTP/1.1 408 REQUEST TIMEOUT\r\n"), is(true));
+        assert_that!(response.contains("\r\nConnection: close\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_no_keep_alive_header_if_connection_is_closed() {
+        let mut client = connect(test_config());
+
+        client.write_all(b"HEAD /hello.html HTTP/1.0\r\n\r\n").unwrap();
+        let response = read_head(&mut client);
+
+        assert_that!(response.contains("\r\nConnection: close\r\n"), is(true));
+        assert_that!(response.contains("Keep-Alive:"), is(false));
+
+        let mut client = connect(test_config());
+
+        client.write_all(b"HEAD /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nConnection: close\r\n\r\n").unwrap();
+        let response = read_head(&mut client);
+
+        assert_that!(response.contains("\r\nConnection: close\r\n"), is(true));
+        assert_that!(response.contains("Keep-Alive:"), is(false));
+    }
+
+    #[test]
+    fn test_respond_serves_embedded_asset_without_file_system() {
+        let mut embedded = HashMap::new();
+        embedded.insert(String::from("/index.html"), b"<h1>Embedded</h1>".to_vec());
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            8080,
+            4,
+            String::from("does/not/exist"),
+            String::from("debug"),
+            String::from("logs")).unwrap();
+        let server = Server::with_embedded_assets(config.clone(), embedded);
+        let request_fixture = "GET / HTTP/1.1\r\nHost: localhost:8080\r\n";
+
+        let services = Services { assets: server.assets.clone(), ..test_services(Metrics::new()) };
+        let response = respond(config, &services, http::parse_request(request_fixture), request_fixture, test_peer());
+        let rendered = String::from_utf8(response.render()).unwrap();
+
+        assert_that!(rendered.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        assert_that!(rendered.contains("\r\nContent-Type: text/html; charset=utf-8\r\n"), is(true));
+        assert_that!(
+            rendered.contains(&format!("\r\nETag: {}\r\n", assets::content_etag(b"<h1>Embedded</h1>"))),
+            is(true));
+        assert_that!(rendered.contains("Last-Modified:"), is(false));
+        assert_that!(rendered.ends_with("\r\n\r\n<h1>Embedded</h1>"), is(true));
+    }
+
+    #[test]
+    fn test_metrics_endpoint_counts_requests() {
+        let config = test_config().with_metrics_path(String::from("/metrics")).unwrap();
+        let mut client = connect_with_handle(ConfigHandle::new(config), Metrics::new());
+        let scrape = b"GET /metrics HTTP/1.1\r\nHost: localhost:8080\r\n\r\n";
+
+        client.write_all(scrape).unwrap();
+        let response = read_response(&mut client);
+        assert_that!(response.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        assert_that!(response.contains("\r\nContent-Type: text/plain; version=0.0.4\r\n"), is(true));
+        assert_that!(response.contains("\r\nCache-Control: no-store, no-cache, must-revalidate\r\n"), is(true));
+        assert_that!(response.contains("\r\nPragma: no-cache\r\n"), is(true));
+        assert_that!(response.contains("\nwebserver_requests_total 0\n"), is(true));
+        assert_that!(response.contains("\nwebserver_active_connections 1\n"), is(true));
+
+        client.write_all(b"GET /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n\r\n").unwrap();
+        read_response(&mut client);
+
+        client.write_all(scrape).unwrap();
+        let response = read_response(&mut client);
+        assert_that!(response.contains("\nwebserver_requests_total 2\n"), is(true));
+        assert_that!(response.contains("\nwebserver_responses_total{class=\"2xx\"} 2\n"), is(true));
+    }
+
+    #[test]
+    fn test_metrics_endpoint_is_disabled_by_default() {
+        let rendered = respond_raw(test_config(), "GET /metrics HTTP/1.1\r\nHost: localhost:8080\r\n").render();
+
+        assert_that!(String::from_utf8_lossy(&rendered).starts_with("HTTP/1.1 404 NOT FOUND\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_prepare_tcp_stream_sets_nodelay() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        let (stream, peer) = prepare_tcp_stream(listener.accept().unwrap().0, true);
+
+        assert_that!(stream.nodelay().unwrap(), is(true));
+        assert_that!(peer, is(equal_to(client.local_addr().unwrap())));
+        assert_that!(prepare_tcp_stream(stream, false).0.nodelay().unwrap(), is(false));
+    }
+
+    #[test]
+    fn test_set_listen_backlog_accepts_large_values() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        assert_that!(set_listen_backlog(&listener, 65535).is_ok(), is(true));
+        assert_that!(set_listen_backlog(&listener, u32::MAX).is_ok(), is(true));
+
+        let address = listener.local_addr().unwrap();
+        let _client = TcpStream::connect(address).unwrap();
+        assert_that!(listener.accept().is_ok(), is(true));
+    }
+
+    #[test]
+    fn test_serve_n_returns_after_handling_n_connections() {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let config = Config::new(
+            String::from("127.0.0.1"),
+            port,
+            2,
+            String::from("test/web_dir"),
+            String::from("debug"),
+            String::from("logs")).unwrap();
+        let server = thread::spawn(move || Server::new(config).serve_n(2).is_ok());
+
+        for _ in 0..2 {
+            let mut client = loop {
+                match TcpStream::connect(("127.0.0.1", port)) {
+                    Ok(client) => break client,
+                    Err(_) => thread::sleep(Duration::from_millis(10)),
+                }
+            };
+            client.write_all(b"HEAD /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nConnection: close\r\n\r\n").unwrap();
+            assert_that!(read_head(&mut client).starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        }
+
+        assert_that!(server.join().unwrap(), is(true));
+    }
+
+    #[test]
+    fn test_serve_with_config_built_in_code() {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let config = Config::default()
+            .with_port(port).unwrap()
+            .with_web_dir(String::from("test/web_dir")).unwrap()
+            .with_server_token(String::from("Embedded"));
+        let server = thread::spawn(move || Server::new(config).serve_n(1).is_ok());
+
+        let mut client = loop {
+            match TcpStream::connect(("127.0.0.1", port)) {
+                Ok(client) => break client,
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        client.write_all(b"HEAD /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nConnection: close\r\n\r\n").unwrap();
+        let head = read_head(&mut client);
+
+        assert_that!(head.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        assert_that!(head.contains("\r\nServer: Embedded\r\n"), is(true));
+        assert_that!(server.join().unwrap(), is(true));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_serve_n_on_unix_socket() {
+        use std::os::unix::net::UnixStream;
+
+        let path = env::temp_dir().join(format!("webserver-serve-{}.sock", process::id()));
+        let config = test_config().with_unix_socket(path.to_string_lossy().into_owned()).unwrap();
+        let server = thread::spawn(move || Server::new(config).serve_n(1).is_ok());
+
+        let mut client = loop {
+            match UnixStream::connect(&path) {
+                Ok(client) => break client,
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        client.write_all(b"GET /hello.html HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+
+        assert_that!(response.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        assert_that!(server.join().unwrap(), is(true));
+        assert_that!(path.exists(), is(false));
+    }
+
+    #[test]
+    fn test_server_header_uses_configured_token() {
+        let request_fixture = b"HEAD /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nConnection: close\r\n\r\n";
+
+        let mut client = connect(test_config());
+        client.write_all(request_fixture).unwrap();
+        assert_that!(
+            read_head(&mut client).contains("\r\nServer: Weltraumschaf's Webserver\r\n"),
+            is(true));
+
+        let mut client = connect(test_config().with_server_token(String::from("MyServer")));
+        client.write_all(request_fixture).unwrap();
+        assert_that!(read_head(&mut client).contains("\r\nServer: MyServer\r\n"), is(true));
+
+        let mut client = connect(test_config().with_server_token(String::from("")));
+        client.write_all(request_fixture).unwrap();
+        assert_that!(read_head(&mut client).contains("Server:"), is(false));
+    }
+
+    #[test]
+    fn test_server_timing_header_if_enabled() {
+        let request_fixture = b"HEAD /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nConnection: close\r\n\r\n";
+
+        let mut client = connect(test_config());
+        client.write_all(request_fixture).unwrap();
+        assert_that!(read_head(&mut client).contains("Server-Timing:"), is(false));
+
+        let mut client = connect(test_config().with_server_timing(true));
+        client.write_all(request_fixture).unwrap();
+        let response = read_head(&mut client);
+        let duration = response.lines()
+            .find(|line| line.starts_with("Server-Timing: app;dur="))
+            .map(|line| line["Server-Timing: app;dur=".len()..].parse::<f64>());
+
+        assert_that!(duration.is_some_and(|duration| duration.is_ok_and(|duration| duration >= 0.0)), is(true));
+    }
+
+    #[test]
+    fn test_reload_swaps_web_dir_on_open_connection() {
+        let handle = ConfigHandle::new(test_config());
+        let mut client = connect_with_handle(handle.clone(), Metrics::new());
+        let request_fixture = b"HEAD /hello.html HTTP/1.1\r\nHost: localhost:8080\r\n\r\n";
+
+        client.write_all(request_fixture).unwrap();
+        assert_that!(read_head(&mut client).starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+
+        let mut reloaded = test_config();
+        reloaded.web_dir = String::from("test/fixtures");
+        handle.reload(reloaded);
+
+        client.write_all(request_fixture).unwrap();
+        assert_that!(read_head(&mut client).starts_with("HTTP/1.1 404 NOT FOUND\r\n"), is(true));
+    }
+
+    #[test]
+    fn test_reload_applies_to_next_request_on_open_connection() {
+        let handle = ConfigHandle::new(test_config());
+        let mut client = connect_with_handle(handle.clone(), Metrics::new());
+        let request_fixture = b"HEAD /hello.html HTTP/1.1\r\nHost: localhost:8080\r\nBad Header: x\r\n\r\n";
+
+        client.write_all(request_fixture).unwrap();
+        let response = read_head(&mut client);
+        assert_that!(response.starts_with("HTTP/1.1 200 OK\r\n"), is(true));
+        assert_that!(response.contains("\r\nServer: Weltraumschaf's Webserver\r\n"), is(true));
+
+        handle.reload(test_config().with_strict(true).with_server_token(String::from("MyServer")));
+
+        client.write_all(request_fixture).unwrap();
+        let response = read_head(&mut client);
+        assert_that!(response.starts_with("HTTP/1.1 400 BAD REQUEST\r\n"), is(true));
+        assert_that!(response.contains("\r\nServer: MyServer\r\n"), is(true));
     }
 }
\ No newline at end of file
@@ -0,0 +1,191 @@
+///! This module provides the streams connections are served over.
+///!
+///! The server listens either on a TCP port or, on Unix, on a Unix domain socket. Connections are
+///! handled the same way for both, only the listener differs.
+
+use std::io;
+use std::io::prelude::*;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpStream};
+use std::time::Duration;
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+
+/// A connected stream a client sends requests over.
+pub trait Stream: Read + Write + Send {
+    /// Set the time to wait for data before a read fails, `None` to wait forever.
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Stream for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl Stream for UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// A stream which first replays bytes already read from the wrapped stream.
+///
+/// A connection handed over to another protocol may have sent more than the request which
+/// switched it. These bytes were read ahead while looking for the end of the request and would
+/// be lost otherwise.
+pub struct ReplayStream<'a, S: Stream + ?Sized + 'a> {
+    replayed: io::Cursor<Vec<u8>>,
+    stream: &'a mut S,
+}
+
+impl<'a, S: Stream + ?Sized> ReplayStream<'a, S> {
+    /// Wraps the stream, which is read after the given bytes.
+    pub fn new(replayed: Vec<u8>, stream: &'a mut S) -> ReplayStream<'a, S> {
+        ReplayStream { replayed: io::Cursor::new(replayed), stream }
+    }
+}
+
+impl<'a, S: Stream + ?Sized> Read for ReplayStream<'a, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.replayed.read(buf)? {
+            0 => self.stream.read(buf),
+            read => Ok(read),
+        }
+    }
+}
+
+impl<'a, S: Stream + ?Sized> Write for ReplayStream<'a, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl<'a, S: Stream + ?Sized> Stream for ReplayStream<'a, S> {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(timeout)
+    }
+}
+
+/// Address reported for clients connected over a Unix domain socket.
+///
+/// They have no IP address. They are local processes, usually a reverse proxy, so they are
+/// reported as localhost. All of them share this address, so the per-IP connection limit and
+/// the rate limits apply to all of them together.
+pub fn unix_peer() -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
+}
+
+/// Address reported for clients whose address can't be determined: `0.0.0.0:0`.
+pub fn unknown_peer() -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))
+}
+
+/// A Unix domain socket listener whose socket file is removed when it is dropped.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct UnixSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixSocket {
+    /// Listens on a Unix domain socket at the given path.
+    ///
+    /// A socket file left over from a previous run is removed first. Binding fails with
+    /// `AddrInUse` if another server still accepts connections on the socket. Any other file at
+    /// the path is kept and binding fails.
+    pub fn bind(path: &str) -> io::Result<UnixSocket> {
+        let path = PathBuf::from(path);
+
+        if fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, "Another server is listening on the socket!"));
+            }
+
+            debug!("Removing stale socket file {:?}.", path);
+            fs::remove_file(&path)?;
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        Ok(UnixSocket { listener, path })
+    }
+
+    /// Get the listener accepting connections.
+    pub fn listener(&self) -> &UnixListener {
+        &self.listener
+    }
+}
+
+#[cfg(unix)]
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            warn!("Can't remove socket file {:?}: {}", self.path, err);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+    use std::env;
+    use std::fs::File;
+    use std::process;
+
+    fn socket_path(name: &str) -> String {
+        env::temp_dir()
+            .join(format!("webserver-{}-{}.sock", name, process::id()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn bind_replaces_stale_socket_and_removes_it_when_dropped() {
+        let path = socket_path("stale");
+        let stale = UnixListener::bind(&path).unwrap();
+        drop(stale);
+
+        let sut = UnixSocket::bind(&path).unwrap();
+        assert_that!(PathBuf::from(&path).exists(), is(true));
+
+        drop(sut);
+        assert_that!(PathBuf::from(&path).exists(), is(false));
+    }
+
+    #[test]
+    fn bind_keeps_socket_of_running_server() {
+        let path = socket_path("running");
+        let running = UnixListener::bind(&path).unwrap();
+
+        let err = UnixSocket::bind(&path).unwrap_err();
+        assert_that!(err.kind(), is(equal_to(io::ErrorKind::AddrInUse)));
+        assert_that!(PathBuf::from(&path).exists(), is(true));
+
+        drop(running);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn bind_keeps_other_files() {
+        let path = socket_path("regular");
+        File::create(&path).unwrap();
+
+        assert_that!(UnixSocket::bind(&path).is_err(), is(true));
+        assert_that!(PathBuf::from(&path).exists(), is(true));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -37,22 +37,35 @@ impl ThreadPool {
     ///
     /// # Panics
     ///
-    /// The `new` function will panic if the size is zero.
+    /// The `new` function will panic if the size is zero or the threads can't be spawned.
     pub fn new(size: usize) -> ThreadPool {
+        ThreadPool::with_stack_size(size, None).expect("Can't spawn worker threads!")
+    }
+
+    /// Create a new ThreadPool whose threads have the given stack size in bytes.
+    ///
+    /// The threads get the platform default stack size if it is `None`. Fails if the threads
+    /// can't be spawned, e.g. because the stack size is too large. Threads already spawned
+    /// are shut down again.
+    ///
+    /// # Panics
+    ///
+    /// The `with_stack_size` function will panic if the size is zero.
+    pub fn with_stack_size(size: usize, stack_size: Option<usize>) -> Result<ThreadPool, &'static str> {
         assert!(size > 0);
 
         let (sender, receiver) = mpsc::channel();
         let receiver = Arc::new(Mutex::new(receiver));
-        let mut workers = Vec::with_capacity(size);
+        let mut pool = ThreadPool {
+            workers: Vec::with_capacity(size),
+            sender,
+        };
 
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            pool.workers.push(Worker::new(id, Arc::clone(&receiver), stack_size)?);
         }
 
-        ThreadPool {
-            workers,
-            sender,
-        }
+        Ok(pool)
     }
 
     /// Executes the given closure in the background by one of the thread pools workers.
@@ -92,8 +105,14 @@ struct Worker {
 }
 
 impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Worker {
-        let thread = thread::spawn(move || {
+    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Message>>>, stack_size: Option<usize>) -> Result<Worker, &'static str> {
+        let mut builder = thread::Builder::new();
+
+        if let Some(stack_size) = stack_size {
+            builder = builder.stack_size(stack_size);
+        }
+
+        let thread = builder.spawn(move || {
             loop {
                 let message = receiver.lock().unwrap().recv().unwrap();
 
@@ -108,11 +127,42 @@ impl Worker {
                     }
                 }
             }
-        });
+        }).map_err(|err| {
+            error!("Can't spawn worker thread {}: {}", id, err);
+            "Can't spawn worker thread!"
+        })?;
 
-        Worker {
+        Ok(Worker {
             id,
             thread: Some(thread),
-        }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+    use std::hint;
+
+    #[test]
+    fn jobs_run_with_configured_stack_size() {
+        let sut = ThreadPool::with_stack_size(1, Some(32 * 1024 * 1024)).unwrap();
+        let (sender, receiver) = mpsc::channel();
+
+        sut.execute(move || {
+            // Larger than the default stack of 2 MiB.
+            let buffer = hint::black_box([1u8; 8 * 1024 * 1024]);
+            sender.send(buffer.iter().map(|&byte| usize::from(byte)).sum::<usize>()).unwrap();
+        });
+
+        assert_that!(receiver.recv().unwrap(), is(equal_to(8 * 1024 * 1024)));
+    }
+
+    #[test]
+    fn with_stack_size_fails_if_threads_can_not_be_spawned() {
+        let sut = ThreadPool::with_stack_size(2, Some(1 << 60));
+
+        assert_that!(sut.is_err(), is(true));
     }
 }
@@ -0,0 +1,208 @@
+///! This module provides the opening handshake of the [WebSocket protocol](https://tools.ietf.org/html/rfc6455).
+///!
+///! Only the handshake is implemented. Afterwards the connection is handed over to a
+///! [`Handler`](type.Handler.html) of the application, which reads and writes the frames itself.
+
+use http::{self, Request, Response, ResponseHeader, Status};
+use stream::Stream;
+
+/// Appended to the key of the client to compute the accept value, see RFC 6455 section 1.3.
+pub static GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// The only version of the protocol supported.
+pub static VERSION: &'static str = "13";
+/// Characters of the base64 encoding, indexed by their value.
+static BASE64_ALPHABET: &'static [u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Takes over a connection after a successful handshake.
+///
+/// It gets the stream of the connection and the request which opened it. The connection is
+/// closed when the handler returns.
+pub type Handler = fn(&mut dyn Stream, &Request);
+
+/// Whether the client asks to switch the connection to the WebSocket protocol.
+pub fn is_handshake(request: &Request) -> bool {
+    has_token(request.upgrade(), "websocket") && has_token(request.connection(), "upgrade")
+}
+
+/// Creates the `101 Switching Protocols` response accepting the handshake.
+///
+/// Returns a message describing the problem if the request is no valid handshake.
+pub fn handshake_response(request: &Request) -> Result<Response, &'static str> {
+    if request.method() != "GET" {
+        return Err("WebSocket handshake must use GET!");
+    }
+
+    if request.version() != "1.1" {
+        return Err("WebSocket handshake must use HTTP/1.1!");
+    }
+
+    if request.header("Sec-WebSocket-Version").map(str::trim) != Some(VERSION) {
+        return Err("WebSocket handshake must ask for version 13!");
+    }
+
+    let key = match request.header("Sec-WebSocket-Key").map(str::trim) {
+        Some(key) if is_valid_key(key) => key,
+        _ => return Err("WebSocket handshake must send a base64 encoded 16 byte key!"),
+    };
+
+    let mut response = Response::new(http::VERSION.to_string(), Status::SwitchingProtocols, Vec::new());
+    response.add_header(ResponseHeader::Upgrade(String::from("websocket")));
+    response.add_header(ResponseHeader::Connection(String::from("Upgrade")));
+    response.add_header(ResponseHeader::SecWebSocketAccept(accept_key(key)));
+    Ok(response)
+}
+
+/// Computes the `Sec-WebSocket-Accept` value for the `Sec-WebSocket-Key` of the client.
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, GUID).as_bytes()))
+}
+
+/// Whether the comma separated header value contains the token, ignoring case.
+fn has_token(value: &str, token: &str) -> bool {
+    value.split(',').any(|option| option.trim().eq_ignore_ascii_case(token))
+}
+
+/// A key is 16 bytes encoded in base64, which are 22 characters and two padding characters.
+fn is_valid_key(key: &str) -> bool {
+    key.len() == 24 && key.ends_with("==") && key[..22].bytes().all(|byte| BASE64_ALPHABET.contains(&byte))
+}
+
+/// Computes the [SHA-1](https://tools.ietf.org/html/rfc3174) digest of the data.
+///
+/// SHA-1 is broken for signatures, but the handshake only uses it to prove the key was read.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+    let bit_length = (data.len() as u64).wrapping_mul(8);
+    let mut message = data.to_vec();
+    message.push(0x80);
+
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+
+    message.extend_from_slice(&bit_length.to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+
+        for (index, word) in block.chunks(4).enumerate() {
+            words[index] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+
+        for index in 16..80 {
+            words[index] = (words[index - 3] ^ words[index - 8] ^ words[index - 14] ^ words[index - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+
+        for (index, &word) in words.iter().enumerate() {
+            let (f, k) = match index {
+                0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp = a.rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (value, added) in state.iter_mut().zip([a, b, c, d, e].iter()) {
+            *value = value.wrapping_add(*added);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+
+    for (bytes, value) in digest.chunks_mut(4).zip(state.iter()) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+
+    digest
+}
+
+/// Encodes the data in [base64](https://tools.ietf.org/html/rfc4648#section-4) with padding.
+fn base64(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter()
+            .chain([0, 0].iter())
+            .take(3)
+            .fold(0u32, |bits, &byte| (bits << 8) | u32::from(byte));
+
+        for index in 0..4 {
+            if index <= chunk.len() {
+                let value = (bits >> (18 - 6 * index)) & 0x3F;
+                encoded.push(BASE64_ALPHABET[value as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hamcrest::prelude::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn handshake(key: &str, version: &str) -> Request {
+        http::parse_request(&format!(
+            "GET /chat HTTP/1.1\r\nHost: server.example.com\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: {}\r\n",
+            key, version)).unwrap()
+    }
+
+    #[test]
+    fn sha1_of_test_vectors() {
+        assert_that!(hex(&sha1(b"")).as_str(), is(equal_to("da39a3ee5e6b4b0d3255bfef95601890afd80709")));
+        assert_that!(hex(&sha1(b"abc")).as_str(), is(equal_to("a9993e364706816aba3e25717850c26c9cd0d89d")));
+        assert_that!(
+            hex(&sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")).as_str(),
+            is(equal_to("84983e441c3bd26ebaae4aa1f95129e5e54670f1")));
+    }
+
+    #[test]
+    fn base64_pads_incomplete_groups() {
+        assert_that!(base64(b"").as_str(), is(equal_to("")));
+        assert_that!(base64(b"f").as_str(), is(equal_to("Zg==")));
+        assert_that!(base64(b"fo").as_str(), is(equal_to("Zm8=")));
+        assert_that!(base64(b"foo").as_str(), is(equal_to("Zm9v")));
+        assert_that!(base64(b"foobar").as_str(), is(equal_to("Zm9vYmFy")));
+    }
+
+    #[test]
+    fn handshake_response_accepts_key_of_rfc_example() {
+        let request = handshake("dGhlIHNhbXBsZSBub25jZQ==", "13");
+
+        assert_that!(is_handshake(&request), is(true));
+
+        let rendered = handshake_response(&request).unwrap().render();
+
+        assert_that!(
+            String::from_utf8_lossy(&rendered).into_owned(),
+            is(equal_to(String::from(
+                "HTTP/1.1 101 SWITCHING PROTOCOLS\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                 Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n"))));
+    }
+
+    #[test]
+    fn handshake_response_rejects_invalid_handshakes() {
+        assert_that!(handshake_response(&handshake("too short", "13")).is_err(), is(true));
+        assert_that!(handshake_response(&handshake("dGhlIHNhbXBsZSBub25jZQ==", "8")).is_err(), is(true));
+    }
+}